@@ -8,5 +8,9 @@ layout(set = 0, binding = 1) uniform sampler s_diffuse;
 
 void main() {
     f_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
+    // Cut out the see-through parts of leaves and sprites
+    if (f_color.a < 0.5) {
+        discard;
+    }
     // f_color = vec4(0.5,0.5,0.5, 1.0);
 }
\ No newline at end of file
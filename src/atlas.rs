@@ -1,64 +1,188 @@
+use std::collections::HashMap;
+use std::fs::read_dir;
+use std::path::Path;
+
 use anyhow::*;
 
-use crate::{block::*, texture::*};
-
-#[allow(dead_code)]
-#[derive(Copy, Clone, Debug)]
-pub enum BlockType {
-    DIRT,
-    GRASS,
-    ROCK,
-    WATER,
-    AIR,
-    DEBUG,
+use crate::texture::*;
+
+pub const DEFAULT_TEXTURE_PACK_PATH: &str = "./assets/texture_packs/default";
+
+/// Number of crack overlay stages, the texture pack must provide `crack_0` to `crack_{CRACK_STAGES - 1}`.
+pub const CRACK_STAGES: u32 = 8;
+
+/// Largest atlas side, matches the default `max_texture_dimension_2d` device limit.
+const MAX_ATLAS_PIXEL_SIZE: u32 = 8192;
+
+/// Texture coordinates of a tile in the atlas.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileUv {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
 }
 
-impl BlockType {
-    pub fn get_texture_coordinates(&self, texture_corner: [u32; 2], face_direction: FaceDirection) -> [f32; 2] {
-        match self {
-            BlockType::GRASS => match face_direction {
-                FaceDirection::TOP => atlas_pos_to_coordinates([0.0, 0.0], texture_corner),
-                FaceDirection::BOTTOM => atlas_pos_to_coordinates([2.0, 0.0], texture_corner),
-                FaceDirection::RIGHT => atlas_pos_to_coordinates([1.0, 0.0], texture_corner),
-                FaceDirection::LEFT => atlas_pos_to_coordinates([1.0, 0.0], texture_corner),
-                FaceDirection::FRONT => atlas_pos_to_coordinates([1.0, 0.0], texture_corner),
-                FaceDirection::BACK => atlas_pos_to_coordinates([1.0, 0.0], texture_corner),
-            },
-            BlockType::DIRT => atlas_pos_to_coordinates([2.0, 0.0], texture_corner),
-            BlockType::ROCK => atlas_pos_to_coordinates([3.0, 0.0], texture_corner),
-            BlockType::WATER => atlas_pos_to_coordinates([0.0, 15.0], texture_corner),
-            BlockType::AIR => [0.0, 0.0],
-            BlockType::DEBUG => atlas_pos_to_coordinates([15.0, 0.0], texture_corner),
-        }
+impl TileUv {
+    pub fn corner(&self, texture_corner: [u32; 2]) -> [f32; 2] {
+        [
+            if texture_corner[0] == 1 { self.max[0] } else { self.min[0] },
+            if texture_corner[1] == 1 { self.max[1] } else { self.min[1] },
+        ]
     }
+
+    /// Point of the tile at `uv`, from `[0.0, 0.0]` at the `min` corner to `[1.0, 1.0]` at the `max` one.
+    pub fn point(&self, uv: [f32; 2]) -> [f32; 2] {
+        [
+            self.min[0] + (self.max[0] - self.min[0]) * uv[0],
+            self.min[1] + (self.max[1] - self.min[1]) * uv[1],
+        ]
+    }
+}
+
+/// Individual block textures stitched into a single atlas image.
+///
+/// Every tile is a square PNG named after the texture it provides (`grass_top.png` -> `grass_top`),
+/// all tiles share the same power-of-two size.
+///
+/// Each tile sits in the middle of a cell twice its size, the border being filled by extruding the
+/// tile edges. Cells stay aligned down to the last mip level, so neither filtering nor mipmapping
+/// mixes neighbouring tiles.
+pub struct TexturePack {
+    pub image: image::RgbaImage,
+    pub tile_size: u32,
+    pub mip_level_count: u32,
+    tiles_per_row: u32,
+    tiles: HashMap<String, u32>,
 }
 
-const BLOCK_PIXEL_SIZE: f32 = 16.0;
-const ATLAS_PIXEL_SIZE: f32 = 256.0;
+impl TexturePack {
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let entries = read_dir(dir).with_context(|| format!("Error could not read texture pack '{}'", dir.display()))?;
 
-fn atlas_pos_to_coordinates(atlas_pos: [f32; 2], texture_corner: [u32; 2]) -> [f32; 2] {
-    let mut pixel_x = atlas_pos[0] * BLOCK_PIXEL_SIZE;
-    let mut pixel_y = atlas_pos[1] * BLOCK_PIXEL_SIZE;
+        let mut tiles = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("png") {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .with_context(|| format!("Error invalid texture name '{}'", path.display()))?
+                .to_string();
+            let tile = image::open(&path)
+                .with_context(|| format!("Error could not load texture '{}'", path.display()))?
+                .to_rgba8();
+            tiles.push((name, tile));
+        }
+
+        TexturePack::stitch(tiles).with_context(|| format!("Error invalid texture pack '{}'", dir.display()))
+    }
 
-    if texture_corner[0] == 1 {
-        pixel_x += 15.0;
+    /// Packs the tiles row by row, sorted by name so the layout does not depend on the file system order.
+    pub fn stitch(mut tiles: Vec<(String, image::RgbaImage)>) -> Result<Self> {
+        if tiles.is_empty() {
+            bail!("Error texture pack has no textures");
+        }
+        tiles.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let tile_size = tiles[0].1.width();
+        if !tile_size.is_power_of_two() {
+            bail!("Error texture size {} is not a power of two", tile_size);
+        }
+        for (name, tile) in tiles.iter() {
+            if tile.width() != tile_size || tile.height() != tile_size {
+                bail!(
+                    "Error texture '{}' is {}x{}, expected {}x{}",
+                    name,
+                    tile.width(),
+                    tile.height(),
+                    tile_size,
+                    tile_size
+                );
+            }
+        }
+
+        let tiles_per_row = ((tiles.len() as f32).sqrt().ceil() as u32).next_power_of_two();
+        let cell_size = 2 * tile_size;
+        let atlas_size = tiles_per_row * cell_size;
+        if atlas_size > MAX_ATLAS_PIXEL_SIZE {
+            bail!("Error atlas of {} textures does not fit in {}px", tiles.len(), MAX_ATLAS_PIXEL_SIZE);
+        }
+
+        let padding = tile_size / 2;
+        let mut image = image::RgbaImage::new(atlas_size, atlas_size);
+        let mut indices = HashMap::new();
+        for (i, (name, tile)) in tiles.into_iter().enumerate() {
+            let i = i as u32;
+            let cell_x = (i % tiles_per_row) * cell_size;
+            let cell_y = (i / tiles_per_row) * cell_size;
+            for y in 0..cell_size {
+                for x in 0..cell_size {
+                    // Clamping to the tile extrudes its edges into the padding
+                    let tile_x = (x as i32 - padding as i32).max(0).min(tile_size as i32 - 1) as u32;
+                    let tile_y = (y as i32 - padding as i32).max(0).min(tile_size as i32 - 1) as u32;
+                    image.put_pixel(cell_x + x, cell_y + y, *tile.get_pixel(tile_x, tile_y));
+                }
+            }
+            indices.insert(name, i);
+        }
+
+        // Stop while the padding is still at least one pixel wide
+        let mip_level_count = (31 - tile_size.leading_zeros()).max(1);
+
+        Ok(Self {
+            image,
+            tile_size,
+            mip_level_count,
+            tiles_per_row,
+            tiles: indices,
+        })
     }
 
-    if texture_corner[1] == 1 {
-        pixel_y += 16.0;
+    pub fn tile_uv(&self, name: &str) -> Option<TileUv> {
+        let index = *self.tiles.get(name)?;
+        Some(tile_uv(index, self.tiles_per_row))
     }
 
-    return [pixel_x / ATLAS_PIXEL_SIZE, pixel_y / ATLAS_PIXEL_SIZE];
+    pub fn crack_tiles(&self) -> Result<Vec<TileUv>> {
+        (0..CRACK_STAGES)
+            .map(|stage| {
+                let name = format!("crack_{}", stage);
+                self.tile_uv(&name).with_context(|| format!("Error texture pack is missing '{}'", name))
+            })
+            .collect()
+    }
+}
+
+/// Texture coordinates of the tile at `index`, excluding the padding of its cell.
+fn tile_uv(index: u32, tiles_per_row: u32) -> TileUv {
+    let cell = 1.0 / tiles_per_row as f32;
+    let x = (index % tiles_per_row) as f32 * cell;
+    let y = (index / tiles_per_row) as f32 * cell;
+    let padding = cell / 4.0;
+
+    TileUv {
+        min: [x + padding, y + padding],
+        max: [x + cell - padding, y + cell - padding],
+    }
+}
+
+/// Crack overlay tile for a breaking progress in [0, 1].
+pub fn crack_stage(crack_tiles: &[TileUv], progress: f32) -> TileUv {
+    let stage = ((progress * crack_tiles.len() as f32) as usize).min(crack_tiles.len() - 1);
+    crack_tiles[stage]
 }
 
 pub struct Atlas {
     pub diffuse_texture: Texture,
     pub diffuse_bind_group: wgpu::BindGroup,
     pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub crack_tiles: Vec<TileUv>,
 }
 
 impl Atlas {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, texture_pack: &TexturePack) -> Result<Self> {
         let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
@@ -67,7 +191,7 @@ impl Atlas {
                     ty: wgpu::BindingType::Texture {
                         multisampled: false,
                         view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
                     },
                     count: None,
                 },
@@ -76,7 +200,7 @@ impl Atlas {
                     visibility: wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::Sampler {
                         comparison: false,
-                        filtering: false,
+                        filtering: true,
                     },
                     count: None,
                 },
@@ -84,8 +208,9 @@ impl Atlas {
             label: Some("texture_bind_group_layout"),
         });
 
-        let diffuse_bytes = include_bytes!("../assets/images/blocks.png");
-        let diffuse_texture = Texture::from_bytes(&device, &queue, diffuse_bytes, "blocks.png").unwrap();
+        let crack_tiles = texture_pack.crack_tiles()?;
+
+        let diffuse_texture = Texture::from_image_with_mipmaps(device, queue, &texture_pack.image, texture_pack.mip_level_count, Some("block_atlas"))?;
 
         let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &texture_bind_group_layout,
@@ -106,6 +231,78 @@ impl Atlas {
             diffuse_texture,
             diffuse_bind_group,
             texture_bind_group_layout,
+            crack_tiles,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_tiles(count: u8, tile_size: u32) -> Vec<(String, image::RgbaImage)> {
+        (0..count)
+            .map(|i| {
+                (
+                    format!("tile_{}", i),
+                    image::RgbaImage::from_pixel(tile_size, tile_size, image::Rgba([i, 255 - i, 0, 255])),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tile_uv_is_the_tile_without_its_padding() {
+        let uv = |min: [f32; 2], max: [f32; 2]| TileUv { min, max };
+        assert_eq!(tile_uv(0, 1), uv([0.25, 0.25], [0.75, 0.75]));
+        // 16 pixel tiles in the 32 pixel cells of a 128 pixel atlas: the tile at column 2 and row 1
+        // goes from pixel 72 to 88 horizontally and from 40 to 56 vertically
+        assert_eq!(tile_uv(6, 4), uv([0.5625, 0.3125], [0.6875, 0.4375]));
+        assert_eq!(tile_uv(3, 2), uv([0.625, 0.625], [0.875, 0.875]));
+        assert_eq!(tile_uv(13, 8), uv([0.65625, 0.15625], [0.71875, 0.21875]));
+        assert_eq!(tile_uv(255, 16), uv([0.953125, 0.953125], [0.984375, 0.984375]));
+    }
+
+    #[test]
+    fn tile_uv_covers_the_pixels_of_the_tile() {
+        let texture_pack = TexturePack::stitch(solid_tiles(5, 16)).unwrap();
+        let atlas_size = texture_pack.image.width() as f32;
+        for i in 0..5u8 {
+            let uv = texture_pack.tile_uv(&format!("tile_{}", i)).unwrap();
+            let min = [(uv.min[0] * atlas_size) as u32, (uv.min[1] * atlas_size) as u32];
+            let max = [(uv.max[0] * atlas_size) as u32, (uv.max[1] * atlas_size) as u32];
+            assert_eq!([max[0] - min[0], max[1] - min[1]], [16, 16]);
+
+            // The padding around the tile is extruded from it
+            for y in min[1] - 8..max[1] + 8 {
+                for x in min[0] - 8..max[0] + 8 {
+                    assert_eq!(*texture_pack.image.get_pixel(x, y), image::Rgba([i, 255 - i, 0, 255]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adjacent_tiles_do_not_overlap() {
+        for tiles_per_row in [1, 2, 4, 8].iter() {
+            let count = tiles_per_row * tiles_per_row;
+            for a in 0..count {
+                for b in (a + 1)..count {
+                    let (a, b) = (tile_uv(a, *tiles_per_row), tile_uv(b, *tiles_per_row));
+                    let apart = (0..2).any(|axis| a.max[axis] < b.min[axis] || b.max[axis] < a.min[axis]);
+                    assert!(apart, "{:?} overlaps {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mip_levels_stop_before_the_padding_is_gone() {
+        for (tile_size, expected) in [(16, 4), (32, 5), (64, 6)].iter() {
+            let texture_pack = TexturePack::stitch(solid_tiles(3, *tile_size)).unwrap();
+            assert_eq!(texture_pack.mip_level_count, *expected);
+            // Half a tile of padding, still a pixel wide on the last level
+            assert_eq!((tile_size / 2) >> (expected - 1), 1);
+        }
+    }
+}
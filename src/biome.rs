@@ -0,0 +1,131 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+
+use crate::block_registry::*;
+
+/// Climate noise frequency per block, biomes span a few hundred blocks.
+const CLIMATE_FREQUENCY: f64 = 1.0 / 512.0;
+const CLIMATE_OCTAVES: usize = 4;
+
+/// Distance in blocks between two samples when blending biome heights.
+const BLEND_STEP: i32 = 4;
+/// Number of samples on each side of a column when blending biome heights.
+const BLEND_RADIUS: i32 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Biome {
+    OCEAN,
+    PLAINS,
+    FOREST,
+    DESERT,
+    SNOW,
+    MOUNTAINS,
+}
+
+/// How a biome shapes the terrain.
+#[derive(Copy, Clone, Debug)]
+pub struct BiomeParameters {
+    pub surface: BlockType,
+    pub subsurface: BlockType,
+    /// Number of subsurface blocks below the surface, rock fills the rest.
+    pub subsurface_depth: usize,
+    /// Applied to the terrain noise as `noise * height_scale + height_offset`.
+    pub height_scale: f64,
+    pub height_offset: f64,
+}
+
+impl Biome {
+    /// Both values are roughly in [-1, 1].
+    pub fn from_climate(temperature: f64, humidity: f64) -> Biome {
+        if humidity > 0.3 {
+            Biome::OCEAN
+        } else if temperature < -0.25 {
+            if humidity < -0.1 {
+                Biome::MOUNTAINS
+            } else {
+                Biome::SNOW
+            }
+        } else if temperature > 0.25 && humidity < 0.0 {
+            Biome::DESERT
+        } else if humidity > 0.05 {
+            Biome::FOREST
+        } else {
+            Biome::PLAINS
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::OCEAN => "OCEAN",
+            Biome::PLAINS => "PLAINS",
+            Biome::FOREST => "FOREST",
+            Biome::DESERT => "DESERT",
+            Biome::SNOW => "SNOW",
+            Biome::MOUNTAINS => "MOUNTAINS",
+        }
+    }
+
+    pub fn parameters(&self) -> BiomeParameters {
+        let (surface, subsurface, subsurface_depth, height_scale, height_offset) = match self {
+            Biome::OCEAN => (BlockType::SAND, BlockType::SAND, 3, 0.5, -0.25),
+            Biome::PLAINS => (BlockType::GRASS, BlockType::DIRT, 3, 0.4, 0.05),
+            Biome::FOREST => (BlockType::GRASS, BlockType::DIRT, 4, 0.6, 0.08),
+            Biome::DESERT => (BlockType::SAND, BlockType::SAND, 5, 0.3, 0.06),
+            Biome::SNOW => (BlockType::SNOW, BlockType::DIRT, 3, 0.7, 0.1),
+            Biome::MOUNTAINS => (BlockType::ROCK, BlockType::ROCK, 1, 1.4, 0.3),
+        };
+
+        BiomeParameters {
+            surface,
+            subsurface,
+            subsurface_depth,
+            height_scale,
+            height_offset,
+        }
+    }
+}
+
+/// Temperature and humidity fields of a world, deciding which biome is where.
+pub struct BiomeMap {
+    temperature: Fbm,
+    humidity: Fbm,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32) -> Self {
+        let temperature = Fbm::new()
+            .set_seed(seed.wrapping_add(1000))
+            .set_frequency(CLIMATE_FREQUENCY)
+            .set_octaves(CLIMATE_OCTAVES);
+        let humidity = Fbm::new()
+            .set_seed(seed.wrapping_add(1001))
+            .set_frequency(CLIMATE_FREQUENCY)
+            .set_octaves(CLIMATE_OCTAVES);
+
+        Self { temperature, humidity }
+    }
+
+    pub fn biome_at(&self, world_x: i32, world_z: i32) -> Biome {
+        let point = [world_x as f64, world_z as f64];
+        Biome::from_climate(self.temperature.get(point), self.humidity.get(point))
+    }
+
+    /// Height scale and offset averaged over the surrounding biomes, so the terrain does not
+    /// step at biome borders.
+    pub fn blended_height_modifiers(&self, world_x: i32, world_z: i32) -> (f64, f64) {
+        let mut height_scale = 0.0;
+        let mut height_offset = 0.0;
+        let mut total_weight = 0.0;
+        for dx in -BLEND_RADIUS..=BLEND_RADIUS {
+            for dz in -BLEND_RADIUS..=BLEND_RADIUS {
+                // Closer samples count more
+                let weight = ((BLEND_RADIUS + 1 - dx.abs()) * (BLEND_RADIUS + 1 - dz.abs())) as f64;
+                let parameters = self.biome_at(world_x + dx * BLEND_STEP, world_z + dz * BLEND_STEP).parameters();
+                height_scale += parameters.height_scale * weight;
+                height_offset += parameters.height_offset * weight;
+                total_weight += weight;
+            }
+        }
+
+        (height_scale / total_weight, height_offset / total_weight)
+    }
+}
@@ -1,6 +1,7 @@
 use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
 
-use crate::{atlas::BlockType, chunk::*, renderer::*};
+use crate::{block_registry::*, chunk::*, fluid::FLUID_SOURCE_LEVEL, renderer::*};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -12,6 +13,19 @@ pub struct BlockVertex {
 unsafe impl bytemuck::Pod for BlockVertex {}
 unsafe impl bytemuck::Zeroable for BlockVertex {}
 
+impl BlockVertex {
+    pub fn new(pos: [f32; 3], texture_coordinates: [f32; 2]) -> Self {
+        Self { pos, texture_coordinates }
+    }
+
+    /// Moves the vertex down to `surface_y` if it sits on the top of its block, `block_top_y`.
+    pub fn lower_top(&mut self, block_top_y: f32, surface_y: f32) {
+        if self.pos[1] >= block_top_y {
+            self.pos[1] = surface_y;
+        }
+    }
+}
+
 impl Vertex for BlockVertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
@@ -46,7 +60,41 @@ pub fn block_vertex(pos: [i8; 3], material_type: BlockType, texture_corners: [u3
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+pub const TEXTURE_CORNERS: [[u32; 2]; 4] = [[0, 0], [0, 1], [1, 1], [1, 0]];
+
+/// Vertices of the six faces of a box of `size` with its lowest corner at `position`, textured like
+/// `material_type`, for blocks and entities drawn outside of chunks. Quads in the order of
+/// `FaceDirection::ALL`.
+pub fn cube_vertices(material_type: BlockType, position: Vector3<f32>, size: Vector3<f32>) -> Vec<BlockVertex> {
+    let mut vertices = Vec::with_capacity(4 * 6);
+    for face_direction in FaceDirection::ALL.iter() {
+        for vertex in face_direction.get_vertices(material_type, [0, 0, 0]).iter() {
+            let pos = [
+                vertex.pos[0] * size.x + position.x,
+                vertex.pos[1] * size.y + position.y,
+                vertex.pos[2] * size.z + position.z,
+            ];
+            vertices.push(BlockVertex::new(pos, vertex.texture_coordinates));
+        }
+    }
+    vertices
+}
+
+/// Indices of the two triangles of the `i`th quad in a list of quads.
+pub fn quad_indices(i: u16) -> [u16; 6] {
+    let displacement = i * 4;
+    [
+        displacement,
+        1 + displacement,
+        2 + displacement,
+        2 + displacement,
+        3 + displacement,
+        displacement,
+    ]
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FaceDirection {
     TOP,
     BOTTOM,
@@ -57,6 +105,19 @@ pub enum FaceDirection {
 }
 
 impl FaceDirection {
+    pub const ALL: [FaceDirection; 6] = [
+        FaceDirection::TOP,
+        FaceDirection::BOTTOM,
+        FaceDirection::RIGHT,
+        FaceDirection::LEFT,
+        FaceDirection::FRONT,
+        FaceDirection::BACK,
+    ];
+
+    pub fn from_vec(direction: Vector3<i32>) -> Option<FaceDirection> {
+        FaceDirection::ALL.iter().cloned().find(|face_direction| face_direction.to_vec() == direction)
+    }
+
     pub fn to_vec(self) -> Vector3<i32> {
         match self {
             FaceDirection::TOP => Vector3::new(0, 1, 0),
@@ -68,112 +129,229 @@ impl FaceDirection {
         }
     }
 
-    fn get_vertices(self, material_type: BlockType, position: [i32; 3]) -> [BlockVertex; 4] {
+    /// Corners of the unit cube face, in the same order as `TEXTURE_CORNERS`.
+    pub fn corners(self) -> [[i8; 3]; 4] {
         match self {
-            FaceDirection::TOP => [
-                block_vertex([0, 1, 0], material_type, [0, 0], position, self),
-                block_vertex([0, 1, 1], material_type, [0, 1], position, self),
-                block_vertex([1, 1, 1], material_type, [1, 1], position, self),
-                block_vertex([1, 1, 0], material_type, [1, 0], position, self),
-            ],
-            FaceDirection::BOTTOM => [
-                block_vertex([0, 0, 1], material_type, [0, 0], position, self),
-                block_vertex([0, 0, 0], material_type, [0, 1], position, self),
-                block_vertex([1, 0, 0], material_type, [1, 1], position, self),
-                block_vertex([1, 0, 1], material_type, [1, 0], position, self),
-            ],
-            FaceDirection::RIGHT => [
-                block_vertex([1, 1, 1], material_type, [0, 0], position, self),
-                block_vertex([1, 0, 1], material_type, [0, 1], position, self),
-                block_vertex([1, 0, 0], material_type, [1, 1], position, self),
-                block_vertex([1, 1, 0], material_type, [1, 0], position, self),
-            ],
-            FaceDirection::LEFT => [
-                block_vertex([0, 1, 0], material_type, [0, 0], position, self),
-                block_vertex([0, 0, 0], material_type, [0, 1], position, self),
-                block_vertex([0, 0, 1], material_type, [1, 1], position, self),
-                block_vertex([0, 1, 1], material_type, [1, 0], position, self),
-            ],
-            FaceDirection::FRONT => [
-                block_vertex([0, 1, 1], material_type, [0, 0], position, self),
-                block_vertex([0, 0, 1], material_type, [0, 1], position, self),
-                block_vertex([1, 0, 1], material_type, [1, 1], position, self),
-                block_vertex([1, 1, 1], material_type, [1, 0], position, self),
-            ],
-            FaceDirection::BACK => [
-                block_vertex([1, 1, 0], material_type, [0, 0], position, self),
-                block_vertex([1, 0, 0], material_type, [0, 1], position, self),
-                block_vertex([0, 0, 0], material_type, [1, 1], position, self),
-                block_vertex([0, 1, 0], material_type, [1, 0], position, self),
-            ],
+            FaceDirection::TOP => [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]],
+            FaceDirection::BOTTOM => [[0, 0, 1], [0, 0, 0], [1, 0, 0], [1, 0, 1]],
+            FaceDirection::RIGHT => [[1, 1, 1], [1, 0, 1], [1, 0, 0], [1, 1, 0]],
+            FaceDirection::LEFT => [[0, 1, 0], [0, 0, 0], [0, 0, 1], [0, 1, 1]],
+            FaceDirection::FRONT => [[0, 1, 1], [0, 0, 1], [1, 0, 1], [1, 1, 1]],
+            FaceDirection::BACK => [[1, 1, 0], [1, 0, 0], [0, 0, 0], [0, 1, 0]],
         }
     }
+
+    pub fn get_vertices(self, material_type: BlockType, position: [i32; 3]) -> [BlockVertex; 4] {
+        let corners = self.corners();
+        [
+            block_vertex(corners[0], material_type, TEXTURE_CORNERS[0], position, self),
+            block_vertex(corners[1], material_type, TEXTURE_CORNERS[1], position, self),
+            block_vertex(corners[2], material_type, TEXTURE_CORNERS[2], position, self),
+            block_vertex(corners[3], material_type, TEXTURE_CORNERS[3], position, self),
+        ]
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct Face {
-    pub vertices: [BlockVertex; 4],
-    pub direction: FaceDirection,
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
 }
 
-impl Face {
-    fn new(material_type: BlockType, face_direction: FaceDirection, position: [i32; 3]) -> Self {
-        Self {
-            vertices: face_direction.get_vertices(material_type, position),
-            direction: face_direction,
+/// Half of the block filled by slabs and stairs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Half {
+    BOTTOM,
+    TOP,
+}
+
+/// Horizontal facings by quarter turns from `FaceDirection::FRONT`, the order of the facing bits.
+const FACINGS: [FaceDirection; 4] = [FaceDirection::FRONT, FaceDirection::RIGHT, FaceDirection::BACK, FaceDirection::LEFT];
+const AXES: [Axis; 3] = [Axis::Y, Axis::X, Axis::Z];
+
+const FACING_MASK: u8 = 0b11;
+const AXIS_SHIFT: u8 = 2;
+const AXIS_MASK: u8 = 0b11 << AXIS_SHIFT;
+const HALF_TOP: u8 = 1 << 4;
+const WATERLOGGED: u8 = 1 << 5;
+
+/// Orientation and state of a block packed in a byte: the facing in bits 0-1, the axis in bits
+/// 2-3, the half in bit 4 and waterlogged in bit 5. The default is a block facing
+/// `FaceDirection::FRONT` along the Y axis, in the bottom half and dry, the way blocks are defined.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockState(pub u8);
+
+impl BlockState {
+    /// Horizontal direction the `front` tile of the block faces.
+    pub fn facing(self) -> FaceDirection {
+        FACINGS[(self.0 & FACING_MASK) as usize]
+    }
+
+    /// Vertical directions are ignored.
+    pub fn with_facing(self, facing: FaceDirection) -> Self {
+        match FACINGS.iter().position(|other| other.to_vec() == facing.to_vec()) {
+            Some(index) => BlockState((self.0 & !FACING_MASK) | index as u8),
+            None => self,
         }
     }
 
-    pub fn get_indices(&self, i: u16) -> [u16; 6] {
-        let displacement = i * 4;
-        [
-            0 + displacement,
-            1 + displacement,
-            2 + displacement,
-            2 + displacement,
-            3 + displacement,
-            0 + displacement,
-        ]
+    /// Axis the `top` and `bottom` tiles of the block are on.
+    pub fn axis(self) -> Axis {
+        AXES[((self.0 & AXIS_MASK) >> AXIS_SHIFT) as usize]
+    }
+
+    pub fn with_axis(self, axis: Axis) -> Self {
+        let index = AXES.iter().position(|other| *other == axis).unwrap_or(0) as u8;
+        BlockState((self.0 & !AXIS_MASK) | (index << AXIS_SHIFT))
+    }
+
+    pub fn half(self) -> Half {
+        if self.0 & HALF_TOP != 0 {
+            Half::TOP
+        } else {
+            Half::BOTTOM
+        }
+    }
+
+    pub fn with_half(self, half: Half) -> Self {
+        match half {
+            Half::TOP => BlockState(self.0 | HALF_TOP),
+            Half::BOTTOM => BlockState(self.0 & !HALF_TOP),
+        }
+    }
+
+    /// Water shares the block, it stays when the block is broken.
+    pub fn waterlogged(self) -> bool {
+        self.0 & WATERLOGGED != 0
+    }
+
+    pub fn with_waterlogged(self, waterlogged: bool) -> Self {
+        if waterlogged {
+            BlockState(self.0 | WATERLOGGED)
+        } else {
+            BlockState(self.0 & !WATERLOGGED)
+        }
+    }
+
+    /// State of `block_type` placed against the `face` of another block, `look` being the direction
+    /// the player looks in and `hit_height` the height of the point in view on that face. Only the
+    /// states listed in the block definition are set.
+    pub fn for_placement(block_type: BlockType, face: Vector3<i32>, look: Vector3<f32>, hit_height: f32, in_water: bool) -> Self {
+        let mut state = BlockState::default();
+        if block_type.has_state(BlockStateProperty::FACING) {
+            // Facing the player
+            let facing = if look.x.abs() > look.z.abs() {
+                if look.x > 0.0 {
+                    FaceDirection::LEFT
+                } else {
+                    FaceDirection::RIGHT
+                }
+            } else if look.z > 0.0 {
+                FaceDirection::BACK
+            } else {
+                FaceDirection::FRONT
+            };
+            state = state.with_facing(facing);
+        }
+        if block_type.has_state(BlockStateProperty::AXIS) {
+            let axis = if face.x != 0 {
+                Axis::X
+            } else if face.z != 0 {
+                Axis::Z
+            } else {
+                Axis::Y
+            };
+            state = state.with_axis(axis);
+        }
+        if block_type.has_state(BlockStateProperty::HALF) && (face.y < 0 || (face.y == 0 && hit_height > 0.5)) {
+            state = state.with_half(Half::TOP);
+        }
+        if block_type.has_state(BlockStateProperty::WATERLOGGED) {
+            state = state.with_waterlogged(in_water);
+        }
+        state
+    }
+
+    /// Turns a point relative to the center of the block from the orientation of the block
+    /// definition to this one: upside down for the top half, then to the axis, then to the facing.
+    fn rotate_centered(self, point: [f32; 3]) -> [f32; 3] {
+        let [mut x, mut y, z] = point;
+        if self.half() == Half::TOP {
+            x = -x;
+            y = -y;
+        }
+        let [x, y, z] = match self.axis() {
+            Axis::Y => [x, y, z],
+            Axis::X => [y, -x, z],
+            Axis::Z => [x, -z, y],
+        };
+        match self.facing() {
+            FaceDirection::RIGHT => [z, y, -x],
+            FaceDirection::BACK => [-x, y, -z],
+            FaceDirection::LEFT => [-z, y, x],
+            _ => [x, y, z],
+        }
+    }
+
+    /// Turns a point of the unit block, see `BlockState::rotate_centered`.
+    pub fn rotate_point(self, point: [f32; 3]) -> [f32; 3] {
+        let rotated = self.rotate_centered([point[0] - 0.5, point[1] - 0.5, point[2] - 0.5]);
+        [rotated[0] + 0.5, rotated[1] + 0.5, rotated[2] + 0.5]
+    }
+
+    /// Direction a face of the block definition points to once the block is oriented.
+    pub fn rotate_face(self, face_direction: FaceDirection) -> FaceDirection {
+        let direction = face_direction.to_vec();
+        let rotated = self.rotate_centered([direction.x as f32, direction.y as f32, direction.z as f32]);
+        let rotated = Vector3::new(rotated[0].round() as i32, rotated[1].round() as i32, rotated[2].round() as i32);
+        FaceDirection::from_vec(rotated).unwrap_or(face_direction)
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Block {
-    pub faces: [Face; 6],
     pub position: [i32; 3],
     pub material_type: BlockType,
+    /// Water level, `FLUID_SOURCE_LEVEL` for sources and 0 for anything but water.
+    pub fluid_level: u8,
+    pub state: BlockState,
 }
 
 impl Block {
-    pub fn new(material_type: BlockType, position: [i32; 3], chunk_offset: [i32; 3]) -> Self {
-        let faces = Block::generate_faces(material_type, position, chunk_offset);
-
+    pub fn new(material_type: BlockType, position: [i32; 3]) -> Self {
         Self {
-            faces,
             position,
             material_type,
+            fluid_level: Block::default_fluid_level(material_type),
+            state: BlockState::default(),
+        }
+    }
+
+    /// Water is placed as a source.
+    fn default_fluid_level(material_type: BlockType) -> u8 {
+        if material_type == BlockType::WATER {
+            FLUID_SOURCE_LEVEL
+        } else {
+            0
         }
     }
 
-    fn generate_faces(material_type: BlockType, position: [i32; 3], chunk_offset: [i32; 3]) -> [Face; 6] {
-        let world_pos = [
+    pub fn world_position(position: [i32; 3], chunk_offset: [i32; 3]) -> [i32; 3] {
+        [
             position[0] + (chunk_offset[0] * CHUNK_X_SIZE as i32),
             position[1],
             position[2] + (chunk_offset[2] * CHUNK_Z_SIZE as i32),
-        ];
-
-        let top = Face::new(material_type, FaceDirection::TOP, world_pos);
-        let bottom = Face::new(material_type, FaceDirection::BOTTOM, world_pos);
-        let right = Face::new(material_type, FaceDirection::RIGHT, world_pos);
-        let left = Face::new(material_type, FaceDirection::LEFT, world_pos);
-        let front = Face::new(material_type, FaceDirection::FRONT, world_pos);
-        let back = Face::new(material_type, FaceDirection::BACK, world_pos);
+        ]
+    }
 
-        [top, bottom, right, left, front, back]
+    pub fn update(&mut self, new_material_type: BlockType) {
+        self.update_with_state(new_material_type, BlockState::default());
     }
 
-    pub fn update(&mut self, new_material_type: BlockType, offset: [i32; 3]) {
+    pub fn update_with_state(&mut self, new_material_type: BlockType, state: BlockState) {
         self.material_type = new_material_type;
-        self.faces = Block::generate_faces(new_material_type, self.position, offset);
+        self.fluid_level = Block::default_fluid_level(new_material_type);
+        self.state = state;
     }
 }
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_to_string, remove_file, write};
+use std::path::PathBuf;
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{block::BlockState, block_registry::*};
+
+/// Directory of a world holding one file of block entities per chunk that has some.
+pub const BLOCK_ENTITIES_DIR: &str = "block_entities";
+pub const CHEST_SLOTS: usize = 27;
+pub const MAX_STACK_SIZE: u32 = 64;
+/// Characters of the text of a sign.
+pub const MAX_SIGN_LENGTH: usize = 64;
+
+/// Block entities of a chunk by world position.
+pub type BlockEntities = HashMap<[i32; 3], BlockEntity>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub block_type: BlockType,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BlockEntityData {
+    /// At most `CHEST_SLOTS` stacks.
+    CHEST(Vec<ItemStack>),
+    SIGN(String),
+}
+
+/// Data attached to the block at `position`, for the blocks whose `BlockEntityKind` is not `NONE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockEntity {
+    pub position: [i32; 3],
+    /// Block the entity belongs to, put back with the entity when its chunk is generated again.
+    pub block_type: BlockType,
+    #[serde(default)]
+    pub state: BlockState,
+    pub data: BlockEntityData,
+}
+
+impl BlockEntity {
+    /// Empty block entity for a new block, `None` for blocks that have none.
+    pub fn new(block_type: BlockType, state: BlockState, position: [i32; 3]) -> Option<Self> {
+        let data = match block_type.block_entity() {
+            BlockEntityKind::NONE => return None,
+            BlockEntityKind::CHEST => BlockEntityData::CHEST(Vec::new()),
+            BlockEntityKind::SIGN => BlockEntityData::SIGN(String::new()),
+        };
+        Some(Self {
+            position,
+            block_type,
+            state,
+            data,
+        })
+    }
+
+    /// Right click with `held` selected: chests store it and signs get their text typed. Returns
+    /// whether the player starts typing.
+    pub fn interact(&mut self, held: BlockType) -> bool {
+        match self.data {
+            BlockEntityData::CHEST(_) => {
+                self.store(held);
+                false
+            }
+            BlockEntityData::SIGN(_) => true,
+        }
+    }
+
+    /// Stores one `block_type` in a chest, returns false when it is full or not a chest.
+    pub fn store(&mut self, block_type: BlockType) -> bool {
+        let stacks = match &mut self.data {
+            BlockEntityData::CHEST(stacks) => stacks,
+            _ => return false,
+        };
+        if let Some(stack) = stacks.iter_mut().find(|stack| stack.block_type == block_type && stack.count < MAX_STACK_SIZE) {
+            stack.count += 1;
+            return true;
+        }
+        if stacks.len() < CHEST_SLOTS {
+            stacks.push(ItemStack { block_type, count: 1 });
+            return true;
+        }
+        false
+    }
+
+    /// Adds a typed character to the text of a sign, backspace removes the last one.
+    pub fn type_character(&mut self, character: char) {
+        if let BlockEntityData::SIGN(text) = &mut self.data {
+            if character == '\u{8}' {
+                text.pop();
+            } else if !character.is_control() && text.chars().count() < MAX_SIGN_LENGTH {
+                text.push(character);
+            }
+        }
+    }
+
+    /// Stacks dropped as items when the block is broken.
+    pub fn drops(&self) -> Vec<ItemStack> {
+        match &self.data {
+            BlockEntityData::CHEST(stacks) => stacks.clone(),
+            BlockEntityData::SIGN(_) => Vec::new(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let [x, y, z] = self.position;
+        match &self.data {
+            BlockEntityData::CHEST(stacks) if stacks.is_empty() => format!("Chest at {} {} {} is empty", x, y, z),
+            BlockEntityData::CHEST(stacks) => {
+                let contents: Vec<String> = stacks
+                    .iter()
+                    .map(|stack| format!("{} {}", stack.count, stack.block_type.properties().name))
+                    .collect();
+                format!("Chest at {} {} {} holds {}", x, y, z, contents.join(", "))
+            }
+            BlockEntityData::SIGN(text) => format!("Sign at {} {} {} reads \"{}\"", x, y, z, text),
+        }
+    }
+}
+
+/// Block entities of the chunks that are not loaded, kept in memory and in the world directory if
+/// there is one.
+#[derive(Debug, Default)]
+pub struct BlockEntityStore {
+    dir: Option<PathBuf>,
+    saved: HashMap<[i32; 3], Vec<BlockEntity>>,
+}
+
+impl BlockEntityStore {
+    pub fn new(world_dir: Option<PathBuf>) -> Self {
+        Self {
+            dir: world_dir.map(|dir| dir.join(BLOCK_ENTITIES_DIR)),
+            saved: HashMap::new(),
+        }
+    }
+
+    fn chunk_path(&self, chunk_offset: [i32; 3]) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}_{}.ron", chunk_offset[0], chunk_offset[2])))
+    }
+
+    /// Block entities of a chunk being loaded, they belong to the chunk until it unloads.
+    pub fn take_chunk(&mut self, chunk_offset: [i32; 3]) -> Result<Vec<BlockEntity>> {
+        if let Some(block_entities) = self.saved.remove(&chunk_offset) {
+            return Ok(block_entities);
+        }
+        let path = match self.chunk_path(chunk_offset) {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(Vec::new()),
+        };
+        let content = read_to_string(&path).with_context(|| format!("Error could not read block entities '{}'", path.display()))?;
+        ron::de::from_str(&content).with_context(|| format!("Error invalid block entities '{}'", path.display()))
+    }
+
+    /// Keeps the block entities of a chunk that unloads.
+    pub fn store_chunk(&mut self, chunk_offset: [i32; 3], block_entities: &BlockEntities) -> Result<()> {
+        self.write_chunk(chunk_offset, block_entities)?;
+        if !block_entities.is_empty() {
+            self.saved.insert(chunk_offset, block_entities.values().cloned().collect());
+        }
+        Ok(())
+    }
+
+    /// Writes the block entities of a chunk to the world directory, chunks without any have no file.
+    pub fn write_chunk(&self, chunk_offset: [i32; 3], block_entities: &BlockEntities) -> Result<()> {
+        let (dir, path) = match (&self.dir, self.chunk_path(chunk_offset)) {
+            (Some(dir), Some(path)) => (dir, path),
+            _ => return Ok(()),
+        };
+        if block_entities.is_empty() {
+            if path.is_file() {
+                remove_file(&path).with_context(|| format!("Error could not remove block entities '{}'", path.display()))?;
+            }
+            return Ok(());
+        }
+
+        let mut block_entities: Vec<&BlockEntity> = block_entities.values().collect();
+        block_entities.sort_unstable_by_key(|block_entity| block_entity.position);
+        create_dir_all(dir).with_context(|| format!("Error could not create block entities directory '{}'", dir.display()))?;
+        let content = ron::ser::to_string_pretty(&block_entities, ron::ser::PrettyConfig::default())?;
+        write(&path, content).with_context(|| format!("Error could not write block entities '{}'", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_dir_all;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn block_entity(name: &str, position: [i32; 3]) -> BlockEntity {
+        init_registry();
+        let block_type = BlockRegistry::get().block_type(name).unwrap();
+        BlockEntity::new(block_type, BlockState::default(), position).unwrap()
+    }
+
+    fn sign_text(sign: &BlockEntity) -> &str {
+        match &sign.data {
+            BlockEntityData::SIGN(text) => text,
+            data => panic!("{:?} is not a sign", data),
+        }
+    }
+
+    #[test]
+    fn signs_are_edited_by_typing() {
+        let mut sign = block_entity("sign", [0, 0, 0]);
+        for character in "Hi!".chars() {
+            sign.type_character(character);
+        }
+        assert_eq!(sign_text(&sign), "Hi!");
+
+        sign.type_character('\u{8}');
+        sign.type_character('\n');
+        sign.type_character('\r');
+        assert_eq!(sign_text(&sign), "Hi");
+        for _ in 0..5 {
+            sign.type_character('\u{8}');
+        }
+        assert_eq!(sign_text(&sign), "");
+
+        for _ in 0..MAX_SIGN_LENGTH + 10 {
+            sign.type_character('é');
+        }
+        assert_eq!(sign_text(&sign).chars().count(), MAX_SIGN_LENGTH);
+        assert!(sign.drops().is_empty());
+    }
+
+    #[test]
+    fn chests_drop_their_stacks() {
+        let mut chest = block_entity("chest", [0, 0, 0]);
+        assert!(chest.drops().is_empty());
+        for _ in 0..MAX_STACK_SIZE + 2 {
+            assert!(chest.store(BlockType::DIRT));
+        }
+        assert!(chest.store(BlockType::SAND));
+        assert_eq!(
+            chest.drops(),
+            vec![
+                ItemStack {
+                    block_type: BlockType::DIRT,
+                    count: MAX_STACK_SIZE
+                },
+                ItemStack {
+                    block_type: BlockType::DIRT,
+                    count: 2
+                },
+                ItemStack {
+                    block_type: BlockType::SAND,
+                    count: 1
+                },
+            ]
+        );
+
+        let mut full_chest = block_entity("chest", [0, 0, 0]);
+        for _ in 0..CHEST_SLOTS as u32 * MAX_STACK_SIZE {
+            assert!(full_chest.store(BlockType::ROCK));
+        }
+        assert!(!full_chest.store(BlockType::ROCK));
+        assert_eq!(full_chest.drops().len(), CHEST_SLOTS);
+    }
+
+    #[test]
+    fn block_entities_are_saved_and_loaded_again() {
+        let world_dir = std::env::temp_dir().join(format!("rustcraft_block_entities_{}", std::process::id()));
+        let mut chest = block_entity("chest", [3, 10, -5]);
+        chest.store(BlockType::LOG);
+        let mut sign = block_entity("sign", [4, 10, -5]);
+        sign.type_character('A');
+        let block_entities: BlockEntities = vec![(chest.position, chest.clone()), (sign.position, sign.clone())].into_iter().collect();
+
+        let mut store = BlockEntityStore::new(Some(world_dir.clone()));
+        store.store_chunk([0, 0, -1], &block_entities).unwrap();
+        store.store_chunk([1, 0, 0], &BlockEntities::new()).unwrap();
+        assert!(world_dir.join(BLOCK_ENTITIES_DIR).join("0_-1.ron").is_file());
+        assert!(!world_dir.join(BLOCK_ENTITIES_DIR).join("1_0.ron").exists());
+
+        // A new store only has the files of the world directory
+        let mut loaded = BlockEntityStore::new(Some(world_dir.clone())).take_chunk([0, 0, -1]).unwrap();
+        loaded.sort_unstable_by_key(|block_entity| block_entity.position);
+        assert_eq!(loaded, vec![chest, sign]);
+        assert!(BlockEntityStore::new(Some(world_dir.clone())).take_chunk([1, 0, 0]).unwrap().is_empty());
+
+        // Chunks that lost their block entities have their file removed
+        store.write_chunk([0, 0, -1], &BlockEntities::new()).unwrap();
+        assert!(!world_dir.join(BLOCK_ENTITIES_DIR).join("0_-1.ron").exists());
+        remove_dir_all(&world_dir).unwrap();
+    }
+}
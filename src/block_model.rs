@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::*;
+use cgmath::Vector3;
+use serde::Deserialize;
+
+use crate::{block::*, block_registry::BlockType, physics::Aabb};
+
+pub const BLOCK_MODELS_PATH: &str = "./assets/models.ron";
+/// Model of the blocks that do not name one.
+pub const DEFAULT_BLOCK_MODEL: &str = "cube";
+/// Model coordinates are in sixteenths of a block, a texture pixel at 16px.
+const MODEL_UNIT: f32 = 16.0;
+
+/// Face of an element. Every field is optional, `()` draws the face with the tile of the block for
+/// that face.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ModelFaceDefinition {
+    /// Part of the tile drawn, `(u0, v0, u1, v1)` in model units. By default the part matching
+    /// where the face is on the block face, the lower half of the tile for the side of a slab.
+    uv: Option<[f32; 4]>,
+    /// Face of the block the tile is taken from, the face itself by default.
+    tile: Option<FaceDirection>,
+}
+
+/// Faces drawn for an element. Specific faces override `side`, which overrides `all`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ElementFacesDefinition {
+    all: Option<ModelFaceDefinition>,
+    side: Option<ModelFaceDefinition>,
+    top: Option<ModelFaceDefinition>,
+    bottom: Option<ModelFaceDefinition>,
+    right: Option<ModelFaceDefinition>,
+    left: Option<ModelFaceDefinition>,
+    front: Option<ModelFaceDefinition>,
+    back: Option<ModelFaceDefinition>,
+}
+
+impl ElementFacesDefinition {
+    fn face(&self, face_direction: FaceDirection) -> Option<&ModelFaceDefinition> {
+        let specific = match face_direction {
+            FaceDirection::TOP => return self.top.as_ref().or(self.all.as_ref()),
+            FaceDirection::BOTTOM => return self.bottom.as_ref().or(self.all.as_ref()),
+            FaceDirection::RIGHT => self.right.as_ref(),
+            FaceDirection::LEFT => self.left.as_ref(),
+            FaceDirection::FRONT => self.front.as_ref(),
+            FaceDirection::BACK => self.back.as_ref(),
+        };
+        specific.or(self.side.as_ref()).or(self.all.as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ElementDefinition {
+    from: [f32; 3],
+    to: [f32; 3],
+    #[serde(default)]
+    faces: ElementFacesDefinition,
+    /// Degrees around the vertical axis through the center of the block.
+    #[serde(default)]
+    rotation: f32,
+    /// Stretches a rotated element horizontally so that it still spans the whole block.
+    #[serde(default)]
+    rescale: bool,
+    /// Only part of the model when the block connects to its neighbour on that side.
+    #[serde(default)]
+    when: Option<FaceDirection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BoxDefinition {
+    from: [f32; 3],
+    to: [f32; 3],
+    #[serde(default)]
+    when: Option<FaceDirection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelDefinition {
+    name: String,
+    elements: Vec<ElementDefinition>,
+    /// Boxes the block collides with, the boxes of the elements by default.
+    #[serde(default)]
+    collision: Option<Vec<BoxDefinition>>,
+}
+
+/// Box of a model in block units, with the side it depends on.
+#[derive(Debug, Clone, Copy)]
+struct ModelBox {
+    min: [f32; 3],
+    max: [f32; 3],
+    when: Option<FaceDirection>,
+}
+
+impl ModelBox {
+    fn new(from: [f32; 3], to: [f32; 3], when: Option<FaceDirection>) -> Self {
+        Self {
+            min: [from[0] / MODEL_UNIT, from[1] / MODEL_UNIT, from[2] / MODEL_UNIT],
+            max: [to[0] / MODEL_UNIT, to[1] / MODEL_UNIT, to[2] / MODEL_UNIT],
+            when,
+        }
+    }
+
+    /// The box turned to the orientation of `state`, at the origin of the block.
+    fn oriented(&self, state: BlockState) -> Aabb {
+        let a = state.rotate_point(self.min);
+        let b = state.rotate_point(self.max);
+        Aabb {
+            min: Vector3::new(a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])),
+            max: Vector3::new(a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ModelFace {
+    /// Corners in block units, in the order of `TEXTURE_CORNERS`.
+    corners: [[f32; 3]; 4],
+    /// Texture coordinates in the tile for each corner, from 0 to 1.
+    uvs: [[f32; 2]; 4],
+    tile: FaceDirection,
+    /// Side of the block the face lies on, if it does.
+    cull: Option<FaceDirection>,
+    when: Option<FaceDirection>,
+}
+
+/// Quad of a block mesh.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelQuad {
+    pub vertices: [BlockVertex; 4],
+    /// Direction of the neighbour hiding the quad when it is a full opaque block, `None` for
+    /// quads inside the block.
+    pub cull: Option<FaceDirection>,
+}
+
+/// Shape of a block: textured boxes, and the boxes it collides with.
+#[derive(Debug, Clone)]
+pub struct BlockModel {
+    pub name: String,
+    faces: Vec<ModelFace>,
+    /// Bounds of the elements, what the player points at.
+    shape: Vec<ModelBox>,
+    collision: Vec<ModelBox>,
+    full: bool,
+}
+
+/// Turns a point of the block around the vertical axis through its center.
+fn rotate_element_point(point: [f32; 3], degrees: f32, rescale: bool) -> [f32; 3] {
+    if degrees == 0.0 {
+        return point;
+    }
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let scale = if rescale { 1.0 / cos.abs() } else { 1.0 };
+    let (x, z) = (point[0] - 0.5, point[2] - 0.5);
+    [(x * cos + z * sin) * scale + 0.5, point[1], (z * cos - x * sin) * scale + 0.5]
+}
+
+impl BlockModel {
+    fn new(definition: ModelDefinition) -> Result<Self> {
+        let mut faces = Vec::new();
+        let mut shape = Vec::new();
+        for element in definition.elements.iter() {
+            if (0..3).any(|axis| element.from[axis] > element.to[axis]) {
+                bail!(
+                    "Error model '{}' has an element from {:?} to {:?}, `from` must be the lowest corner",
+                    definition.name,
+                    element.from,
+                    element.to
+                );
+            }
+            let element_box = ModelBox::new(element.from, element.to, element.when);
+            for face_direction in FaceDirection::ALL.iter() {
+                if let Some(face) = element.faces.face(*face_direction) {
+                    faces.push(BlockModel::element_face(&element_box, element, face, *face_direction));
+                }
+            }
+
+            // The shape of a rotated element is its horizontal bounds once rotated
+            let (mut min, mut max) = (element_box.min, element_box.max);
+            if element.rotation != 0.0 {
+                let rotated: Vec<[f32; 3]> = [[min[0], min[2]], [max[0], min[2]], [max[0], max[2]], [min[0], max[2]]]
+                    .iter()
+                    .map(|[x, z]| rotate_element_point([*x, 0.0, *z], element.rotation, element.rescale))
+                    .collect();
+                for axis in [0, 2].iter() {
+                    min[*axis] = rotated.iter().map(|corner| corner[*axis]).fold(f32::INFINITY, f32::min);
+                    max[*axis] = rotated.iter().map(|corner| corner[*axis]).fold(f32::NEG_INFINITY, f32::max);
+                }
+            }
+            shape.push(ModelBox { min, max, when: element.when });
+        }
+
+        let collision = match &definition.collision {
+            Some(boxes) => boxes
+                .iter()
+                .map(|collision| ModelBox::new(collision.from, collision.to, collision.when))
+                .collect(),
+            None => shape.clone(),
+        };
+        let full = definition
+            .elements
+            .iter()
+            .any(|element| element.when.is_none() && element.rotation == 0.0 && element.from == [0.0; 3] && element.to == [MODEL_UNIT; 3]);
+        Ok(Self {
+            name: definition.name,
+            faces,
+            shape,
+            collision,
+            full,
+        })
+    }
+
+    fn element_face(element_box: &ModelBox, element: &ElementDefinition, face: &ModelFaceDefinition, face_direction: FaceDirection) -> ModelFace {
+        let unit_corners = face_direction.corners();
+        let unit_corner = |i: usize| [unit_corners[i][0] as f32, unit_corners[i][1] as f32, unit_corners[i][2] as f32];
+        let mut corners = [[0.0; 3]; 4];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let unit = unit_corner(i);
+            for axis in 0..3 {
+                corner[axis] = element_box.min[axis] + unit[axis] * (element_box.max[axis] - element_box.min[axis]);
+            }
+        }
+
+        let uvs = match face.uv {
+            Some([u0, v0, u1, v1]) => [
+                [u0 / MODEL_UNIT, v0 / MODEL_UNIT],
+                [u0 / MODEL_UNIT, v1 / MODEL_UNIT],
+                [u1 / MODEL_UNIT, v1 / MODEL_UNIT],
+                [u1 / MODEL_UNIT, v0 / MODEL_UNIT],
+            ],
+            None => {
+                // Where the corner is on the face of the full block, whose corners have the texture corners
+                let origin = unit_corner(0);
+                let (first, last) = (unit_corner(1), unit_corner(3));
+                let u_axis = [last[0] - origin[0], last[1] - origin[1], last[2] - origin[2]];
+                let v_axis = [first[0] - origin[0], first[1] - origin[1], first[2] - origin[2]];
+                let mut uvs = [[0.0; 2]; 4];
+                for (uv, corner) in uvs.iter_mut().zip(corners.iter()) {
+                    let relative = [corner[0] - origin[0], corner[1] - origin[1], corner[2] - origin[2]];
+                    *uv = [
+                        (0..3).map(|axis| relative[axis] * u_axis[axis]).sum(),
+                        (0..3).map(|axis| relative[axis] * v_axis[axis]).sum(),
+                    ];
+                }
+                uvs
+            }
+        };
+
+        let direction = face_direction.to_vec();
+        let axis = if direction.x != 0 {
+            0
+        } else if direction.y != 0 {
+            1
+        } else {
+            2
+        };
+        let side = if direction[axis] > 0 { 1.0 } else { 0.0 };
+        let cull = if element.rotation == 0.0 && corners[0][axis] == side {
+            Some(face_direction)
+        } else {
+            None
+        };
+
+        for corner in corners.iter_mut() {
+            *corner = rotate_element_point(*corner, element.rotation, element.rescale);
+        }
+        ModelFace {
+            corners,
+            uvs,
+            tile: face.tile.unwrap_or(face_direction),
+            cull,
+            when: element.when,
+        }
+    }
+
+    /// Whether the model fills its block, hiding the faces of its neighbours against it.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Whether a part of the model that depends on `side` is there. `connected` tells whether the
+    /// block connects to its neighbour in a world direction.
+    fn has_part(state: BlockState, side: Option<FaceDirection>, connected: &impl Fn(FaceDirection) -> bool) -> bool {
+        match side {
+            Some(side) => connected(state.rotate_face(side)),
+            None => true,
+        }
+    }
+
+    /// Quads of a block of `block_type` at `position`, turned to the orientation of `state`.
+    pub fn quads(&self, block_type: BlockType, state: BlockState, position: [i32; 3], connected: impl Fn(FaceDirection) -> bool) -> Vec<ModelQuad> {
+        self.faces
+            .iter()
+            .filter(|face| BlockModel::has_part(state, face.when, &connected))
+            .map(|face| {
+                let vertex = |i: usize| {
+                    let corner = state.rotate_point(face.corners[i]);
+                    let pos = [corner[0] + position[0] as f32, corner[1] + position[1] as f32, corner[2] + position[2] as f32];
+                    BlockVertex::new(pos, block_type.get_tile_coordinates(face.uvs[i], face.tile))
+                };
+                ModelQuad {
+                    vertices: [vertex(0), vertex(1), vertex(2), vertex(3)],
+                    cull: face.cull.map(|cull| state.rotate_face(cull)),
+                }
+            })
+            .collect()
+    }
+
+    /// Boxes of the elements of the block, at the origin of the block.
+    pub fn shape(&self, state: BlockState, connected: impl Fn(FaceDirection) -> bool) -> Vec<Aabb> {
+        BlockModel::oriented_boxes(&self.shape, state, &connected)
+    }
+
+    /// Boxes the block collides with, at the origin of the block.
+    pub fn collision_boxes(&self, state: BlockState, connected: impl Fn(FaceDirection) -> bool) -> Vec<Aabb> {
+        BlockModel::oriented_boxes(&self.collision, state, &connected)
+    }
+
+    fn oriented_boxes(boxes: &[ModelBox], state: BlockState, connected: &impl Fn(FaceDirection) -> bool) -> Vec<Aabb> {
+        boxes
+            .iter()
+            .filter(|model_box| BlockModel::has_part(state, model_box.when, connected))
+            .map(|model_box| model_box.oriented(state))
+            .collect()
+    }
+}
+
+/// Models defined in the models file, referenced by name by the block definitions.
+#[derive(Debug)]
+pub struct BlockModels {
+    models: HashMap<String, BlockModel>,
+}
+
+impl BlockModels {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = read_to_string(path).with_context(|| format!("Error could not read block models '{}'", path.display()))?;
+        BlockModels::parse(&content).with_context(|| format!("Error invalid block models '{}'", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let definitions: Vec<ModelDefinition> = ron::de::from_str(content)?;
+
+        let mut models = HashMap::new();
+        for definition in definitions {
+            let model = BlockModel::new(definition)?;
+            if models.contains_key(&model.name) {
+                bail!("Error duplicate block model '{}'", model.name);
+            }
+            models.insert(model.name.clone(), model);
+        }
+        if !models.contains_key(DEFAULT_BLOCK_MODEL) {
+            bail!("Error missing block model '{}'", DEFAULT_BLOCK_MODEL);
+        }
+        Ok(Self { models })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BlockModel> {
+        self.models.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const MODELS: &str = r#"
+#![enable(implicit_some)]
+[
+    (name: "cube", elements: [(from: (0, 0, 0), to: (16, 16, 16), faces: (all: ()))]),
+    (name: "slab", elements: [(from: (0, 0, 0), to: (16, 8, 16), faces: (all: ()))]),
+    (
+        name: "stairs",
+        elements: [
+            (from: (0, 0, 0), to: (16, 8, 16), faces: (all: ())),
+            (from: (0, 8, 0), to: (16, 16, 8), faces: (top: (), side: ())),
+        ],
+    ),
+    (
+        name: "fence",
+        elements: [
+            (from: (6, 0, 6), to: (10, 16, 10), faces: (all: ())),
+            (from: (10, 12, 7), to: (16, 15, 9), when: right, faces: (top: (), bottom: ())),
+        ],
+        collision: [(from: (6, 0, 6), to: (10, 24, 10)), (from: (10, 0, 6), to: (16, 24, 10), when: right)],
+    ),
+]
+"#;
+
+    fn model(name: &str) -> BlockModel {
+        BlockModels::parse(MODELS).unwrap().get(name).unwrap().clone()
+    }
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    fn connected_to(sides: &[FaceDirection]) -> impl Fn(FaceDirection) -> bool + '_ {
+        move |direction| sides.iter().any(|side| side.to_vec() == direction.to_vec())
+    }
+
+    #[test]
+    fn slabs_fill_the_bottom_half() {
+        init_registry();
+        let slab = model("slab");
+        assert!(model("cube").is_full());
+        assert!(!slab.is_full());
+        assert_eq!(slab.shape(BlockState::default(), |_| false), vec![aabb([0.0, 0.0, 0.0], [1.0, 0.5, 1.0])]);
+        assert_eq!(
+            slab.collision_boxes(BlockState::default(), |_| false),
+            slab.shape(BlockState::default(), |_| false)
+        );
+
+        // Every face but the top one lies on a side of the block
+        let quads = slab.quads(BlockType::DIRT, BlockState::default(), [0, 0, 0], |_| false);
+        assert_eq!(quads.len(), 6);
+        let culls: Vec<Option<Vector3<i32>>> = quads.iter().map(|quad| quad.cull.map(FaceDirection::to_vec)).collect();
+        assert_eq!(culls.iter().filter(|cull| cull.is_none()).count(), 1);
+        assert!(!culls.contains(&Some(FaceDirection::TOP.to_vec())));
+    }
+
+    #[test]
+    fn stairs_turn_with_their_facing() {
+        init_registry();
+        let stairs = model("stairs");
+        let state = BlockState::default();
+        assert_eq!(
+            stairs.shape(state, |_| false),
+            vec![aabb([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]), aabb([0.0, 0.5, 0.0], [1.0, 1.0, 0.5])]
+        );
+        assert_eq!(stairs.quads(BlockType::DIRT, state, [0, 0, 0], |_| false).len(), 6 + 5);
+
+        let turned = state.with_facing(FaceDirection::BACK);
+        assert_eq!(
+            stairs.shape(turned, |_| false),
+            vec![aabb([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]), aabb([0.0, 0.5, 0.5], [1.0, 1.0, 1.0])]
+        );
+    }
+
+    #[test]
+    fn fence_parts_depend_on_the_connected_sides() {
+        init_registry();
+        let fence = model("fence");
+        let state = BlockState::default();
+        assert!(!fence.is_full());
+        let post = aabb([0.375, 0.0, 0.375], [0.625, 1.0, 0.625]);
+        assert_eq!(fence.shape(state, |_| false), vec![post]);
+        assert_eq!(fence.collision_boxes(state, |_| false), vec![aabb([0.375, 0.0, 0.375], [0.625, 1.5, 0.625])]);
+        assert_eq!(fence.quads(BlockType::DIRT, state, [0, 0, 0], |_| false).len(), 6);
+
+        let right = [FaceDirection::RIGHT];
+        assert_eq!(
+            fence.shape(state, connected_to(&right)),
+            vec![post, aabb([0.625, 0.75, 0.4375], [1.0, 0.9375, 0.5625])]
+        );
+        assert_eq!(
+            fence.collision_boxes(state, connected_to(&right)),
+            vec![aabb([0.375, 0.0, 0.375], [0.625, 1.5, 0.625]), aabb([0.625, 0.0, 0.375], [1.0, 1.5, 0.625])]
+        );
+        assert_eq!(fence.quads(BlockType::DIRT, state, [0, 0, 0], connected_to(&right)).len(), 6 + 2);
+        // Other sides have no part of their own
+        let left = [FaceDirection::LEFT, FaceDirection::FRONT, FaceDirection::TOP];
+        assert_eq!(fence.shape(state, connected_to(&left)), vec![post]);
+    }
+
+    #[test]
+    fn invalid_models_are_rejected() {
+        let parse = |models: &[&str]| BlockModels::parse(&format!("#![enable(implicit_some)]\n[{}]", models.join(", ")));
+        let cube = r#"(name: "cube", elements: [(from: (0, 0, 0), to: (16, 16, 16), faces: (all: ()))])"#;
+        assert!(parse(&[cube]).is_ok());
+        assert!(parse(&[cube, cube]).is_err());
+        assert!(parse(&[r#"(name: "slab", elements: [(from: (0, 0, 0), to: (16, 8, 16))])"#]).is_err());
+        assert!(parse(&[cube, r#"(name: "inverted", elements: [(from: (0, 8, 0), to: (16, 0, 16))])"#]).is_err());
+        assert!(parse(&[cube, r#"(name: "post", elements: [(from: (0, 0, 0), to: (16, 16, 16), when: up)])"#]).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashSet;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::*;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{atlas::*, block::FaceDirection, block_model::*};
+
+pub const BLOCK_REGISTRY_PATH: &str = "./assets/blocks.ron";
+
+static BLOCK_REGISTRY: OnceCell<BlockRegistry> = OnceCell::new();
+
+/// Id of a block definition in the `BlockRegistry`.
+///
+/// Only the blocks the engine itself refers to (world generation, player) have a constant here,
+/// every other block only exists in the registry file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockType(pub u16);
+
+impl BlockType {
+    pub const AIR: BlockType = BlockType(0);
+    pub const DIRT: BlockType = BlockType(1);
+    pub const GRASS: BlockType = BlockType(2);
+    pub const ROCK: BlockType = BlockType(3);
+    pub const WATER: BlockType = BlockType(4);
+    pub const DEBUG: BlockType = BlockType(5);
+    pub const BEDROCK: BlockType = BlockType(6);
+    pub const SAND: BlockType = BlockType(7);
+    pub const SNOW: BlockType = BlockType(8);
+    pub const LOG: BlockType = BlockType(9);
+    pub const LEAVES: BlockType = BlockType(10);
+    pub const SPRUCE_LOG: BlockType = BlockType(11);
+    pub const SPRUCE_LEAVES: BlockType = BlockType(12);
+    pub const TALL_GRASS: BlockType = BlockType(13);
+    pub const ROSE: BlockType = BlockType(14);
+    pub const DANDELION: BlockType = BlockType(15);
+    pub const DEAD_BUSH: BlockType = BlockType(16);
+    pub const GRAVEL: BlockType = BlockType(17);
+    pub const COAL_ORE: BlockType = BlockType(18);
+    pub const IRON_ORE: BlockType = BlockType(19);
+    pub const GOLD_ORE: BlockType = BlockType(20);
+    pub const DIAMOND_ORE: BlockType = BlockType(21);
+
+    /// Blocks the engine refers to directly, they must be defined with these names.
+    const BUILTIN: [(BlockType, &'static str); 22] = [
+        (BlockType::AIR, "air"),
+        (BlockType::DIRT, "dirt"),
+        (BlockType::GRASS, "grass"),
+        (BlockType::ROCK, "rock"),
+        (BlockType::WATER, "water"),
+        (BlockType::DEBUG, "debug"),
+        (BlockType::BEDROCK, "bedrock"),
+        (BlockType::SAND, "sand"),
+        (BlockType::SNOW, "snow"),
+        (BlockType::LOG, "log"),
+        (BlockType::LEAVES, "leaves"),
+        (BlockType::SPRUCE_LOG, "spruce_log"),
+        (BlockType::SPRUCE_LEAVES, "spruce_leaves"),
+        (BlockType::TALL_GRASS, "tall_grass"),
+        (BlockType::ROSE, "rose"),
+        (BlockType::DANDELION, "dandelion"),
+        (BlockType::DEAD_BUSH, "dead_bush"),
+        (BlockType::GRAVEL, "gravel"),
+        (BlockType::COAL_ORE, "coal_ore"),
+        (BlockType::IRON_ORE, "iron_ore"),
+        (BlockType::GOLD_ORE, "gold_ore"),
+        (BlockType::DIAMOND_ORE, "diamond_ore"),
+    ];
+
+    pub fn properties(&self) -> &'static BlockProperties {
+        BlockRegistry::get().properties(*self)
+    }
+
+    pub fn get_texture_coordinates(&self, texture_corner: [u32; 2], face_direction: FaceDirection) -> [f32; 2] {
+        match self.properties().tiles {
+            Some(tiles) => tiles[face_direction as usize].corner(texture_corner),
+            None => [0.0, 0.0],
+        }
+    }
+
+    /// Texture coordinates of the point at `uv` in the tile of a face, see `TileUv::point`.
+    pub fn get_tile_coordinates(&self, uv: [f32; 2], face_direction: FaceDirection) -> [f32; 2] {
+        match self.properties().tiles {
+            Some(tiles) => tiles[face_direction as usize].point(uv),
+            None => [0.0, 0.0],
+        }
+    }
+
+    /// Time in seconds it takes to break the block, `None` if it cannot be broken.
+    pub fn hardness(&self) -> Option<f32> {
+        self.properties().hardness
+    }
+
+    /// How much the block weakens the explosions going through it, its hardness unless set.
+    /// `None` if explosions cannot destroy it.
+    pub fn blast_resistance(&self) -> Option<f32> {
+        self.properties().blast_resistance.or(self.properties().hardness)
+    }
+
+    /// Blocks without tiles (air) are never meshed.
+    pub fn is_visible(&self) -> bool {
+        self.properties().tiles.is_some()
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.properties().transparent
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.properties().solid
+    }
+
+    pub fn model(&self) -> &'static BlockModel {
+        &self.properties().model
+    }
+
+    /// Blocks hiding the faces of their neighbours: full and not transparent.
+    pub fn is_opaque_cube(&self) -> bool {
+        self.model().is_full() && !self.is_transparent()
+    }
+
+    /// Whether the parts of the model depending on a side are there when `neighbour` is on that
+    /// side: next to blocks of the same model, like fences, and to opaque cubes.
+    pub fn connects_to(&self, neighbour: BlockType) -> bool {
+        neighbour.model().name == self.model().name || neighbour.is_opaque_cube()
+    }
+
+    pub fn behaviour(&self) -> BlockBehaviour {
+        self.properties().behaviour
+    }
+
+    pub fn block_entity(&self) -> BlockEntityKind {
+        self.properties().block_entity
+    }
+
+    /// Whether blocks of the type keep `property` in their `BlockState`.
+    pub fn has_state(&self, property: BlockStateProperty) -> bool {
+        self.properties().states.contains(&property)
+    }
+}
+
+/// How a block changes over time, run by `BlockUpdates` on scheduled updates and random ticks.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockBehaviour {
+    #[default]
+    NONE,
+    /// Spreads onto nearby dirt with nothing opaque above it on random ticks, turns into dirt when
+    /// covered.
+    SPREADING,
+    /// Turns into a falling block when the block below is not solid, after a neighbour changed or
+    /// when generated that way.
+    FALLING,
+    /// Disappears on random ticks when no log is close enough.
+    DECAYING,
+    /// Explodes after a fuse when lit with `right click` or caught in another explosion.
+    EXPLOSIVE,
+}
+
+/// Part of the `BlockState` a block uses, set when the block is placed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockStateProperty {
+    /// The `front` tile faces the player.
+    FACING,
+    /// The `top` and `bottom` tiles follow the face the block is placed against.
+    AXIS,
+    /// Upside down when placed against the upper half of a side or against a bottom face.
+    HALF,
+    /// Keeps the water it is placed in.
+    WATERLOGGED,
+}
+
+/// Data a block of the type keeps in a `BlockEntity`, created and removed with the block.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockEntityKind {
+    #[default]
+    NONE,
+    /// Stacks of blocks stored with `right click`, dropped when the chest is broken.
+    CHEST,
+    /// A line of text typed after a `right click`.
+    SIGN,
+}
+
+/// Texture names of a block definition. Specific faces override `side`, which overrides `all`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TileDefinition {
+    all: Option<String>,
+    side: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    right: Option<String>,
+    left: Option<String>,
+    front: Option<String>,
+    back: Option<String>,
+}
+
+impl TileDefinition {
+    fn face(&self, face_direction: FaceDirection) -> Option<&String> {
+        let specific = match face_direction {
+            FaceDirection::TOP => return self.top.as_ref().or(self.all.as_ref()),
+            FaceDirection::BOTTOM => return self.bottom.as_ref().or(self.all.as_ref()),
+            FaceDirection::RIGHT => self.right.as_ref(),
+            FaceDirection::LEFT => self.left.as_ref(),
+            FaceDirection::FRONT => self.front.as_ref(),
+            FaceDirection::BACK => self.back.as_ref(),
+        };
+        specific.or(self.side.as_ref()).or(self.all.as_ref())
+    }
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockDefinition {
+    id: u16,
+    name: String,
+    #[serde(default)]
+    tiles: Option<TileDefinition>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    behaviour: BlockBehaviour,
+    #[serde(default)]
+    block_entity: BlockEntityKind,
+    #[serde(default)]
+    states: Vec<BlockStateProperty>,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    hardness: Option<f32>,
+    #[serde(default)]
+    blast_resistance: Option<f32>,
+    #[serde(default)]
+    light_emission: u8,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct BlockProperties {
+    pub name: String,
+    /// Atlas tile per face, indexed by `FaceDirection`. `None` for blocks that are not rendered.
+    pub tiles: Option<[TileUv; 6]>,
+    pub model: BlockModel,
+    pub behaviour: BlockBehaviour,
+    pub block_entity: BlockEntityKind,
+    pub states: Vec<BlockStateProperty>,
+    pub solid: bool,
+    pub transparent: bool,
+    pub hardness: Option<f32>,
+    pub blast_resistance: Option<f32>,
+    pub light_emission: u8,
+}
+
+#[derive(Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockProperties>>,
+}
+
+impl BlockRegistry {
+    /// Loads the registry file and makes it available through `BlockRegistry::get`.
+    pub fn init<P: AsRef<Path>>(path: P, models: &BlockModels, texture_pack: &TexturePack) -> Result<&'static Self> {
+        let registry = BlockRegistry::load(path, models, texture_pack)?;
+        if BLOCK_REGISTRY.set(registry).is_err() {
+            bail!("Error block registry is already initialized");
+        }
+        Ok(BlockRegistry::get())
+    }
+
+    pub fn get() -> &'static Self {
+        BLOCK_REGISTRY.get().expect("Block registry is not initialized")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, models: &BlockModels, texture_pack: &TexturePack) -> Result<Self> {
+        let path = path.as_ref();
+        let content = read_to_string(path).with_context(|| format!("Error could not read block registry '{}'", path.display()))?;
+        BlockRegistry::parse(&content, models, texture_pack).with_context(|| format!("Error invalid block registry '{}'", path.display()))
+    }
+
+    pub fn parse(content: &str, models: &BlockModels, texture_pack: &TexturePack) -> Result<Self> {
+        let definitions: Vec<BlockDefinition> = ron::de::from_str(content)?;
+
+        let mut names = HashSet::new();
+        let mut blocks: Vec<Option<BlockProperties>> = Vec::new();
+        for definition in definitions {
+            let id = definition.id as usize;
+            if blocks.len() <= id {
+                blocks.resize(id + 1, None);
+            }
+            if let Some(other) = &blocks[id] {
+                bail!("Error duplicate block id {} for '{}' and '{}'", id, other.name, definition.name);
+            }
+            if !names.insert(definition.name.clone()) {
+                bail!("Error duplicate block name '{}'", definition.name);
+            }
+
+            let tiles = match &definition.tiles {
+                Some(tile_definition) => Some(BlockRegistry::resolve_tiles(&definition.name, tile_definition, texture_pack)?),
+                None => None,
+            };
+
+            let model_name = definition.model.as_deref().unwrap_or(DEFAULT_BLOCK_MODEL);
+            let model = match models.get(model_name) {
+                Some(model) => model.clone(),
+                None => bail!("Error block '{}' uses model '{}' which is not defined", definition.name, model_name),
+            };
+
+            blocks[id] = Some(BlockProperties {
+                name: definition.name,
+                tiles,
+                model,
+                behaviour: definition.behaviour,
+                block_entity: definition.block_entity,
+                states: definition.states,
+                solid: definition.solid,
+                transparent: definition.transparent,
+                hardness: definition.hardness,
+                blast_resistance: definition.blast_resistance,
+                light_emission: definition.light_emission,
+            });
+        }
+
+        let registry = Self { blocks };
+        for (block_type, name) in BlockType::BUILTIN.iter() {
+            match registry.try_properties(*block_type) {
+                Some(properties) if properties.name == *name => {}
+                Some(properties) => bail!("Error block id {} must be '{}' but is '{}'", block_type.0, name, properties.name),
+                None => bail!("Error missing builtin block '{}' with id {}", name, block_type.0),
+            }
+        }
+
+        Ok(registry)
+    }
+
+    fn resolve_tiles(name: &str, tile_definition: &TileDefinition, texture_pack: &TexturePack) -> Result<[TileUv; 6]> {
+        let mut tiles = [TileUv {
+            min: [0.0, 0.0],
+            max: [0.0, 0.0],
+        }; 6];
+        for face_direction in FaceDirection::ALL.iter() {
+            let texture = match tile_definition.face(*face_direction) {
+                Some(texture) => texture,
+                None => bail!("Error block '{}' is missing a tile for face {:?}", name, face_direction),
+            };
+            tiles[*face_direction as usize] = match texture_pack.tile_uv(texture) {
+                Some(tile) => tile,
+                None => bail!("Error block '{}' uses texture '{}' which is not in the texture pack", name, texture),
+            };
+        }
+        Ok(tiles)
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.iter().filter(|block| block.is_some()).count()
+    }
+
+    /// Registered block types, by increasing id.
+    pub fn block_types(&self) -> impl Iterator<Item = BlockType> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.is_some())
+            .map(|(id, _)| BlockType(id as u16))
+    }
+
+    pub fn block_type(&self, name: &str) -> Option<BlockType> {
+        self.blocks
+            .iter()
+            .position(|block| matches!(block, Some(block) if block.name == name))
+            .map(|id| BlockType(id as u16))
+    }
+
+    pub fn try_properties(&self, block_type: BlockType) -> Option<&BlockProperties> {
+        self.blocks.get(block_type.0 as usize).and_then(|block| block.as_ref())
+    }
+
+    pub fn properties(&self, block_type: BlockType) -> &BlockProperties {
+        match self.try_properties(block_type) {
+            Some(properties) => properties,
+            None => panic!("Error unknown block id {}", block_type.0),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::*;
+use cgmath::*;
+use wgpu::util::DeviceExt;
+
+use crate::renderer::*;
+
+/// Small offset so the outline does not z-fight with the block faces.
+const OUTLINE_OFFSET: f32 = 0.002;
+const OUTLINE_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
+const FACE_HIGHLIGHT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const MAX_VERTICES: usize = 24 + 4;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SelectionVertex {
+    pos: [f32; 3],
+    color: [f32; 3],
+}
+
+unsafe impl bytemuck::Pod for SelectionVertex {}
+unsafe impl bytemuck::Zeroable for SelectionVertex {}
+
+impl Vertex for SelectionVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<SelectionVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float3,
+                },
+            ],
+        }
+    }
+}
+
+fn selection_vertex(pos: Vector3<f32>, color: [f32; 3]) -> SelectionVertex {
+    SelectionVertex { pos: pos.into(), color }
+}
+
+/// Line list of the 12 edges of the block at `block_pos`, slightly inflated.
+fn create_outline_vertices(block_pos: Vector3<i32>) -> Vec<SelectionVertex> {
+    let min = block_pos.cast::<f32>().unwrap() - vec3(OUTLINE_OFFSET, OUTLINE_OFFSET, OUTLINE_OFFSET);
+    let size = 1.0 + 2.0 * OUTLINE_OFFSET;
+    let corner = |x: f32, y: f32, z: f32| selection_vertex(min + vec3(x, y, z) * size, OUTLINE_COLOR);
+
+    vec![
+        // bottom
+        corner(0.0, 0.0, 0.0),
+        corner(1.0, 0.0, 0.0),
+        corner(1.0, 0.0, 0.0),
+        corner(1.0, 0.0, 1.0),
+        corner(1.0, 0.0, 1.0),
+        corner(0.0, 0.0, 1.0),
+        corner(0.0, 0.0, 1.0),
+        corner(0.0, 0.0, 0.0),
+        // top
+        corner(0.0, 1.0, 0.0),
+        corner(1.0, 1.0, 0.0),
+        corner(1.0, 1.0, 0.0),
+        corner(1.0, 1.0, 1.0),
+        corner(1.0, 1.0, 1.0),
+        corner(0.0, 1.0, 1.0),
+        corner(0.0, 1.0, 1.0),
+        corner(0.0, 1.0, 0.0),
+        // sides
+        corner(0.0, 0.0, 0.0),
+        corner(0.0, 1.0, 0.0),
+        corner(1.0, 0.0, 0.0),
+        corner(1.0, 1.0, 0.0),
+        corner(1.0, 0.0, 1.0),
+        corner(1.0, 1.0, 1.0),
+        corner(0.0, 0.0, 1.0),
+        corner(0.0, 1.0, 1.0),
+    ]
+}
+
+/// Two diagonals crossing the face of the block pointed to by `face_direction`.
+fn create_face_vertices(block_pos: Vector3<i32>, face_direction: Vector3<i32>) -> Vec<SelectionVertex> {
+    let normal = face_direction.cast::<f32>().unwrap();
+    let center = block_pos.cast::<f32>().unwrap() + vec3(0.5, 0.5, 0.5) + normal * (0.5 + OUTLINE_OFFSET);
+
+    // Two axes spanning the face plane
+    let (u, v) = if face_direction.x != 0 {
+        (Vector3::unit_y(), Vector3::unit_z())
+    } else if face_direction.y != 0 {
+        (Vector3::unit_x(), Vector3::unit_z())
+    } else {
+        (Vector3::unit_x(), Vector3::unit_y())
+    };
+    let u = u * 0.5;
+    let v = v * 0.5;
+
+    vec![
+        selection_vertex(center - u - v, FACE_HIGHLIGHT_COLOR),
+        selection_vertex(center + u + v, FACE_HIGHLIGHT_COLOR),
+        selection_vertex(center - u + v, FACE_HIGHLIGHT_COLOR),
+        selection_vertex(center + u - v, FACE_HIGHLIGHT_COLOR),
+    ]
+}
+
+pub struct BlockSelection {
+    pub vertex_buffer: wgpu::Buffer,
+    pub num_vertices: usize,
+    pub render_pipeline: wgpu::RenderPipeline,
+}
+
+impl BlockSelection {
+    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, uniform_bind_group_layout: &wgpu::BindGroupLayout) -> Result<Self> {
+        let vertices = vec![selection_vertex(Vector3::zero(), OUTLINE_COLOR); MAX_VERTICES];
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SelectionVertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            wgpu::PrimitiveTopology::LineList,
+            sc_desc.format,
+            wgpu::BlendState::REPLACE,
+            &[<SelectionVertex>::desc()],
+            wgpu::include_spirv!("../assets/shaders/line.vert.spv"),
+            wgpu::include_spirv!("../assets/shaders/line.frag.spv"),
+            false,
+        );
+
+        Ok(Self {
+            vertex_buffer,
+            num_vertices: 0,
+            render_pipeline,
+        })
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, block_pos: Option<Vector3<i32>>, face_direction: Vector3<i32>) {
+        match block_pos {
+            Some(block_pos) => {
+                let mut vertices = create_outline_vertices(block_pos);
+                if face_direction != Vector3::zero() {
+                    vertices.extend(create_face_vertices(block_pos, face_direction));
+                }
+                self.num_vertices = vertices.len();
+
+                queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            }
+            None => self.num_vertices = 0,
+        }
+    }
+}
+
+impl Draw for BlockSelection {
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, uniforms: &'a wgpu::BindGroup) -> Result<()> {
+        if self.num_vertices > 0 {
+            render_pass.set_pipeline(&self.render_pipeline);
+
+            render_pass.set_bind_group(0, uniforms, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.draw(0..self.num_vertices as u32, 0..1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::BTreeSet;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{block::BlockState, block_registry::*, chunk::*};
+
+/// Blocks picked at random in each loaded chunk on every tick.
+pub const RANDOM_TICKS_PER_CHUNK: usize = 40;
+/// Ticks between a change and a falling block reacting to it.
+pub const FALLING_DELAY: u64 = 2;
+/// Leaves further than this from a log decay, in blocks along each axis.
+const LEAVES_SUPPORT_DISTANCE: i32 = 4;
+
+const NEIGHBOURS: [[i32; 3]; 6] = [[0, 1, 0], [0, -1, 0], [1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+fn add(position: [i32; 3], offset: [i32; 3]) -> [i32; 3] {
+    [position[0] + offset[0], position[1] + offset[1], position[2] + offset[2]]
+}
+
+/// Blocks the behaviours read and write, in world coordinates.
+///
+/// `World` implements it over its loaded chunks, anything else can be used to run block updates
+/// without rendering.
+pub trait BlockGrid {
+    /// `None` outside of the loaded world.
+    fn block_type(&self, position: [i32; 3]) -> Option<BlockType>;
+
+    /// Orientation of the block, the default one outside of the loaded world.
+    fn block_state(&self, position: [i32; 3]) -> BlockState;
+
+    /// Replaces a block, updates of its neighbours are scheduled by `BlockUpdates`.
+    fn replace_block(&mut self, position: [i32; 3], block_type: BlockType);
+
+    /// Turns the block removed from `position` into a falling block, returns false when no more
+    /// blocks can fall at once.
+    fn spawn_falling_block(&mut self, position: [i32; 3], block_type: BlockType) -> bool;
+
+    /// Offsets of the chunks getting random ticks.
+    fn loaded_chunk_offsets(&self) -> Vec<[i32; 3]>;
+
+    /// Random ticks pick blocks below this height.
+    fn world_height(&self) -> usize;
+}
+
+/// Light reaches a block through anything but water and opaque solid blocks.
+fn is_covered(grid: &impl BlockGrid, position: [i32; 3]) -> bool {
+    match grid.block_type(add(position, [0, 1, 0])) {
+        Some(above) => above == BlockType::WATER || (above.is_solid() && !above.is_transparent()),
+        None => false,
+    }
+}
+
+/// Whether a falling block at `position` has nothing solid below it.
+pub fn is_unsupported(grid: &impl BlockGrid, position: [i32; 3]) -> bool {
+    match grid.block_type(add(position, [0, -1, 0])) {
+        // Falling blocks sink through water and crush plants
+        Some(below_type) => !below_type.is_solid(),
+        None => false,
+    }
+}
+
+/// Changes made by a falling block, `None` if it cannot fall yet because too many blocks are
+/// falling.
+fn fall(grid: &mut impl BlockGrid, position: [i32; 3], block_type: BlockType) -> Option<Vec<([i32; 3], BlockType)>> {
+    if !is_unsupported(grid, position) {
+        return Some(Vec::new());
+    }
+    if !grid.spawn_falling_block(position, block_type) {
+        return None;
+    }
+    Some(vec![(position, BlockType::AIR)])
+}
+
+fn spread(grid: &impl BlockGrid, position: [i32; 3], block_type: BlockType, rng: &mut StdRng) -> Vec<([i32; 3], BlockType)> {
+    if is_covered(grid, position) {
+        return vec![(position, BlockType::DIRT)];
+    }
+
+    let target = add(position, [rng.gen_range(-1..=1), rng.gen_range(-3..=1), rng.gen_range(-1..=1)]);
+    if grid.block_type(target) == Some(BlockType::DIRT) && !is_covered(grid, target) {
+        return vec![(target, block_type)];
+    }
+    Vec::new()
+}
+
+fn decay(grid: &impl BlockGrid, position: [i32; 3]) -> Vec<([i32; 3], BlockType)> {
+    for x in -LEAVES_SUPPORT_DISTANCE..=LEAVES_SUPPORT_DISTANCE {
+        for y in -LEAVES_SUPPORT_DISTANCE..=LEAVES_SUPPORT_DISTANCE {
+            for z in -LEAVES_SUPPORT_DISTANCE..=LEAVES_SUPPORT_DISTANCE {
+                // Blocks outside of the loaded world or of the world height are no log
+                if let Some(BlockType::LOG) | Some(BlockType::SPRUCE_LOG) = grid.block_type(add(position, [x, y, z])) {
+                    return Vec::new();
+                }
+            }
+        }
+    }
+    vec![(position, BlockType::AIR)]
+}
+
+/// Scheduled block updates and random ticks, running the `BlockBehaviour` of each block type.
+///
+/// Updates due on a tick run in position order and random ticks draw from an RNG seeded with the
+/// world seed, so the same changes on the same world always give the same result.
+#[derive(Debug)]
+pub struct BlockUpdates {
+    tick: u64,
+    /// (tick, position) of the pending updates.
+    scheduled: BTreeSet<(u64, [i32; 3])>,
+    rng: StdRng,
+}
+
+impl BlockUpdates {
+    /// Random ticks draw from an RNG seeded with `seed`.
+    pub fn new(seed: u32) -> Self {
+        Self {
+            tick: 0,
+            scheduled: BTreeSet::new(),
+            rng: StdRng::seed_from_u64(seed as u64),
+        }
+    }
+
+    /// Schedules an update of `position` in `delay` ticks, only falling blocks react to updates.
+    pub fn schedule(&mut self, position: [i32; 3], delay: u64) {
+        self.scheduled.insert((self.tick + delay.max(1), position));
+    }
+
+    /// Schedules an update of `position` and its neighbours, after a block changed there.
+    pub fn block_changed(&mut self, position: [i32; 3]) {
+        self.schedule(position, FALLING_DELAY);
+        for offset in NEIGHBOURS.iter() {
+            self.schedule(add(position, *offset), FALLING_DELAY);
+        }
+    }
+
+    fn apply(&mut self, grid: &mut impl BlockGrid, changes: Vec<([i32; 3], BlockType)>, changed: &mut Vec<[i32; 3]>) {
+        for (position, block_type) in changes {
+            grid.replace_block(position, block_type);
+            self.block_changed(position);
+            changed.push(position);
+        }
+    }
+
+    /// Runs the updates due on the next tick and the random ticks, and returns the positions that
+    /// changed.
+    pub fn tick(&mut self, grid: &mut impl BlockGrid) -> Vec<[i32; 3]> {
+        self.tick += 1;
+        let mut changed = Vec::new();
+
+        let mut due = Vec::new();
+        while let Some(&(tick, position)) = self.scheduled.iter().next() {
+            if tick > self.tick {
+                break;
+            }
+            self.scheduled.remove(&(tick, position));
+            due.push(position);
+        }
+        due.sort_unstable();
+        due.dedup();
+
+        for position in due {
+            let changes = match grid.block_type(position) {
+                Some(block_type) if block_type.behaviour() == BlockBehaviour::FALLING => match fall(grid, position, block_type) {
+                    Some(changes) => changes,
+                    // The block stays where it is until there is room for it to fall
+                    None => {
+                        self.schedule(position, FALLING_DELAY);
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            self.apply(grid, changes, &mut changed);
+        }
+
+        let mut chunk_offsets = grid.loaded_chunk_offsets();
+        chunk_offsets.sort_unstable();
+        let world_height = grid.world_height();
+        for chunk_offset in chunk_offsets {
+            for _ in 0..RANDOM_TICKS_PER_CHUNK {
+                let position = [
+                    chunk_offset[0] * CHUNK_X_SIZE as i32 + self.rng.gen_range(0..CHUNK_X_SIZE as i32),
+                    self.rng.gen_range(0..world_height as i32),
+                    chunk_offset[2] * CHUNK_Z_SIZE as i32 + self.rng.gen_range(0..CHUNK_Z_SIZE as i32),
+                ];
+                let changes = match grid.block_type(position) {
+                    Some(block_type) => match block_type.behaviour() {
+                        BlockBehaviour::SPREADING => spread(grid, position, block_type, &mut self.rng),
+                        BlockBehaviour::DECAYING => decay(grid, position),
+                        _ => continue,
+                    },
+                    None => continue,
+                };
+                self.apply(grid, changes, &mut changed);
+            }
+        }
+
+        changed
+    }
+}
+
+impl Default for BlockUpdates {
+    fn default() -> Self {
+        BlockUpdates::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn run(block_updates: &mut BlockUpdates, grid: &mut MapGrid, ticks: usize) -> Vec<Vec<[i32; 3]>> {
+        (0..ticks).map(|_| block_updates.tick(grid)).collect()
+    }
+
+    #[test]
+    fn grass_spreads_to_uncovered_dirt() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 3, 15]);
+        grid.fill([0, 0, 0], [15, 0, 15], BlockType::DIRT);
+        grid.set([8, 0, 8], BlockType::GRASS);
+        grid.set([7, 1, 8], BlockType::ROCK);
+
+        run(&mut BlockUpdates::new(42), &mut grid, 2000);
+        assert_eq!(grid.block_type([9, 0, 8]), Some(BlockType::GRASS));
+        assert_eq!(grid.block_type([7, 0, 8]), Some(BlockType::DIRT));
+    }
+
+    #[test]
+    fn covered_grass_turns_to_dirt() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 3, 15]);
+        grid.set([4, 0, 4], BlockType::GRASS);
+        grid.set([4, 1, 4], BlockType::ROCK);
+
+        run(&mut BlockUpdates::new(42), &mut grid, 2000);
+        assert_eq!(grid.block_type([4, 0, 4]), Some(BlockType::DIRT));
+    }
+
+    #[test]
+    fn leaves_decay_away_from_logs() {
+        let mut grid = MapGrid::new([0, 0, 0], [31, 11, 31]);
+        grid.set([8, 5, 8], BlockType::LEAVES);
+        grid.set([20, 5, 20], BlockType::LEAVES);
+        grid.set([24, 5, 24], BlockType::LOG);
+        // Closer to the edge of the loaded world than to any log, and on the bottom of the world
+        grid.set([2, 5, 20], BlockType::SPRUCE_LEAVES);
+        grid.set([0, 0, 0], BlockType::LEAVES);
+        // Near a log at the top of the world
+        grid.set([28, 11, 28], BlockType::LOG);
+        grid.set([28, 9, 28], BlockType::LEAVES);
+
+        run(&mut BlockUpdates::new(42), &mut grid, 3000);
+        assert_eq!(grid.block_type([8, 5, 8]), Some(BlockType::AIR));
+        assert_eq!(grid.block_type([20, 5, 20]), Some(BlockType::LEAVES));
+        assert_eq!(grid.block_type([2, 5, 20]), Some(BlockType::AIR));
+        assert_eq!(grid.block_type([0, 0, 0]), Some(BlockType::AIR));
+        assert_eq!(grid.block_type([28, 9, 28]), Some(BlockType::LEAVES));
+    }
+
+    #[test]
+    fn scheduled_sand_falls_after_the_delay() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 7, 15]);
+        grid.set([8, 5, 8], BlockType::SAND);
+        grid.set([4, 1, 4], BlockType::SAND);
+        grid.set([4, 0, 4], BlockType::ROCK);
+        let mut block_updates = BlockUpdates::new(42);
+        block_updates.schedule([8, 5, 8], FALLING_DELAY);
+        block_updates.schedule([4, 1, 4], FALLING_DELAY);
+
+        let changed = run(&mut block_updates, &mut grid, FALLING_DELAY as usize);
+        assert!(changed[..FALLING_DELAY as usize - 1].iter().all(|changed| changed.is_empty()));
+        assert_eq!(changed[FALLING_DELAY as usize - 1], vec![[8, 5, 8]]);
+        assert_eq!(grid.block_type([8, 5, 8]), Some(BlockType::AIR));
+        assert_eq!(grid.falling, vec![([8, 5, 8], BlockType::SAND)]);
+        assert_eq!(grid.block_type([4, 1, 4]), Some(BlockType::SAND));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_changes() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 7, 15]);
+        grid.fill([0, 0, 0], [15, 0, 15], BlockType::DIRT);
+        grid.set([3, 0, 3], BlockType::GRASS);
+        grid.set([12, 0, 12], BlockType::GRASS);
+        grid.fill([6, 4, 6], [9, 5, 9], BlockType::LEAVES);
+        let mut other_grid = grid.clone();
+
+        let changed = run(&mut BlockUpdates::new(7), &mut grid, 500);
+        let other_changed = run(&mut BlockUpdates::new(7), &mut other_grid, 500);
+        assert!(changed.iter().any(|changed| !changed.is_empty()));
+        assert_eq!(changed, other_changed);
+        assert_eq!(grid.blocks, other_grid.blocks);
+    }
+
+    #[test]
+    fn sand_waits_for_room_to_fall() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 7, 15]);
+        grid.max_falling = 1;
+        grid.set([8, 5, 8], BlockType::SAND);
+        grid.set([4, 5, 4], BlockType::SAND);
+        let mut block_updates = BlockUpdates::new(42);
+        block_updates.schedule([8, 5, 8], FALLING_DELAY);
+        block_updates.schedule([4, 5, 4], FALLING_DELAY);
+
+        let changed: Vec<[i32; 3]> = run(&mut block_updates, &mut grid, FALLING_DELAY as usize).concat();
+        assert_eq!(changed, vec![[4, 5, 4]]);
+        assert_eq!(grid.block_type([8, 5, 8]), Some(BlockType::SAND));
+
+        // The first block landed, the other one falls on its next update
+        grid.falling.clear();
+        let changed: Vec<[i32; 3]> = run(&mut block_updates, &mut grid, FALLING_DELAY as usize).concat();
+        assert_eq!(changed, vec![[8, 5, 8]]);
+        assert_eq!(grid.falling, vec![([8, 5, 8], BlockType::SAND)]);
+    }
+}
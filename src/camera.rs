@@ -57,13 +57,15 @@ impl Camera {
 
         return direction.normalize();
     }
-    pub fn new<V: Into<Point3<f32>>, Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(renderer: &Renderer, position: V, yaw: Y, pitch: P) -> Self {
+    /// `world_height` is used to keep the whole height of the loaded chunks within the far plane.
+    pub fn new<V: Into<Point3<f32>>, Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(renderer: &Renderer, position: V, yaw: Y, pitch: P, world_height: usize) -> Self {
+        let view_distance = (WORLD_SIZE * CHUNK_Z_SIZE) as f32;
         let projection = Projection::new(
             renderer.sc_desc.width,
             renderer.sc_desc.height,
             cgmath::Deg(45.0),
             0.1,
-            (WORLD_SIZE * CHUNK_Z_SIZE) as f32,
+            view_distance.hypot(world_height as f32),
         );
         let camera_controller = CameraController::new(20.0, 0.4);
 
@@ -113,7 +115,8 @@ impl Camera {
             uniforms,
         };
 
-        camera.update(&renderer.queue, Duration::from_secs(0));
+        camera.update_camera_controller(Duration::from_secs(0));
+        camera.update_uniforms(&renderer.queue);
 
         return camera;
     }
@@ -153,8 +156,8 @@ impl Camera {
         self.projection.resize(new_size.width, new_size.height)
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue, dt: Duration) {
-        self.update_camera_controller(dt);
+    /// Sends the view to the shaders, once the position is final for this frame.
+    pub fn update_uniforms(&mut self, queue: &wgpu::Queue) {
         self.uniforms.update_view_proj(self.position, self.calc_matrix(), self.projection.calc_matrix());
         queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
     }
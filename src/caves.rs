@@ -0,0 +1,236 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, Seedable};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{block_registry::*, chunk::*, world_metadata::WorldSettings};
+
+/// Caves never reach the bottom layers so bedrock stays sealed.
+const CAVE_MIN_Y: usize = 5;
+/// Cheese caves stay this far below the sea level, only tunnels and ravines open to the surface.
+const CHEESE_SEA_DEPTH: usize = 8;
+
+const CHEESE_FREQUENCY: f64 = 1.0 / 64.0;
+/// Cheese caves are flattened, they are wider than they are high.
+const CHEESE_Y_STRETCH: f64 = 2.0;
+const SPAGHETTI_FREQUENCY: f64 = 1.0 / 96.0;
+const RAVINE_FREQUENCY: f64 = 1.0 / 256.0;
+const RAVINE_MASK_FREQUENCY: f64 = 1.0 / 512.0;
+const RAVINE_DEPTH: usize = 50;
+
+/// Which caves are carved into the terrain of a world.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CaveSettings {
+    /// Large open caverns where the density noise is above the threshold, `None` to disable.
+    pub cheese_threshold: Option<f64>,
+    /// Long tunnels where two noise fields are both close to zero, `None` to disable.
+    pub spaghetti_width: Option<f64>,
+    /// Narrow cracks going down from the surface, `None` to disable.
+    pub ravine_width: Option<f64>,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            cheese_threshold: Some(0.32),
+            spaghetti_width: Some(0.06),
+            ravine_width: Some(0.02),
+        }
+    }
+}
+
+impl CaveSettings {
+    pub fn none() -> Self {
+        Self {
+            cheese_threshold: None,
+            spaghetti_width: None,
+            ravine_width: None,
+        }
+    }
+}
+
+/// 3D density noise deciding which blocks are hollowed out.
+///
+/// Carving only depends on the seed and the world position of a block, a chunk is carved the same
+/// way whatever the generation order.
+pub struct CaveCarver {
+    settings: CaveSettings,
+    world_settings: WorldSettings,
+    cheese: Fbm,
+    spaghetti: [Perlin; 2],
+    ravine: Perlin,
+    ravine_mask: Perlin,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u32, settings: CaveSettings, world_settings: WorldSettings) -> Self {
+        let cheese = Fbm::new().set_seed(seed.wrapping_add(2000)).set_frequency(CHEESE_FREQUENCY).set_octaves(3);
+        let spaghetti = [Perlin::new().set_seed(seed.wrapping_add(2001)), Perlin::new().set_seed(seed.wrapping_add(2002))];
+        let ravine = Perlin::new().set_seed(seed.wrapping_add(2003));
+        let ravine_mask = Perlin::new().set_seed(seed.wrapping_add(2004));
+
+        Self {
+            settings,
+            world_settings,
+            cheese,
+            spaghetti,
+            ravine,
+            ravine_mask,
+        }
+    }
+
+    pub fn is_cave(&self, world_x: i32, y: usize, world_z: i32) -> bool {
+        if y < CAVE_MIN_Y {
+            return false;
+        }
+        let (x, z) = (world_x as f64, world_z as f64);
+        let y_f = y as f64;
+
+        if let Some(threshold) = self.settings.cheese_threshold {
+            if y + CHEESE_SEA_DEPTH < self.world_settings.sea_level && self.cheese.get([x, y_f * CHEESE_Y_STRETCH, z]) > threshold {
+                return true;
+            }
+        }
+
+        if let Some(width) = self.settings.spaghetti_width {
+            let point = [x * SPAGHETTI_FREQUENCY, y_f * SPAGHETTI_FREQUENCY, z * SPAGHETTI_FREQUENCY];
+            if self.spaghetti[0].get(point).abs() < width && self.spaghetti[1].get(point).abs() < width {
+                return true;
+            }
+        }
+
+        if let Some(width) = self.settings.ravine_width {
+            // Ravines only appear in some areas and narrow down to nothing at the bottom
+            let bottom = self.world_settings.sea_level.saturating_sub(RAVINE_DEPTH);
+            if y > bottom && self.ravine_mask.get([x * RAVINE_MASK_FREQUENCY, z * RAVINE_MASK_FREQUENCY]) > 0.3 {
+                let depth_factor = ((y - bottom) as f64 / RAVINE_DEPTH as f64).min(1.0);
+                if self.ravine.get([x * RAVINE_FREQUENCY, z * RAVINE_FREQUENCY]).abs() < width * depth_factor {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Hollows out the caves of an already generated chunk.
+    ///
+    /// Blocks next to water are kept so oceans and lakes do not drain into the caves.
+    /// `is_water_outside(x, y, z)` tells whether there is water at a position of the neighbouring
+    /// chunks, in the coordinates of this chunk.
+    pub fn carve(&self, blocks: &Blocks, offset: [i32; 3], is_water_outside: impl Fn(i32, usize, i32) -> bool + Sync) {
+        let is_water = |x: i32, y: usize, z: i32| {
+            if x < 0 || z < 0 || x >= CHUNK_X_SIZE as i32 || z >= CHUNK_Z_SIZE as i32 {
+                return is_water_outside(x, y, z);
+            }
+            blocks[y][x as usize][z as usize].lock().unwrap().material_type == BlockType::WATER
+        };
+
+        (0..CHUNK_X_SIZE * CHUNK_Z_SIZE).into_par_iter().for_each(|i| {
+            let x = i / CHUNK_Z_SIZE;
+            let z = i % CHUNK_Z_SIZE;
+            let world_x = offset[0] * CHUNK_X_SIZE as i32 + x as i32;
+            let world_z = offset[2] * CHUNK_Z_SIZE as i32 + z as i32;
+
+            for y in CAVE_MIN_Y..self.world_settings.world_height - 1 {
+                let material_type = blocks[y][x][z].lock().unwrap().material_type;
+                if material_type == BlockType::AIR || material_type == BlockType::WATER || material_type == BlockType::BEDROCK {
+                    continue;
+                }
+                // Neighbours are read without holding the block, columns are carved in parallel
+                let (x, z) = (x as i32, z as i32);
+                let next_to_water = is_water(x, y + 1, z)
+                    || is_water(x, y - 1, z)
+                    || is_water(x + 1, y, z)
+                    || is_water(x - 1, y, z)
+                    || is_water(x, y, z + 1)
+                    || is_water(x, y, z - 1);
+                if next_to_water {
+                    continue;
+                }
+
+                if self.is_cave(world_x, y, world_z) {
+                    blocks[y][x as usize][z as usize].lock().unwrap().update(BlockType::AIR);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const OFFSETS: [[i32; 3]; 3] = [[0, 0, 0], [3, 0, -5], [-8, 0, 2]];
+
+    fn carved(seed: u32, offset: [i32; 3]) -> Vec<BlockType> {
+        let blocks = chunk_blocks(BlockType::ROCK);
+        CaveCarver::new(seed, CaveSettings::default(), WorldSettings::default()).carve(&blocks, offset, |_, _, _| false);
+        chunk_block_types(&blocks)
+    }
+
+    fn air_count(block_types: &[BlockType]) -> usize {
+        block_types.iter().filter(|block_type| **block_type == BlockType::AIR).count()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_caves() {
+        for offset in OFFSETS.iter() {
+            let block_types = carved(11, *offset);
+            assert!(air_count(&block_types) > 0);
+            assert_eq!(block_types, carved(11, *offset));
+            assert_ne!(block_types, carved(12, *offset));
+        }
+    }
+
+    #[test]
+    fn caves_keep_bedrock_and_water_sealed() {
+        let settings = WorldSettings::default();
+        let carver = CaveCarver::new(11, CaveSettings::default(), settings);
+        for offset in OFFSETS.iter() {
+            let blocks = chunk_blocks(BlockType::ROCK);
+            for x in 0..CHUNK_X_SIZE {
+                for z in 0..CHUNK_Z_SIZE {
+                    for y in 0..settings.world_height {
+                        let block_type = match y {
+                            0..=2 | 40 => BlockType::BEDROCK,
+                            60 => BlockType::WATER,
+                            _ if (x, z) == (8, 8) && y < 90 => BlockType::WATER,
+                            _ => continue,
+                        };
+                        blocks[y][x][z].lock().unwrap().update(block_type);
+                    }
+                }
+            }
+            let before = chunk_block_types(&blocks);
+            // Water in the chunk on the negative x side
+            let is_water_outside = |x: i32, y: usize, _z: i32| x < 0 && (20..50).contains(&y);
+            carver.carve(&blocks, *offset, is_water_outside);
+            let after = chunk_block_types(&blocks);
+            assert!(air_count(&after) > 0);
+
+            for (before, after) in before.iter().zip(after.iter()) {
+                if *before != BlockType::ROCK {
+                    assert_eq!(before, after);
+                }
+            }
+            let is_water = |x: i32, y: usize, z: i32| {
+                if x < 0 || z < 0 || x >= CHUNK_X_SIZE as i32 || z >= CHUNK_Z_SIZE as i32 {
+                    return is_water_outside(x, y, z);
+                }
+                chunk_block(&blocks, [x as usize, y, z as usize]) == BlockType::WATER
+            };
+            for x in 0..CHUNK_X_SIZE as i32 {
+                for z in 0..CHUNK_Z_SIZE as i32 {
+                    for y in 1..settings.world_height - 1 {
+                        if chunk_block(&blocks, [x as usize, y, z as usize]) != BlockType::AIR {
+                            continue;
+                        }
+                        let neighbours = [(x + 1, y, z), (x - 1, y, z), (x, y + 1, z), (x, y - 1, z), (x, y, z + 1), (x, y, z - 1)];
+                        assert!(neighbours.iter().all(|(x, y, z)| !is_water(*x, *y, *z)), "{:?}", [x, y as i32, z]);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,17 +2,16 @@ use std::sync::{Arc, Mutex};
 
 use cgmath::*;
 
-use crate::{atlas::*, block::*, Config};
+use crate::{block::*, block_entity::BlockEntities, block_registry::*, Config};
 
 pub const CHUNK_Y_SIZE: usize = 200;
 pub const CHUNK_Z_SIZE: usize = 16;
 pub const CHUNK_X_SIZE: usize = 16;
 
-pub const SEA_LEVEL: usize = CHUNK_Y_SIZE / 2;
 pub const TOTAL_CHUNK_SIZE: usize = CHUNK_Y_SIZE * CHUNK_Z_SIZE * CHUNK_X_SIZE;
 
 fn init_blocks_and_mesh(offset: [i32; 3]) -> (Vec<Vec<Vec<Arc<Mutex<Block>>>>>, Mesh) {
-    let mut blocks = vec![vec![vec![Arc::new(Mutex::new(Block::new(BlockType::DIRT, [0, 0, 0], offset))); CHUNK_Z_SIZE]; CHUNK_X_SIZE]; CHUNK_Y_SIZE];
+    let mut blocks = vec![vec![vec![Arc::new(Mutex::new(Block::new(BlockType::DIRT, [0, 0, 0]))); CHUNK_Z_SIZE]; CHUNK_X_SIZE]; CHUNK_Y_SIZE];
 
     let mut vertices: Vec<BlockVertex> = Vec::new();
     let mut indices: Vec<u16> = Vec::new();
@@ -22,15 +21,15 @@ fn init_blocks_and_mesh(offset: [i32; 3]) -> (Vec<Vec<Vec<Arc<Mutex<Block>>>>>,
             for x in 0..CHUNK_X_SIZE {
                 let block_type = BlockType::DEBUG;
                 let position = [x as i32, y as i32, z as i32];
-                let block = Block::new(block_type, position, offset);
+                let block = Block::new(block_type, position);
 
                 let mut block_vertices = Vec::with_capacity(4 * 6);
                 let mut block_indices = Vec::with_capacity(6 * 6);
-                let mut face_counter: u16 = 0;
-                for face in block.faces.iter() {
-                    block_vertices.extend_from_slice(&face.vertices);
-                    block_indices.extend_from_slice(&face.get_indices(face_counter));
-                    face_counter += 1;
+                let world_pos = Block::world_position(position, offset);
+                let quads = block_type.model().quads(block_type, block.state, world_pos, |_| false);
+                for (face_counter, quad) in quads.iter().enumerate() {
+                    block_vertices.extend_from_slice(&quad.vertices);
+                    block_indices.extend_from_slice(&quad_indices(face_counter as u16));
                 }
 
                 vertices.extend(block_vertices);
@@ -67,6 +66,7 @@ pub struct ChunkArray {
     pub mesh_array: Vec<Arc<Mutex<Mesh>>>,
     pub offset_array: Vec<Arc<Mutex<[i32; 3]>>>,
     pub blocks_array: Vec<Arc<Mutex<Blocks>>>,
+    pub block_entities_array: Vec<Arc<Mutex<BlockEntities>>>,
 
     _config: Config,
 }
@@ -76,6 +76,7 @@ impl ChunkArray {
         let (blocks, mesh) = init_blocks_and_mesh(offset);
         self.mesh_array.push(Arc::new(Mutex::new(mesh)));
         self.blocks_array.push(Arc::new(Mutex::new(blocks)));
+        self.block_entities_array.push(Arc::new(Mutex::new(BlockEntities::new())));
         self.offset_array.push(Arc::new(Mutex::new(offset)));
         return self;
     }
@@ -89,14 +90,14 @@ impl ChunkArray {
         return false;
     }
 
-    pub fn change_block(&mut self, chunk_index: usize, position: [usize; 3], new_material_type: BlockType) {
+    pub fn change_block(&mut self, chunk_index: usize, position: [usize; 3], new_material_type: BlockType, state: BlockState) {
         let x = position[0];
         let y = position[1];
         let z = position[2];
         self.blocks_array[chunk_index].lock().unwrap()[y][x][z]
             .lock()
             .unwrap()
-            .update(new_material_type, self.offset_array[chunk_index].lock().unwrap().clone());
+            .update_with_state(new_material_type, state);
     }
 
     pub fn get_block(&self, chunk_index: usize, position: [i32; 3]) -> Block {
@@ -0,0 +1,136 @@
+use anyhow::*;
+use cgmath::*;
+use wgpu::util::DeviceExt;
+
+use crate::{atlas::*, block::*, renderer::*};
+
+/// Small offset so the cracks are drawn in front of the block faces.
+const CRACK_OFFSET: f32 = 0.004;
+
+fn create_vertices(block_pos: Vector3<i32>, crack_tile: TileUv) -> (Vec<BlockVertex>, Vec<u16>) {
+    let origin = block_pos.cast::<f32>().unwrap();
+    let size = 1.0 + 2.0 * CRACK_OFFSET;
+
+    let mut vertices = Vec::with_capacity(4 * 6);
+    let mut indices = Vec::with_capacity(6 * 6);
+    for (i, face_direction) in FaceDirection::ALL.iter().enumerate() {
+        for (corner, texture_corner) in face_direction.corners().iter().zip(TEXTURE_CORNERS.iter()) {
+            let pos = origin + vec3(corner[0] as f32, corner[1] as f32, corner[2] as f32) * size - vec3(CRACK_OFFSET, CRACK_OFFSET, CRACK_OFFSET);
+            vertices.push(BlockVertex::new(pos.into(), crack_tile.corner(*texture_corner)));
+        }
+
+        let displacement = i as u16 * 4;
+        indices.extend_from_slice(&[
+            displacement,
+            1 + displacement,
+            2 + displacement,
+            2 + displacement,
+            3 + displacement,
+            displacement,
+        ]);
+    }
+
+    (vertices, indices)
+}
+
+pub struct CrackOverlay {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: usize,
+    pub render_pipeline: wgpu::RenderPipeline,
+
+    diffuse_bind_group: wgpu::BindGroup,
+    crack_tiles: Vec<TileUv>,
+}
+
+impl CrackOverlay {
+    pub fn new(renderer: &Renderer, atlas: &Atlas, uniform_bind_group_layout: &wgpu::BindGroupLayout) -> Result<Self> {
+        let (vertices, indices) = create_vertices(Vector3::zero(), atlas.crack_tiles[0]);
+
+        let vertex_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("CrackOverlay Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        });
+        let index_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("CrackOverlay Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsage::INDEX,
+        });
+
+        // Same atlas texture as the world, bound separately so the overlay can draw itself.
+        let diffuse_bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &atlas.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas.diffuse_texture.sampler),
+                },
+            ],
+            label: Some("crack_overlay_bind_group"),
+        });
+
+        let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[&atlas.texture_bind_group_layout, uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = create_render_pipeline(
+            &renderer.device,
+            &render_pipeline_layout,
+            wgpu::PrimitiveTopology::TriangleList,
+            renderer.sc_desc.format,
+            wgpu::BlendState {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            &[<BlockVertex>::desc()],
+            wgpu::include_spirv!("../assets/shaders/3d_texture.vert.spv"),
+            wgpu::include_spirv!("../assets/shaders/3d_texture.frag.spv"),
+            false,
+        );
+
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            num_indices: 0,
+            render_pipeline,
+
+            diffuse_bind_group,
+            crack_tiles: atlas.crack_tiles.clone(),
+        })
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, block_pos: Option<Vector3<i32>>, progress: f32) {
+        match block_pos {
+            Some(block_pos) if progress > 0.0 => {
+                let (vertices, indices) = create_vertices(block_pos, crack_stage(&self.crack_tiles, progress));
+                self.num_indices = indices.len();
+
+                queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            }
+            _ => self.num_indices = 0,
+        }
+    }
+}
+
+impl Draw for CrackOverlay {
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, uniforms: &'a wgpu::BindGroup) -> Result<()> {
+        if self.num_indices > 0 {
+            render_pass.set_pipeline(&self.render_pipeline);
+
+            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+            render_pass.set_bind_group(1, uniforms, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..self.num_indices as u32, 0, 0..1);
+        }
+        Ok(())
+    }
+}
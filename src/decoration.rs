@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{biome::*, block_registry::*, chunk::*};
+
+/// A block placed by a decoration, in the coordinates of the chunk it lands in.
+#[derive(Copy, Clone, Debug)]
+pub struct BlockWrite {
+    pub position: [usize; 3],
+    pub block_type: BlockType,
+}
+
+/// Deterministic hash of a column (or chunk) position, `salt` gives independent values for the same position.
+pub fn position_hash(seed: u32, x: i32, z: i32, salt: u32) -> u64 {
+    // splitmix64 finalizer
+    let mut hash = ((seed as u64) << 32 | salt as u64) ^ ((x as u32 as u64) << 32 | z as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Deterministic value in [0, 1) for a column.
+fn column_random(seed: u32, world_x: i32, world_z: i32, salt: u32) -> f64 {
+    (position_hash(seed, world_x, world_z, salt) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Decorations only grow into air and plants, trunks also go through leaves.
+fn can_replace(existing: BlockType, new: BlockType) -> bool {
+    if existing == BlockType::WATER {
+        return false;
+    }
+    if !existing.is_solid() {
+        return true;
+    }
+    existing.is_transparent() && !new.is_transparent()
+}
+
+fn write_block(blocks: &Blocks, block_write: BlockWrite) {
+    let [x, y, z] = block_write.position;
+    let mut block = blocks[y][x][z].lock().unwrap();
+    if can_replace(block.material_type, block_write.block_type) {
+        block.update(block_write.block_type);
+    }
+}
+
+/// Places the decorations of a chunk, writing directly into `blocks` whatever fits in the chunk.
+struct Decorator<'a> {
+    blocks: &'a Blocks,
+    offset: [i32; 3],
+    world_height: usize,
+    /// Writes landing in other chunks, with the offset of the chunk they belong to.
+    overflow: Vec<([i32; 3], BlockWrite)>,
+}
+
+impl<'a> Decorator<'a> {
+    /// Sets a block at a position relative to the chunk, which may be outside of it.
+    fn set(&mut self, x: i32, y: i32, z: i32, block_type: BlockType) {
+        if y < 0 || y >= self.world_height as i32 {
+            return;
+        }
+        let chunk_x = x.div_euclid(CHUNK_X_SIZE as i32);
+        let chunk_z = z.div_euclid(CHUNK_Z_SIZE as i32);
+        let block_write = BlockWrite {
+            position: [
+                x.rem_euclid(CHUNK_X_SIZE as i32) as usize,
+                y as usize,
+                z.rem_euclid(CHUNK_Z_SIZE as i32) as usize,
+            ],
+            block_type,
+        };
+
+        if chunk_x == 0 && chunk_z == 0 {
+            write_block(self.blocks, block_write);
+        } else {
+            let target = [self.offset[0] + chunk_x, self.offset[1], self.offset[2] + chunk_z];
+            self.overflow.push((target, block_write));
+        }
+    }
+
+    fn oak_tree(&mut self, x: i32, y: i32, z: i32, random: impl Fn(i32, i32, u32) -> f64) {
+        let height = 4 + (random(x, z, 2) * 3.0) as i32;
+        for dy in height - 2..=height + 1 {
+            let radius: i32 = if dy < height { 2 } else { 1 };
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    // Round off the canopy by dropping some corners
+                    let corner = dx.abs() == radius && dz.abs() == radius;
+                    if corner && (dy == height + 1 || random(x + dx, z + dz, 3 + dy as u32) < 0.5) {
+                        continue;
+                    }
+                    self.set(x + dx, y + dy, z + dz, BlockType::LEAVES);
+                }
+            }
+        }
+        for dy in 0..height {
+            self.set(x, y + dy, z, BlockType::LOG);
+        }
+    }
+
+    fn spruce_tree(&mut self, x: i32, y: i32, z: i32, random: impl Fn(i32, i32, u32) -> f64) {
+        let height = 6 + (random(x, z, 2) * 3.0) as i32;
+        for dy in 2..=height + 1 {
+            // Alternating wide and narrow layers, narrowing to a single leaf at the tip
+            let radius: i32 = if dy > height {
+                0
+            } else if dy + 2 >= height || (height - dy) % 2 == 0 {
+                1
+            } else {
+                2
+            };
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    if radius > 0 && dx.abs() == radius && dz.abs() == radius {
+                        continue;
+                    }
+                    self.set(x + dx, y + dy, z + dz, BlockType::SPRUCE_LEAVES);
+                }
+            }
+        }
+        for dy in 0..height {
+            self.set(x, y + dy, z, BlockType::SPRUCE_LOG);
+        }
+    }
+}
+
+/// Grows trees, grass and flowers on the surface of a generated chunk.
+///
+/// Placement only depends on the seed, the world position and the terrain. Blocks landing in
+/// neighbouring chunks are returned with the offset of their chunk, see `DeferredWrites`.
+pub fn decorate(blocks: &Blocks, offset: [i32; 3], seed: u32, biome_map: &BiomeMap, world_height: usize) -> Vec<([i32; 3], BlockWrite)> {
+    let mut decorator = Decorator {
+        blocks,
+        offset,
+        world_height,
+        overflow: Vec::new(),
+    };
+    let chunk_x = offset[0] * CHUNK_X_SIZE as i32;
+    let chunk_z = offset[2] * CHUNK_Z_SIZE as i32;
+    let random = move |x: i32, z: i32, salt: u32| column_random(seed, chunk_x + x, chunk_z + z, salt);
+
+    for x in 0..CHUNK_X_SIZE as i32 {
+        for z in 0..CHUNK_Z_SIZE as i32 {
+            let surface_y = (1..world_height - 1)
+                .rev()
+                .find(|y| blocks[*y][x as usize][z as usize].lock().unwrap().material_type != BlockType::AIR);
+            let surface_y = match surface_y {
+                Some(surface_y) => surface_y,
+                None => continue,
+            };
+            let surface = blocks[surface_y][x as usize][z as usize].lock().unwrap().material_type;
+            let y = surface_y as i32 + 1;
+
+            let roll = random(x, z, 0);
+            let flower = if random(x, z, 1) < 0.5 { BlockType::ROSE } else { BlockType::DANDELION };
+            match (biome_map.biome_at(chunk_x + x, chunk_z + z), surface) {
+                (Biome::FOREST, BlockType::GRASS) => {
+                    if roll < 0.06 {
+                        decorator.oak_tree(x, y, z, random);
+                    } else if roll < 0.35 {
+                        decorator.set(x, y, z, BlockType::TALL_GRASS);
+                    } else if roll < 0.4 {
+                        decorator.set(x, y, z, flower);
+                    }
+                }
+                (Biome::PLAINS, BlockType::GRASS) => {
+                    if roll < 0.004 {
+                        decorator.oak_tree(x, y, z, random);
+                    } else if roll < 0.2 {
+                        decorator.set(x, y, z, BlockType::TALL_GRASS);
+                    } else if roll < 0.25 {
+                        decorator.set(x, y, z, flower);
+                    }
+                }
+                (Biome::SNOW, BlockType::SNOW) if roll < 0.03 => decorator.spruce_tree(x, y, z, random),
+                (Biome::DESERT, BlockType::SAND) if roll < 0.01 => decorator.set(x, y, z, BlockType::DEAD_BUSH),
+                _ => {}
+            }
+        }
+    }
+
+    decorator.overflow
+}
+
+/// Chunks around the loaded window whose deferred writes are kept after they unload.
+const KEPT_WRITES_MARGIN: i32 = 2;
+
+/// Decoration blocks that crossed a chunk border, kept per target chunk.
+///
+/// Writes are applied when their target chunk is generated, or right away if it is already
+/// loaded. They are kept afterwards since unloaded chunks are generated again from scratch when
+/// they come back in view, until both chunks are far from the loaded window.
+#[derive(Default)]
+pub struct DeferredWrites {
+    /// Target chunk offset -> source chunk offset -> writes, sources are ordered so writes are
+    /// always applied in the same order.
+    writes: HashMap<[i32; 3], BTreeMap<[i32; 3], Vec<BlockWrite>>>,
+}
+
+impl DeferredWrites {
+    /// Stores the overflowing writes of the chunk at `source`, replacing the ones from a previous
+    /// generation. Returns the chunks they target.
+    pub fn insert(&mut self, source: [i32; 3], overflow: Vec<([i32; 3], BlockWrite)>) -> Vec<[i32; 3]> {
+        let mut writes_by_target: HashMap<[i32; 3], Vec<BlockWrite>> = HashMap::new();
+        for (target, block_write) in overflow {
+            writes_by_target.entry(target).or_default().push(block_write);
+        }
+
+        let targets = writes_by_target.keys().cloned().collect();
+        for (target, writes) in writes_by_target {
+            self.writes.entry(target).or_default().insert(source, writes);
+        }
+        targets
+    }
+
+    /// Drops the writes whose target and sources are more than `KEPT_WRITES_MARGIN` chunks away
+    /// from the loaded window, from `min` to `max` included. A source stores its writes again when
+    /// it is generated.
+    pub fn evict(&mut self, min: [i32; 3], max: [i32; 3]) {
+        let near = |offset: &[i32; 3]| {
+            offset[0] >= min[0] - KEPT_WRITES_MARGIN
+                && offset[0] <= max[0] + KEPT_WRITES_MARGIN
+                && offset[2] >= min[2] - KEPT_WRITES_MARGIN
+                && offset[2] <= max[2] + KEPT_WRITES_MARGIN
+        };
+        self.writes.retain(|target, writes_by_source| near(target) || writes_by_source.keys().any(near));
+    }
+
+    pub fn apply(&self, target: [i32; 3], blocks: &Blocks) {
+        if let Some(writes_by_source) = self.writes.get(&target) {
+            for block_write in writes_by_source.values().flatten() {
+                write_block(blocks, *block_write);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overflow(target: [i32; 3]) -> Vec<([i32; 3], BlockWrite)> {
+        let block_write = BlockWrite {
+            position: [0, 10, 0],
+            block_type: BlockType::LEAVES,
+        };
+        vec![(target, block_write)]
+    }
+
+    #[test]
+    fn writes_far_from_the_loaded_window_are_evicted() {
+        let mut deferred_writes = DeferredWrites::default();
+        deferred_writes.insert([0, 0, 0], overflow([1, 0, 0]));
+        deferred_writes.insert([20, 0, 0], overflow([21, 0, 0]));
+        deferred_writes.insert([-13, 0, 5], overflow([-12, 0, 5]));
+
+        // Window from -10 to -1, the first and last writes are within the margin
+        deferred_writes.evict([-10, 0, -5], [-1, 0, 4]);
+        let mut targets: Vec<[i32; 3]> = deferred_writes.writes.keys().cloned().collect();
+        targets.sort_unstable();
+        assert_eq!(targets, vec![[-12, 0, 5], [1, 0, 0]]);
+
+        deferred_writes.evict([100, 0, 100], [109, 0, 109]);
+        assert!(deferred_writes.writes.is_empty());
+    }
+
+    #[test]
+    fn writes_are_kept_while_their_source_is_near() {
+        let mut deferred_writes = DeferredWrites::default();
+        deferred_writes.insert([1, 0, 0], overflow([2, 0, 0]));
+        deferred_writes.evict([-10, 0, 0], [-1, 0, 9]);
+        assert!(deferred_writes.writes.contains_key(&[2, 0, 0]));
+    }
+}
@@ -2,7 +2,11 @@ use anyhow::*;
 use cgmath::EuclideanSpace;
 use winit::event::*;
 
-use crate::{camera::*, chunk::*, coordinate::*, crosshair::*, framerate::Framerate, overlay_info::*, player::*, renderer::*, world::*, Config};
+use crate::{
+    atlas::TexturePack, block_selection::*, camera::*, coordinate::*, crack_overlay::*, crosshair::*, explosion::EXPLOSION_POWER, framerate::Framerate,
+    overlay_info::*, physics::move_aabb, player::*, renderer::*, spawn::*, tick::TickStats, world::*, world_generation::WorldGenerator,
+    world_metadata::WorldMetadata, Config,
+};
 
 #[allow(dead_code)]
 pub struct Engine {
@@ -13,16 +17,34 @@ pub struct Engine {
     coordinate: Coordinate,
     overlay_info: OverlayInfo,
     crosshair: Crosshair,
+    block_selection: BlockSelection,
+    crack_overlay: CrackOverlay,
     player: Player,
     world: World,
+    spawn_point: [i32; 3],
+    tick_stats: TickStats,
 
     logger: slog::Logger,
     config: Config,
 }
 
 impl Engine {
-    pub fn new(logger: slog::Logger, config: Config, renderer: Renderer) -> Result<Self> {
-        let camera = Camera::new(&renderer, (0.0, SEA_LEVEL as f32 + 5.0, 0.0), cgmath::Deg(90.0), cgmath::Deg(-20.0));
+    pub fn new(
+        logger: slog::Logger,
+        config: Config,
+        renderer: Renderer,
+        texture_pack: &TexturePack,
+        generator: Box<dyn WorldGenerator>,
+        metadata: &WorldMetadata,
+        spawn_point: [i32; 3],
+    ) -> Result<Self> {
+        let camera = Camera::new(
+            &renderer,
+            spawn_camera_position(spawn_point),
+            cgmath::Deg(90.0),
+            cgmath::Deg(-20.0),
+            metadata.settings.world_height,
+        );
         let coordinate = Coordinate::new(
             &renderer.device,
             &renderer.sc_desc,
@@ -33,11 +55,22 @@ impl Engine {
         let overlay_info = OverlayInfo::new(&renderer)?;
         let player = Player::new(&camera);
         let crosshair = Crosshair::new(&renderer);
+        let block_selection = BlockSelection::new(&renderer.device, &renderer.sc_desc, &camera.uniform_bind_group_layout)?;
 
         info!(logger, "Generating World...");
-        let world = World::new(logger.clone(), config, &renderer, &camera.uniform_bind_group_layout)?;
+        let world = World::new(
+            logger.clone(),
+            config,
+            &renderer,
+            &camera.uniform_bind_group_layout,
+            texture_pack,
+            generator,
+            metadata,
+        )?;
         info!(logger, "World Generated!");
 
+        let crack_overlay = CrackOverlay::new(&renderer, &world.atlas, &camera.uniform_bind_group_layout)?;
+
         Ok(Self {
             renderer,
             framerate: Framerate::new(),
@@ -46,8 +79,12 @@ impl Engine {
             coordinate,
             overlay_info,
             crosshair,
+            block_selection,
+            crack_overlay,
             player,
             world,
+            spawn_point,
+            tick_stats: TickStats::new(),
 
             logger,
             config,
@@ -61,28 +98,132 @@ impl Engine {
         self.crosshair.resize(&self.renderer.queue, new_size);
     }
 
-    pub fn update(&mut self, dt: std::time::Duration) {
-        self.camera.update(&self.renderer.queue, dt);
+    /// Runs the world logic for one tick, at `TICKS_PER_SECOND` whatever the framerate.
+    pub fn tick(&mut self) {
+        let start = std::time::Instant::now();
+        self.player.tick(&mut self.world, &self.renderer.queue);
+        self.world.tick(&self.renderer.queue);
+        for block_type in self.world.tick_entities(&player_aabb(self.camera.position)) {
+            self.player.pick_up(block_type);
+        }
+        self.tick_stats.record(start.elapsed());
+    }
+
+    /// Updates what is drawn once per frame, `tick_alpha` being the fraction of a tick elapsed since
+    /// the last one.
+    pub fn update(&mut self, dt: std::time::Duration, tick_alpha: f32) {
+        // The camera moves as the player, stopping against blocks like the entities do
+        let previous_position = self.camera.position;
+        self.camera.update_camera_controller(dt);
+        let offset = self.camera.position - previous_position;
+        self.camera.position = previous_position + move_aabb(&self.world, player_aabb(previous_position), offset);
+        self.camera.update_uniforms(&self.renderer.queue);
         self.world.set_center(&self.renderer.queue, self.camera.position.to_vec());
+        let biome = self.world.biome_at(self.camera.position.to_vec());
         self.overlay_info
-            .update(&self.renderer.queue, self.framerate.current_fps, self.camera.position)
+            .update(&self.renderer.queue, self.framerate.current_fps, &self.tick_stats, self.camera.position, biome)
             .expect("Overlay update broke.");
         self.player.update2(&self.camera, &mut self.world);
+        self.world.update_moving(&self.renderer.queue, tick_alpha);
+        self.block_selection
+            .update(&self.renderer.queue, self.player.block_pos_in_view, self.player.block_face_direction_in_view);
+        self.crack_overlay.update(
+            &self.renderer.queue,
+            self.player.block_pos_in_view,
+            self.player.interpolated_break_progress(tick_alpha),
+        );
     }
 
     pub fn input(&mut self, event: &DeviceEvent) {
-        self.player.input(event, &self.renderer.queue, &mut self.world);
+        if let Some(pos) = self.player.input(event, &self.renderer.queue, &mut self.world) {
+            self.log_block_entity(pos);
+        }
         self.camera.input(event);
     }
 
+    fn log_block_entity(&self, pos: cgmath::Vector3<i32>) {
+        if let Some(description) = self.world.with_block_entity(pos, |block_entity| block_entity.describe()) {
+            info!(self.logger, "{}", description);
+        }
+    }
+
     pub fn input_keyboard(&mut self, event: &WindowEvent) -> bool {
+        // Typing on a sign, key releases still reach the camera so that it stops moving
+        if let Some(pos) = self.player.editing_sign {
+            match event {
+                WindowEvent::ReceivedCharacter(character) => {
+                    self.world.with_block_entity(pos, |block_entity| block_entity.type_character(*character));
+                    return true;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode,
+                            ..
+                        },
+                    ..
+                } => {
+                    if let Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::Escape) = virtual_keycode {
+                        self.player.editing_sign = None;
+                        self.log_block_entity(pos);
+                    }
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::R),
+                    ..
+                },
+            ..
+        } = event
+        {
+            // Back to the spawn point, chunks around it are loaded on the next update
+            self.camera.position = spawn_camera_position(self.spawn_point);
+            return true;
+        }
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::X),
+                    ..
+                },
+            ..
+        } = event
+        {
+            // Debug explosion at the block in view
+            if let Some(pos) = self.player.block_pos_in_view {
+                let destroyed = self.world.explode(pos, EXPLOSION_POWER, &self.renderer.queue);
+                info!(self.logger, "Explosion at {} {} {} destroyed {} blocks", pos.x, pos.y, pos.z, destroyed);
+            }
+            return true;
+        }
         self.camera.input_keyboard(event)
     }
 
+    /// Saves what is not written as the world changes, the entities and block entities of the
+    /// loaded chunks.
+    pub fn save(&self) -> Result<()> {
+        self.world.save()
+    }
+
     pub fn render(&mut self) -> Result<()> {
         self.framerate.fps();
-        self.renderer
-            .render(&self.camera, &self.world, &self.coordinate, &self.overlay_info, &self.crosshair)?;
+        self.renderer.render(
+            &self.camera,
+            &self.world,
+            &self.coordinate,
+            &self.block_selection,
+            &self.crack_overlay,
+            &self.overlay_info,
+            &self.crosshair,
+        )?;
 
         Ok(())
     }
@@ -0,0 +1,506 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::PathBuf;
+
+use anyhow::*;
+use cgmath::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{block::*, block_registry::*, block_updates::BlockGrid, chunk::*, decoration::position_hash, pathfinding::*, physics::*};
+
+/// Entities loaded at once, the vertex buffer is sized for them.
+pub const MAX_ENTITIES: usize = 256;
+/// Directory of a world holding one file of entities per saved chunk.
+pub const ENTITIES_DIR: &str = "entities";
+
+/// Fraction of the horizontal speed kept every tick on the ground.
+const GROUND_FRICTION: f32 = 0.6;
+/// Ticks before a dropped item disappears, 5 minutes.
+const ITEM_LIFETIME: u32 = 6000;
+/// Ticks before a dropped item can be picked up.
+const ITEM_PICKUP_DELAY: u32 = 10;
+/// Items closer than this to the box of the player are picked up.
+const ITEM_PICKUP_REACH: f32 = 1.0;
+const ITEM_SIZE: f32 = 0.25;
+const MOB_SIZE: [f32; 3] = [0.9, 1.3, 0.9];
+/// Walking speed of mobs, in blocks per tick.
+const MOB_SPEED: f32 = 0.05;
+/// Enough to climb a block.
+const MOB_JUMP_SPEED: f32 = 0.32;
+/// Chance per tick for an idle mob to start walking.
+const MOB_WANDER_CHANCE: f64 = 0.02;
+/// Blocks along each axis from a mob to the goals it picks.
+const MOB_WANDER_DISTANCE: i32 = 8;
+/// Ticks a mob may take per block of its path before giving up, when it is stuck.
+const MOB_TICKS_PER_BLOCK: u32 = 40;
+/// Horizontal distance at which a mob reached the center of a block of its path.
+const MOB_WAYPOINT_DISTANCE: f32 = 0.2;
+/// Chance for a newly generated chunk to hold a group of mobs.
+const MOB_SPAWN_CHANCE: f64 = 0.1;
+
+/// Serializes `Vector3<f32>` as `[x, y, z]`.
+mod vector_serde {
+    use cgmath::Vector3;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(vector: &Vector3<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+        [vector.x, vector.y, vector.z].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vector3<f32>, D::Error> {
+        let [x, y, z] = <[f32; 3]>::deserialize(deserializer)?;
+        Ok(Vector3::new(x, y, z))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EntityKind {
+    /// A broken block lying on the ground until the player picks it up.
+    ITEM(BlockType),
+    /// Passive mob wandering around.
+    MOB,
+}
+
+/// What an entity is drawn as: a box textured like a block.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderModel {
+    pub block_type: BlockType,
+    pub size: Vector3<f32>,
+}
+
+impl EntityKind {
+    /// Size of the collision box.
+    pub fn size(&self) -> Vector3<f32> {
+        match self {
+            EntityKind::ITEM(_) => Vector3::new(ITEM_SIZE, ITEM_SIZE, ITEM_SIZE),
+            EntityKind::MOB => MOB_SIZE.into(),
+        }
+    }
+
+    pub fn render_model(&self) -> RenderModel {
+        match self {
+            EntityKind::ITEM(block_type) => RenderModel {
+                block_type: *block_type,
+                size: self.size(),
+            },
+            // A woolly box
+            EntityKind::MOB => RenderModel {
+                block_type: BlockType::SNOW,
+                size: self.size(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    /// Center of the bottom of the collision box.
+    #[serde(with = "vector_serde")]
+    pub position: Vector3<f32>,
+    /// In blocks per tick.
+    #[serde(with = "vector_serde")]
+    pub velocity: Vector3<f32>,
+    /// Ticks since the entity spawned.
+    pub age: u32,
+    /// Blocks an item stands for, a whole stack drops as a single item.
+    #[serde(default = "default_count")]
+    pub count: u32,
+    /// Ticks left for a mob to reach the end of its path.
+    #[serde(default)]
+    wander_ticks: u32,
+    /// Blocks a mob walks through, the next one first. Paths are searched again after loading.
+    #[serde(skip)]
+    path: Vec<[i32; 3]>,
+    /// Goal of a mob waiting for its path to be searched.
+    #[serde(skip)]
+    goal: Option<[i32; 3]>,
+    /// Position before the last tick, to interpolate rendering between ticks.
+    #[serde(skip)]
+    previous_position: Option<Vector3<f32>>,
+    #[serde(skip)]
+    on_ground: bool,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl Entity {
+    pub fn new(kind: EntityKind, position: Vector3<f32>, velocity: Vector3<f32>) -> Self {
+        Self {
+            kind,
+            position,
+            velocity,
+            age: 0,
+            count: 1,
+            wander_ticks: 0,
+            path: Vec::new(),
+            goal: None,
+            previous_position: None,
+            on_ground: false,
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_feet(self.position, self.kind.size())
+    }
+
+    fn chunk_offset(&self) -> [i32; 3] {
+        [
+            (self.position.x / CHUNK_X_SIZE as f32).floor() as i32,
+            0,
+            (self.position.z / CHUNK_Z_SIZE as f32).floor() as i32,
+        ]
+    }
+
+    /// Block the feet are in.
+    fn block_position(&self) -> [i32; 3] {
+        [self.position.x.floor() as i32, self.position.y.floor() as i32, self.position.z.floor() as i32]
+    }
+
+    /// Random walkable block around a mob, on about the same level.
+    fn pick_goal(&self, grid: &impl BlockGrid, rng: &mut StdRng) -> Option<[i32; 3]> {
+        let position = self.block_position();
+        let x = position[0] + rng.gen_range(-MOB_WANDER_DISTANCE..=MOB_WANDER_DISTANCE);
+        let z = position[2] + rng.gen_range(-MOB_WANDER_DISTANCE..=MOB_WANDER_DISTANCE);
+        (position[1] - MAX_DROP..=position[1] + JUMP_HEIGHT)
+            .rev()
+            .map(|y| [x, y, z])
+            .find(|goal| is_walkable(grid, *goal))
+    }
+
+    fn wander(&mut self, grid: &impl BlockGrid, rng: &mut StdRng, pathfinder: &mut Pathfinder) {
+        if self.path.is_empty() && self.goal.is_none() && self.on_ground && rng.gen_bool(MOB_WANDER_CHANCE) {
+            self.goal = self.pick_goal(grid, rng);
+        }
+        if let Some(goal) = self.goal {
+            match pathfinder.find_path(grid, self.block_position(), goal) {
+                PathResult::FOUND(path) => {
+                    self.wander_ticks = path.len() as u32 * MOB_TICKS_PER_BLOCK;
+                    self.path = path;
+                    self.goal = None;
+                }
+                PathResult::UNREACHABLE => self.goal = None,
+                PathResult::DEFERRED => {}
+            }
+        }
+
+        if self.path.is_empty() {
+            return;
+        }
+        self.wander_ticks = self.wander_ticks.saturating_sub(1);
+        if self.wander_ticks == 0 {
+            self.path.clear();
+            return;
+        }
+        let next = self.path[0];
+        let offset = Vector2::new(next[0] as f32 + 0.5 - self.position.x, next[2] as f32 + 0.5 - self.position.z);
+        if offset.magnitude() < MOB_WAYPOINT_DISTANCE && self.position.y.floor() as i32 >= next[1] {
+            self.path.remove(0);
+            return;
+        }
+        let direction = offset.normalize();
+        self.velocity.x = direction.x * MOB_SPEED;
+        self.velocity.z = direction.y * MOB_SPEED;
+    }
+
+    /// Moves the entity by one tick, it collides with blocks like the player.
+    fn tick(&mut self, grid: &impl BlockGrid, rng: &mut StdRng, pathfinder: &mut Pathfinder) {
+        self.previous_position = Some(self.position);
+        self.age += 1;
+        if self.kind == EntityKind::MOB {
+            self.wander(grid, rng, pathfinder);
+        }
+
+        self.velocity.y = (self.velocity.y - GRAVITY) * DRAG;
+        let wanted = self.velocity;
+        let moved = move_aabb(grid, self.aabb(), wanted);
+        self.position += moved;
+
+        let blocked_horizontally = moved.x != wanted.x || moved.z != wanted.z;
+        self.on_ground = wanted.y < 0.0 && moved.y > wanted.y;
+        if moved.y != wanted.y {
+            self.velocity.y = 0.0;
+        }
+        if self.on_ground {
+            // The next block of the path is higher
+            if self.kind == EntityKind::MOB && blocked_horizontally && !self.path.is_empty() {
+                self.velocity.y = MOB_JUMP_SPEED;
+            }
+            self.velocity.x *= GROUND_FRICTION;
+            self.velocity.z *= GROUND_FRICTION;
+        }
+    }
+
+    fn interpolated_position(&self, tick_alpha: f32) -> Vector3<f32> {
+        match self.previous_position {
+            Some(previous_position) => previous_position.lerp(self.position, tick_alpha.min(1.0)),
+            None => self.position,
+        }
+    }
+}
+
+/// Every entity of the world, the ones of loaded chunks move and the others are kept per chunk,
+/// and in the world directory if there is one, until their chunk is loaded again.
+#[derive(Debug)]
+pub struct Entities {
+    entities: Vec<Entity>,
+    /// Entities of loaded chunks over `MAX_ENTITIES`, they come in as others go.
+    waiting: Vec<Entity>,
+    loaded_chunks: HashSet<[i32; 3]>,
+    /// Entities of unloaded chunks.
+    saved: HashMap<[i32; 3], Vec<Entity>>,
+    dir: Option<PathBuf>,
+    seed: u32,
+    rng: StdRng,
+    pathfinder: Pathfinder,
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Entities::new(0, None)
+    }
+}
+
+impl Entities {
+    /// Entities are saved in `world_dir` when it is set, they only live in memory otherwise.
+    pub fn new(seed: u32, world_dir: Option<PathBuf>) -> Self {
+        Self {
+            entities: Vec::new(),
+            waiting: Vec::new(),
+            loaded_chunks: HashSet::new(),
+            saved: HashMap::new(),
+            dir: world_dir.map(|dir| dir.join(ENTITIES_DIR)),
+            seed,
+            rng: StdRng::seed_from_u64(seed as u64),
+            pathfinder: Pathfinder::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Collision boxes of the loaded entities.
+    pub fn boxes(&self) -> Vec<Aabb> {
+        self.entities.iter().map(|entity| entity.aabb()).collect()
+    }
+
+    /// Forgets the paths of the mobs going next to a block that changed.
+    pub fn block_changed(&mut self, position: [i32; 3]) {
+        self.pathfinder.block_changed(position);
+    }
+
+    /// Entities over the limit wait, and are saved with their chunk, until there is room for them.
+    pub fn spawn(&mut self, entity: Entity) {
+        if self.entities.len() < MAX_ENTITIES {
+            self.entities.push(entity);
+        } else {
+            self.waiting.push(entity);
+        }
+    }
+
+    /// Drops `count` blocks of `block_type` as one item in the middle of the block at `position`.
+    pub fn spawn_item(&mut self, block_type: BlockType, count: u32, position: [i32; 3]) {
+        let center = Vector3::new(position[0] as f32 + 0.5, position[1] as f32 + 0.25, position[2] as f32 + 0.5);
+        let velocity = Vector3::new(self.rng.gen_range(-0.05..0.05), 0.15, self.rng.gen_range(-0.05..0.05));
+        let mut item = Entity::new(EntityKind::ITEM(block_type), center, velocity);
+        item.count = count;
+        self.spawn(item);
+    }
+
+    fn chunk_path(&self, chunk_offset: [i32; 3]) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(format!("{}_{}.ron", chunk_offset[0], chunk_offset[2])))
+    }
+
+    /// Entities saved for an unloaded chunk, read from the world directory the first time.
+    fn saved_chunk(&mut self, chunk_offset: [i32; 3]) -> Result<Option<&mut Vec<Entity>>> {
+        if !self.saved.contains_key(&chunk_offset) {
+            let path = match self.chunk_path(chunk_offset) {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(None),
+            };
+            let content = read_to_string(&path).with_context(|| format!("Error could not read entities '{}'", path.display()))?;
+            let entities: Vec<Entity> = ron::de::from_str(&content).with_context(|| format!("Error invalid entities '{}'", path.display()))?;
+            self.saved.insert(chunk_offset, entities);
+        }
+        Ok(self.saved.get_mut(&chunk_offset))
+    }
+
+    fn write_chunk(&self, chunk_offset: [i32; 3], entities: &[&Entity]) -> Result<()> {
+        let (dir, path) = match (&self.dir, self.chunk_path(chunk_offset)) {
+            (Some(dir), Some(path)) => (dir, path),
+            _ => return Ok(()),
+        };
+        create_dir_all(dir).with_context(|| format!("Error could not create entities directory '{}'", dir.display()))?;
+        let content = ron::ser::to_string_pretty(&entities, ron::ser::PrettyConfig::default())?;
+        write(&path, content).with_context(|| format!("Error could not write entities '{}'", path.display()))
+    }
+
+    /// Brings back the entities saved with a chunk that was loaded, or spawns mobs on the grass of a
+    /// chunk loaded for the first time.
+    pub fn load_chunk(&mut self, grid: &impl BlockGrid, chunk_offset: [i32; 3]) -> Result<()> {
+        if !self.loaded_chunks.insert(chunk_offset) {
+            return Ok(());
+        }
+        if self.saved_chunk(chunk_offset)?.is_some() {
+            let entities = self.saved.remove(&chunk_offset).unwrap_or_default();
+            for entity in entities {
+                self.spawn(entity);
+            }
+            return Ok(());
+        }
+
+        let mut rng = StdRng::seed_from_u64(position_hash(self.seed, chunk_offset[0], chunk_offset[2], 500));
+        if !rng.gen_bool(MOB_SPAWN_CHANCE) {
+            return Ok(());
+        }
+        for _ in 0..rng.gen_range(1..=3) {
+            let x = chunk_offset[0] * CHUNK_X_SIZE as i32 + rng.gen_range(0..CHUNK_X_SIZE as i32);
+            let z = chunk_offset[2] * CHUNK_Z_SIZE as i32 + rng.gen_range(0..CHUNK_Z_SIZE as i32);
+            let ground = (0..grid.world_height() as i32).rev().find(|y| match grid.block_type([x, *y, z]) {
+                Some(block_type) => block_type != BlockType::AIR,
+                None => false,
+            });
+            if let Some(y) = ground {
+                if grid.block_type([x, y, z]) == Some(BlockType::GRASS) {
+                    let position = Vector3::new(x as f32 + 0.5, y as f32 + 1.0, z as f32 + 0.5);
+                    self.spawn(Entity::new(EntityKind::MOB, position, Vector3::zero()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the entities of the chunks that are no longer loaded, along with the ones that walked
+    /// out of the loaded chunks.
+    pub fn unload_chunks(&mut self, loaded_chunk_offsets: &[[i32; 3]]) -> Result<()> {
+        let loaded: HashSet<[i32; 3]> = loaded_chunk_offsets.iter().cloned().collect();
+        let unloaded_chunks: Vec<[i32; 3]> = self.loaded_chunks.difference(&loaded).cloned().collect();
+        let (staying, mut leaving): (Vec<Entity>, Vec<Entity>) = self.entities.drain(..).partition(|entity| loaded.contains(&entity.chunk_offset()));
+        self.entities = staying;
+        let (waiting, waiting_leaving): (Vec<Entity>, Vec<Entity>) = self.waiting.drain(..).partition(|entity| loaded.contains(&entity.chunk_offset()));
+        self.waiting = waiting;
+        leaving.extend(waiting_leaving);
+
+        let mut chunks: HashMap<[i32; 3], Vec<Entity>> = unloaded_chunks.iter().map(|offset| (*offset, Vec::new())).collect();
+        for entity in leaving {
+            chunks.entry(entity.chunk_offset()).or_default().push(entity);
+        }
+        for offset in unloaded_chunks {
+            self.loaded_chunks.remove(&offset);
+        }
+
+        let mut chunk_offsets: Vec<[i32; 3]> = chunks.keys().cloned().collect();
+        chunk_offsets.sort_unstable();
+        for chunk_offset in chunk_offsets {
+            let entities = chunks.remove(&chunk_offset).unwrap_or_default();
+            // Entities walking into a chunk that was never loaded
+            if let Some(saved) = self.saved_chunk(chunk_offset)? {
+                saved.extend(entities);
+            } else {
+                self.saved.insert(chunk_offset, entities);
+            }
+            let saved = &self.saved[&chunk_offset];
+            self.write_chunk(chunk_offset, &saved.iter().collect::<Vec<&Entity>>())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the entities of the loaded chunks to the world directory.
+    pub fn save(&self) -> Result<()> {
+        let mut chunk_offsets: Vec<[i32; 3]> = self.loaded_chunks.iter().cloned().collect();
+        chunk_offsets.sort_unstable();
+        for chunk_offset in chunk_offsets {
+            let entities: Vec<&Entity> = self
+                .entities
+                .iter()
+                .chain(self.waiting.iter())
+                .filter(|entity| entity.chunk_offset() == chunk_offset)
+                .collect();
+            self.write_chunk(chunk_offset, &entities)?;
+        }
+        Ok(())
+    }
+
+    /// Moves every entity by one tick and returns the items picked up by the player.
+    pub fn tick(&mut self, grid: &impl BlockGrid, player: &Aabb) -> Vec<BlockType> {
+        let pickup_area = player.grow(ITEM_PICKUP_REACH);
+        let mut picked_up = Vec::new();
+        let mut entities = Vec::with_capacity(self.entities.len());
+        self.pathfinder.tick();
+        for mut entity in self.entities.drain(..) {
+            entity.tick(grid, &mut self.rng, &mut self.pathfinder);
+            if let EntityKind::ITEM(block_type) = entity.kind {
+                if entity.age >= ITEM_LIFETIME {
+                    continue;
+                }
+                if entity.age >= ITEM_PICKUP_DELAY && entity.aabb().intersects(&pickup_area) {
+                    picked_up.push(block_type);
+                    continue;
+                }
+            }
+            entities.push(entity);
+        }
+        self.entities = entities;
+
+        let room = MAX_ENTITIES.saturating_sub(self.entities.len()).min(self.waiting.len());
+        self.entities.extend(self.waiting.drain(..room));
+        picked_up
+    }
+
+    /// Vertices of the render model of every entity, interpolated between the last two ticks.
+    pub fn vertices(&self, tick_alpha: f32) -> Vec<BlockVertex> {
+        self.entities
+            .iter()
+            .flat_map(|entity| {
+                let model = entity.kind.render_model();
+                let position = entity.interpolated_position(tick_alpha);
+                let corner = Vector3::new(position.x - model.size.x / 2.0, position.y, position.z - model.size.z / 2.0);
+                cube_vertices(model.block_type, corner, model.size)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn item(x: f32) -> Entity {
+        Entity::new(EntityKind::ITEM(BlockType::DIRT), Vector3::new(x, 1.0, 0.5), Vector3::zero())
+    }
+
+    #[test]
+    fn entities_over_the_limit_are_kept() {
+        let mut grid = MapGrid::new([0, 0, 0], [31, 3, 15]);
+        grid.fill([0, 0, 0], [31, 0, 15], BlockType::ROCK);
+        let mut entities = Entities::new(0, None);
+        entities.load_chunk(&grid, [0, 0, 0]).unwrap();
+        entities.load_chunk(&grid, [1, 0, 0]).unwrap();
+        entities.entities.clear();
+        for i in 0..MAX_ENTITIES + 10 {
+            entities.spawn(item(if i < 20 { 20.5 } else { 0.5 }));
+        }
+        assert_eq!(entities.entities.len(), MAX_ENTITIES);
+
+        // Entities of an unloaded chunk make room for the waiting ones
+        entities.unload_chunks(&[[0, 0, 0]]).unwrap();
+        assert_eq!(entities.saved[&[1, 0, 0]].len(), 20);
+        entities.tick(&grid, &Aabb::from_feet(Vector3::new(0.0, 50.0, 0.0), Vector3::new(1.0, 1.0, 1.0)));
+        assert_eq!(entities.entities.len(), MAX_ENTITIES - 10);
+        assert!(entities.waiting.is_empty());
+
+        // The ones still waiting are saved with their chunk
+        for _ in 0..20 {
+            entities.spawn(item(0.5));
+        }
+        entities.unload_chunks(&[]).unwrap();
+        assert_eq!(entities.saved[&[0, 0, 0]].len(), MAX_ENTITIES + 10);
+        entities.load_chunk(&grid, [0, 0, 0]).unwrap();
+        assert_eq!(entities.entities.len() + entities.waiting.len(), MAX_ENTITIES + 10);
+    }
+}
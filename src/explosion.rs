@@ -0,0 +1,248 @@
+use std::collections::{BTreeSet, HashSet};
+
+use cgmath::*;
+
+use crate::{block_registry::*, block_updates::BlockGrid};
+
+/// Power of the explosion of an explosive block, it reaches about 5 blocks through air.
+pub const EXPLOSION_POWER: f32 = 4.0;
+/// Ticks between lighting an explosive block and its explosion.
+pub const FUSE_TICKS: u64 = 80;
+/// Ticks before an explosive block caught in an explosion goes off.
+pub const CHAIN_FUSE_TICKS: u64 = 10;
+/// Distance between two points of a ray, in blocks.
+const RAY_STEP: f32 = 0.3;
+/// Rays go towards the points on the surface of a cube with this many points per edge.
+const RAYS_PER_EDGE: usize = 16;
+/// Intensity a ray loses per step, on top of the resistance of the blocks it goes through.
+const RAY_DECAY: f32 = 0.75 * RAY_STEP;
+
+/// Blocks an explosion reaches.
+#[derive(Debug, Default)]
+pub struct Blast {
+    /// Blocks removed by the explosion, in position order.
+    pub destroyed: Vec<[i32; 3]>,
+    /// Explosive blocks the explosion lights instead of removing them.
+    pub ignited: Vec<[i32; 3]>,
+}
+
+/// Directions of the rays of an explosion, towards the surface of a cube around its center.
+fn ray_directions() -> Vec<Vector3<f32>> {
+    let last = (RAYS_PER_EDGE - 1) as f32;
+    let mut directions = Vec::new();
+    for x in 0..RAYS_PER_EDGE {
+        for y in 0..RAYS_PER_EDGE {
+            for z in 0..RAYS_PER_EDGE {
+                let on_surface = [x, y, z].iter().any(|i| *i == 0 || *i == RAYS_PER_EDGE - 1);
+                if on_surface {
+                    let point = Vector3::new(x as f32, y as f32, z as f32) / last * 2.0 - Vector3::new(1.0, 1.0, 1.0);
+                    directions.push(point.normalize());
+                }
+            }
+        }
+    }
+    directions
+}
+
+/// Blocks destroyed by an explosion of `power` at `center`.
+///
+/// Rays leave the center in every direction, losing intensity with distance and with the blast
+/// resistance of the blocks they cross, which makes a sphere of radius about `power / RAY_DECAY *
+/// RAY_STEP` in air. A block is reached while the ray still has some intensity. Rays stop at
+/// blocks that cannot be destroyed and at the edge of the loaded world.
+pub fn blast(grid: &impl BlockGrid, center: Vector3<f32>, power: f32) -> Blast {
+    let mut destroyed = HashSet::new();
+    let mut ignited = HashSet::new();
+    for direction in ray_directions() {
+        let mut point = center;
+        let mut intensity = power;
+        while intensity > 0.0 {
+            let position = [point.x.floor() as i32, point.y.floor() as i32, point.z.floor() as i32];
+            match grid.block_type(position) {
+                None => break,
+                Some(BlockType::AIR) => {}
+                Some(block_type) => {
+                    let resistance = match block_type.blast_resistance() {
+                        Some(resistance) => resistance,
+                        None => break,
+                    };
+                    intensity -= (resistance + RAY_STEP) * RAY_STEP;
+                    if intensity > 0.0 {
+                        if block_type.behaviour() == BlockBehaviour::EXPLOSIVE {
+                            ignited.insert(position);
+                        } else {
+                            destroyed.insert(position);
+                        }
+                    }
+                }
+            }
+            point += direction * RAY_STEP;
+            intensity -= RAY_DECAY;
+        }
+    }
+
+    let mut destroyed: Vec<[i32; 3]> = destroyed.into_iter().collect();
+    destroyed.sort_unstable();
+    let mut ignited: Vec<[i32; 3]> = ignited.into_iter().collect();
+    ignited.sort_unstable();
+    Blast { destroyed, ignited }
+}
+
+/// Lit explosive blocks waiting for their explosion.
+#[derive(Debug, Default)]
+pub struct Fuses {
+    tick: u64,
+    /// (tick, position) of the pending explosions.
+    lit: BTreeSet<(u64, [i32; 3])>,
+}
+
+impl Fuses {
+    /// Lights the block at `position`, which explodes in `delay` ticks. A block already lit keeps
+    /// the fuse that burns out first.
+    pub fn ignite(&mut self, position: [i32; 3], delay: u64) {
+        let tick = self.tick + delay.max(1);
+        if let Some(&(lit_tick, _)) = self.lit.iter().find(|(_, lit_position)| *lit_position == position) {
+            if lit_tick <= tick {
+                return;
+            }
+            self.lit.remove(&(lit_tick, position));
+        }
+        self.lit.insert((tick, position));
+    }
+
+    /// Advances by one tick and returns the positions whose fuse burnt out, in position order.
+    pub fn tick(&mut self) -> Vec<[i32; 3]> {
+        self.tick += 1;
+        let mut due = Vec::new();
+        while let Some(&(tick, position)) = self.lit.iter().next() {
+            if tick > self.tick {
+                break;
+            }
+            self.lit.remove(&(tick, position));
+            due.push(position);
+        }
+        due.sort_unstable();
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const CENTER: [i32; 3] = [0, 8, 0];
+
+    fn grid() -> MapGrid {
+        MapGrid::new([-16, 0, -16], [16, 16, 16])
+    }
+
+    fn center() -> Vector3<f32> {
+        Vector3::new(CENTER[0] as f32 + 0.5, CENTER[1] as f32 + 0.5, CENTER[2] as f32 + 0.5)
+    }
+
+    fn offset(position: [i32; 3], offset: [i32; 3]) -> [i32; 3] {
+        [position[0] + offset[0], position[1] + offset[1], position[2] + offset[2]]
+    }
+
+    fn tnt() -> BlockType {
+        BlockRegistry::get().block_type("tnt").unwrap()
+    }
+
+    /// Whether a blast at the center destroys a dirt block at `target` from it, with `blocks` around.
+    fn destroys(blocks: &[([i32; 3], BlockType)], target: [i32; 3]) -> bool {
+        let mut grid = grid();
+        for (position, block_type) in blocks.iter() {
+            grid.set(offset(CENTER, *position), *block_type);
+        }
+        grid.set(offset(CENTER, target), BlockType::DIRT);
+        blast(&grid, center(), EXPLOSION_POWER).destroyed.contains(&offset(CENTER, target))
+    }
+
+    #[test]
+    fn blasts_in_air_reach_about_five_blocks() {
+        let directions = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+        for direction in directions.iter() {
+            for distance in 1..=8 {
+                let target = [direction[0] * distance, direction[1] * distance, direction[2] * distance];
+                assert_eq!(destroys(&[], target), distance <= 5, "{:?}", target);
+            }
+        }
+        assert!(destroys(&[], [3, 0, 3]));
+        assert!(destroys(&[], [-2, 2, 2]));
+        assert!(!destroys(&[], [4, 0, 4]));
+        assert!(!destroys(&[], [4, 4, -4]));
+    }
+
+    #[test]
+    fn blasts_destroy_the_blocks_around_them() {
+        let mut grid = grid();
+        grid.fill([-3, 5, -3], [3, 11, 3], BlockType::DIRT);
+        let destroyed = blast(&grid, center(), EXPLOSION_POWER).destroyed;
+        assert!(destroyed.contains(&CENTER));
+        assert!(destroyed.contains(&offset(CENTER, [1, 1, 1])));
+        assert!(!destroyed.contains(&offset(CENTER, [3, 3, 3])));
+        assert!(destroyed.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn indestructible_blocks_stop_the_rays() {
+        assert!(destroys(&[], [3, 0, 0]));
+        assert!(!destroys(&[([2, 0, 0], BlockType::BEDROCK)], [3, 0, 0]));
+
+        let mut grid = grid();
+        grid.set(offset(CENTER, [2, 0, 0]), BlockType::BEDROCK);
+        let blast = blast(&grid, center(), EXPLOSION_POWER);
+        assert!(blast.destroyed.is_empty());
+        assert!(blast.ignited.is_empty());
+    }
+
+    #[test]
+    fn water_shields_the_blocks_behind_it() {
+        assert!(!destroys(&[([2, 0, 0], BlockType::WATER)], [3, 0, 0]));
+        assert!(!destroys(&[([0, -1, 0], BlockType::WATER)], [0, -2, 0]));
+
+        let mut grid = grid();
+        grid.set(offset(CENTER, [2, 0, 0]), BlockType::WATER);
+        assert!(!blast(&grid, center(), EXPLOSION_POWER).destroyed.contains(&offset(CENTER, [2, 0, 0])));
+    }
+
+    #[test]
+    fn explosives_are_ignited_instead_of_destroyed() {
+        let mut grid = grid();
+        grid.set(offset(CENTER, [2, 0, 0]), tnt());
+        grid.set(offset(CENTER, [0, 0, -3]), tnt());
+        grid.set(offset(CENTER, [0, 0, 8]), tnt());
+        let blast = blast(&grid, center(), EXPLOSION_POWER);
+        assert!(blast.destroyed.is_empty());
+        assert_eq!(blast.ignited, vec![offset(CENTER, [0, 0, -3]), offset(CENTER, [2, 0, 0])]);
+    }
+
+    #[test]
+    fn fuses_burn_out_in_position_order() {
+        let mut fuses = Fuses::default();
+        fuses.ignite([5, 0, 0], 3);
+        fuses.ignite([1, 0, 0], 3);
+        fuses.ignite([2, 0, 0], 1);
+        fuses.ignite([0, 0, 0], 0);
+        assert_eq!(fuses.tick(), vec![[0, 0, 0], [2, 0, 0]]);
+        assert!(fuses.tick().is_empty());
+        assert_eq!(fuses.tick(), vec![[1, 0, 0], [5, 0, 0]]);
+        assert!(fuses.tick().is_empty());
+    }
+
+    #[test]
+    fn relighting_keeps_the_earlier_fuse() {
+        let mut fuses = Fuses::default();
+        fuses.ignite([1, 0, 0], 2);
+        fuses.ignite([1, 0, 0], 10);
+        fuses.ignite([2, 0, 0], 10);
+        fuses.ignite([2, 0, 0], 3);
+        assert!(fuses.tick().is_empty());
+        assert_eq!(fuses.tick(), vec![[1, 0, 0]]);
+        assert_eq!(fuses.tick(), vec![[2, 0, 0]]);
+        for _ in 0..20 {
+            assert!(fuses.tick().is_empty());
+        }
+    }
+}
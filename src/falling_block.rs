@@ -0,0 +1,222 @@
+use cgmath::*;
+
+use crate::{block::*, block_registry::*, block_updates::BlockGrid, physics::*};
+
+/// Falling blocks drawn at once, the vertex buffer is sized for them.
+pub const MAX_FALLING_BLOCKS: usize = 256;
+
+/// A block detached from the world, falling straight down until it lands.
+#[derive(Debug, Clone, Copy)]
+pub struct FallingBlock {
+    pub block_type: BlockType,
+    /// Lowest corner of the block.
+    pub position: Vector3<f32>,
+    /// Position before the last tick, to interpolate rendering between ticks.
+    previous_position: Vector3<f32>,
+    /// Vertical speed in blocks per tick, negative when falling.
+    velocity: f32,
+}
+
+impl FallingBlock {
+    fn new(block_type: BlockType, position: [i32; 3]) -> Self {
+        let position = Vector3::new(position[0] as f32, position[1] as f32, position[2] as f32);
+        Self {
+            block_type,
+            position,
+            previous_position: position,
+            velocity: 0.0,
+        }
+    }
+
+    /// Moves the block by one tick and returns where it settles if it landed.
+    fn tick(&mut self, grid: &impl BlockGrid) -> Option<[i32; 3]> {
+        self.previous_position = self.position;
+        self.velocity = (self.velocity - GRAVITY) * DRAG;
+        let new_y = self.position.y + self.velocity;
+
+        // Cells under the block during this tick from the top, the current one included since a
+        // block falling just ahead may have landed in it
+        let x = self.position.x.floor() as i32;
+        let z = self.position.z.floor() as i32;
+        for y in (new_y.floor() as i32..=self.position.y.floor() as i32).rev() {
+            let blocked = match grid.block_type([x, y, z]) {
+                Some(block_type) => block_type.is_solid(),
+                // The bottom of the world and unloaded chunks stop the fall
+                None => true,
+            };
+            if blocked {
+                return Some([x, y + 1, z]);
+            }
+        }
+
+        self.position.y = new_y;
+        None
+    }
+
+    fn interpolated_position(&self, tick_alpha: f32) -> Vector3<f32> {
+        self.previous_position.lerp(self.position, tick_alpha.min(1.0))
+    }
+}
+
+/// Falling blocks that landed during a tick.
+#[derive(Debug, Default)]
+pub struct Landed {
+    /// Blocks to place back into the world, with where to place them.
+    pub placed: Vec<([i32; 3], BlockType)>,
+    /// Blocks that landed where there is no room left, they drop as items instead.
+    pub dropped: Vec<([i32; 3], BlockType)>,
+}
+
+/// Blocks currently falling, they turn back into blocks when they land.
+#[derive(Debug, Default)]
+pub struct FallingBlocks {
+    blocks: Vec<FallingBlock>,
+}
+
+impl FallingBlocks {
+    /// Starts the fall of a block that was removed from `position`, returns false when
+    /// `MAX_FALLING_BLOCKS` are already falling.
+    pub fn spawn(&mut self, block_type: BlockType, position: [i32; 3]) -> bool {
+        if self.blocks.len() >= MAX_FALLING_BLOCKS {
+            return false;
+        }
+        self.blocks.push(FallingBlock::new(block_type, position));
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Moves every block by one tick and returns the blocks that landed.
+    pub fn tick(&mut self, grid: &impl BlockGrid) -> Landed {
+        let mut landed = Landed::default();
+        let mut falling = Vec::with_capacity(self.blocks.len());
+        for mut block in self.blocks.drain(..) {
+            match block.tick(grid) {
+                Some(position) => {
+                    // Something solid was placed where the block is, or another block landed there
+                    // during this tick
+                    let occupied = matches!(grid.block_type(position), Some(block_type) if block_type.is_solid())
+                        || landed.placed.iter().any(|(placed, _)| *placed == position);
+                    if occupied {
+                        landed.dropped.push((position, block.block_type));
+                    } else {
+                        landed.placed.push((position, block.block_type));
+                    }
+                }
+                None => falling.push(block),
+            }
+        }
+        self.blocks = falling;
+        landed
+    }
+
+    /// Vertices of every falling block, interpolated between the last two ticks.
+    pub fn vertices(&self, tick_alpha: f32) -> Vec<BlockVertex> {
+        self.blocks
+            .iter()
+            .flat_map(|block| cube_vertices(block.block_type, block.interpolated_position(tick_alpha), Vector3::new(1.0, 1.0, 1.0)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_updates::*, test_utils::*};
+
+    /// Ticks the falling blocks until none is left, and returns everything that landed.
+    fn land(falling_blocks: &mut FallingBlocks, grid: &mut MapGrid) -> Landed {
+        let mut landed = Landed::default();
+        for _ in 0..100 {
+            let tick_landed = falling_blocks.tick(grid);
+            for (position, block_type) in tick_landed.placed.iter() {
+                grid.set(*position, *block_type);
+            }
+            landed.placed.extend(tick_landed.placed);
+            landed.dropped.extend(tick_landed.dropped);
+            if falling_blocks.is_empty() {
+                break;
+            }
+        }
+        landed
+    }
+
+    #[test]
+    fn blocks_land_on_the_first_solid_block_below() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 31, 15]);
+        grid.set([4, 3, 4], BlockType::ROCK);
+        grid.set([4, 8, 4], BlockType::WATER);
+        grid.set([4, 1, 4], BlockType::ROCK);
+        let mut falling_blocks = FallingBlocks::default();
+        falling_blocks.spawn(BlockType::SAND, [4, 20, 4]);
+        falling_blocks.spawn(BlockType::GRAVEL, [9, 20, 9]);
+
+        let landed = land(&mut falling_blocks, &mut grid);
+        assert!(falling_blocks.is_empty());
+        // Through the water, onto the rock, and onto the bottom of the world
+        assert_eq!(landed.placed, vec![([4, 4, 4], BlockType::SAND), ([9, 0, 9], BlockType::GRAVEL)]);
+        assert!(landed.dropped.is_empty());
+    }
+
+    #[test]
+    fn sand_columns_collapse_one_block_at_a_time() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 15, 15]);
+        grid.set([4, 0, 4], BlockType::ROCK);
+        grid.fill([4, 6, 4], [4, 8, 4], BlockType::SAND);
+        let mut block_updates = BlockUpdates::new(42);
+        block_updates.schedule([4, 6, 4], FALLING_DELAY);
+        let mut falling_blocks = FallingBlocks::default();
+
+        // Runs the block updates and the falling blocks the way the world does
+        let mut spawned = Vec::new();
+        for tick in 0..200 {
+            block_updates.tick(&mut grid);
+            for (position, block_type) in grid.falling.drain(..) {
+                spawned.push((tick, position));
+                falling_blocks.spawn(block_type, position);
+            }
+            let landed = falling_blocks.tick(&grid);
+            assert!(landed.dropped.is_empty());
+            for (position, block_type) in landed.placed {
+                grid.set(position, block_type);
+                block_updates.block_changed(position);
+            }
+        }
+
+        let positions: Vec<[i32; 3]> = spawned.iter().map(|(_, position)| *position).collect();
+        assert_eq!(positions, vec![[4, 6, 4], [4, 7, 4], [4, 8, 4]]);
+        assert!(spawned.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(falling_blocks.is_empty());
+        for y in 1..=3 {
+            assert_eq!(grid.block_type([4, y, 4]), Some(BlockType::SAND));
+        }
+        for y in 4..=8 {
+            assert_eq!(grid.block_type([4, y, 4]), Some(BlockType::AIR));
+        }
+    }
+
+    #[test]
+    fn blocks_landing_in_a_filled_cell_are_dropped() {
+        let mut grid = MapGrid::new([0, 0, 0], [15, 15, 15]);
+        let mut falling_blocks = FallingBlocks::default();
+        falling_blocks.spawn(BlockType::SAND, [4, 5, 4]);
+        // Placed where the block is falling before it landed
+        grid.set([4, 5, 4], BlockType::ROCK);
+        grid.set([4, 6, 4], BlockType::ROCK);
+
+        let landed = land(&mut falling_blocks, &mut grid);
+        assert!(landed.placed.is_empty());
+        assert_eq!(landed.dropped, vec![([4, 6, 4], BlockType::SAND)]);
+        assert_eq!(grid.block_type([4, 6, 4]), Some(BlockType::ROCK));
+
+        // Two blocks landing in the same cell during the same tick
+        grid.set([9, 0, 9], BlockType::ROCK);
+        falling_blocks.spawn(BlockType::SAND, [9, 1, 9]);
+        falling_blocks.spawn(BlockType::GRAVEL, [9, 1, 9]);
+        let landed = land(&mut falling_blocks, &mut grid);
+        assert_eq!(landed.placed, vec![([9, 1, 9], BlockType::SAND)]);
+        assert_eq!(landed.dropped, vec![([9, 1, 9], BlockType::GRAVEL)]);
+    }
+}
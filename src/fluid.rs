@@ -0,0 +1,234 @@
+use std::collections::BTreeSet;
+
+use crate::block_registry::*;
+
+/// Level of a source block, sources never drain. Flowing water goes from `FLUID_SOURCE_LEVEL - 1`
+/// next to its source down to 1.
+pub const FLUID_SOURCE_LEVEL: u8 = 8;
+/// Ticks between a change and the reaction of the neighbouring water.
+pub const FLUID_TICK_DELAY: u64 = 5;
+
+const NEIGHBOURS: [[i32; 3]; 6] = [[0, 1, 0], [0, -1, 0], [1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+const HORIZONTAL_NEIGHBOURS: [[i32; 3]; 4] = [[1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+fn add(position: [i32; 3], offset: [i32; 3]) -> [i32; 3] {
+    [position[0] + offset[0], position[1] + offset[1], position[2] + offset[2]]
+}
+
+/// Height of the surface of water at `level`, as a fraction of a block.
+pub fn fluid_height(level: u8) -> f32 {
+    level.min(FLUID_SOURCE_LEVEL) as f32 / FLUID_SOURCE_LEVEL as f32
+}
+
+/// Blocks the simulation reads and writes, in world coordinates.
+///
+/// `World` implements it over its loaded chunks, anything else (a `HashMap`) can be used to run
+/// the simulation without rendering.
+pub trait FluidGrid {
+    /// Block type and fluid level, `None` outside of the loaded world.
+    fn fluid_block(&self, position: [i32; 3]) -> Option<(BlockType, u8)>;
+
+    /// Sets water at `level`, or air when `level` is 0.
+    fn set_fluid(&mut self, position: [i32; 3], level: u8);
+}
+
+/// Water only flows into air and plants.
+fn can_flow_into(block_type: BlockType) -> bool {
+    block_type == BlockType::WATER || !block_type.is_solid()
+}
+
+/// Water spreads sideways once it cannot fall any further, onto solid blocks or still water.
+fn is_supported(grid: &impl FluidGrid, position: [i32; 3]) -> bool {
+    match grid.fluid_block(add(position, [0, -1, 0])) {
+        Some((BlockType::WATER, level)) => level >= FLUID_SOURCE_LEVEL,
+        Some((block_type, _)) => !can_flow_into(block_type),
+        // The bottom of the world and unloaded chunks hold water
+        None => true,
+    }
+}
+
+/// Scheduled, tick-based water flow.
+///
+/// Positions are updated `FLUID_TICK_DELAY` ticks after one of their neighbours changed. All the
+/// positions due on a tick are computed from the state before the tick and applied afterwards,
+/// in position order, so the result does not depend on the order updates were scheduled in.
+#[derive(Debug, Default)]
+pub struct FluidSimulation {
+    tick: u64,
+    /// (tick, position) of the pending updates.
+    scheduled: BTreeSet<(u64, [i32; 3])>,
+}
+
+impl FluidSimulation {
+    /// Schedules an update of `position` and its neighbours, after a block changed there.
+    pub fn block_changed(&mut self, position: [i32; 3]) {
+        let tick = self.tick + FLUID_TICK_DELAY;
+        self.scheduled.insert((tick, position));
+        for offset in NEIGHBOURS.iter() {
+            self.scheduled.insert((tick, add(position, *offset)));
+        }
+    }
+
+    /// Level the water at `position` should have given its neighbours, `None` if it cannot change.
+    fn target_level(grid: &impl FluidGrid, position: [i32; 3]) -> Option<u8> {
+        let (block_type, level) = grid.fluid_block(position)?;
+        if !can_flow_into(block_type) || (block_type == BlockType::WATER && level >= FLUID_SOURCE_LEVEL) {
+            return None;
+        }
+
+        // Falling water keeps spreading as if it came from a source
+        if let Some((BlockType::WATER, _)) = grid.fluid_block(add(position, [0, 1, 0])) {
+            return Some(FLUID_SOURCE_LEVEL - 1);
+        }
+
+        let mut target = 0;
+        for offset in HORIZONTAL_NEIGHBOURS.iter() {
+            let neighbour = add(position, *offset);
+            if let Some((BlockType::WATER, neighbour_level)) = grid.fluid_block(neighbour) {
+                if is_supported(grid, neighbour) {
+                    target = target.max(neighbour_level.min(FLUID_SOURCE_LEVEL) - 1);
+                }
+            }
+        }
+        Some(target)
+    }
+
+    /// Advances the simulation by one tick and returns the positions that changed.
+    pub fn tick(&mut self, grid: &mut impl FluidGrid) -> Vec<[i32; 3]> {
+        self.tick += 1;
+
+        let mut due = Vec::new();
+        while let Some(&(tick, position)) = self.scheduled.iter().next() {
+            if tick > self.tick {
+                break;
+            }
+            self.scheduled.remove(&(tick, position));
+            due.push(position);
+        }
+        due.sort_unstable();
+        due.dedup();
+
+        let mut changes = Vec::new();
+        for position in due {
+            let (block_type, level) = match grid.fluid_block(position) {
+                Some(block) => block,
+                None => continue,
+            };
+            let current = if block_type == BlockType::WATER { level } else { 0 };
+            if let Some(target) = FluidSimulation::target_level(grid, position) {
+                if target != current {
+                    changes.push((position, target));
+                }
+            }
+        }
+
+        for (position, level) in changes.iter() {
+            grid.set_fluid(*position, *level);
+            self.block_changed(*position);
+        }
+        changes.into_iter().map(|(position, _)| position).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const SOURCE: [i32; 3] = [0, 1, 0];
+
+    /// Rock floor at y = 0 with room for water above it.
+    fn grid() -> MapGrid {
+        let mut grid = MapGrid::new([-16, 0, -16], [16, 8, 16]);
+        grid.fill([-16, 0, -16], [16, 0, 16], BlockType::ROCK);
+        grid
+    }
+
+    /// Ticks until no update is pending, returns the positions changed on each tick.
+    fn settle(fluids: &mut FluidSimulation, grid: &mut MapGrid) -> Vec<Vec<[i32; 3]>> {
+        let mut changed = Vec::new();
+        while !fluids.scheduled.is_empty() {
+            changed.push(fluids.tick(grid));
+            assert!(changed.len() < 1000, "water never settles");
+        }
+        changed
+    }
+
+    fn water_count(grid: &MapGrid) -> usize {
+        grid.blocks.keys().filter(|position| grid.level(**position) > 0).count()
+    }
+
+    #[test]
+    fn sources_spread_one_level_less_per_block() {
+        let mut grid = grid();
+        let mut fluids = FluidSimulation::default();
+        grid.set(SOURCE, BlockType::WATER);
+        fluids.block_changed(SOURCE);
+        settle(&mut fluids, &mut grid);
+
+        assert_eq!(grid.level(SOURCE), FLUID_SOURCE_LEVEL);
+        for distance in 1..8 {
+            assert_eq!(grid.level([distance, 1, 0]), FLUID_SOURCE_LEVEL - distance as u8);
+            assert_eq!(grid.level([0, 1, -distance]), FLUID_SOURCE_LEVEL - distance as u8);
+        }
+        assert_eq!(grid.level([8, 1, 0]), 0);
+        assert_eq!(grid.level([2, 1, 3]), 3);
+        assert_eq!(grid.level([0, 2, 0]), 0);
+    }
+
+    #[test]
+    fn falling_water_spreads_again_from_the_top_level() {
+        let mut grid = grid();
+        let mut fluids = FluidSimulation::default();
+        grid.set([0, 5, 0], BlockType::ROCK);
+        grid.set([0, 6, 0], BlockType::WATER);
+        fluids.block_changed([0, 6, 0]);
+        settle(&mut fluids, &mut grid);
+
+        // Water flowing off the ledge falls instead of spreading
+        assert_eq!(grid.level([1, 6, 0]), FLUID_SOURCE_LEVEL - 1);
+        assert_eq!(grid.level([2, 6, 0]), 0);
+        for y in 1..6 {
+            assert_eq!(grid.level([1, y, 0]), FLUID_SOURCE_LEVEL - 1);
+        }
+        assert_eq!(grid.level([2, 1, 0]), FLUID_SOURCE_LEVEL - 2);
+        assert_eq!(grid.level([3, 1, 0]), FLUID_SOURCE_LEVEL - 3);
+    }
+
+    #[test]
+    fn flowing_water_drains_without_its_source() {
+        let mut grid = grid();
+        let mut fluids = FluidSimulation::default();
+        grid.set(SOURCE, BlockType::WATER);
+        fluids.block_changed(SOURCE);
+        settle(&mut fluids, &mut grid);
+        assert!(water_count(&grid) > 1);
+
+        grid.set(SOURCE, BlockType::AIR);
+        fluids.block_changed(SOURCE);
+        settle(&mut fluids, &mut grid);
+        assert_eq!(water_count(&grid), 0);
+    }
+
+    #[test]
+    fn runs_do_not_depend_on_the_order_of_the_changes() {
+        let sources = [[0, 1, 0], [3, 1, 2], [-4, 4, 1]];
+        let run = |sources: &[[i32; 3]]| {
+            let mut grid = grid();
+            let mut fluids = FluidSimulation::default();
+            for source in sources.iter() {
+                grid.set(*source, BlockType::WATER);
+                fluids.block_changed(*source);
+            }
+            let changed = settle(&mut fluids, &mut grid);
+            (changed, grid.blocks)
+        };
+
+        let (changed, blocks) = run(&sources);
+        let mut reversed = sources;
+        reversed.reverse();
+        let (reversed_changed, reversed_blocks) = run(&reversed);
+        assert_eq!(changed, reversed_changed);
+        assert_eq!(blocks, reversed_blocks);
+    }
+}
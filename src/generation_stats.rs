@@ -0,0 +1,54 @@
+use crate::{chunk::*, ores::*, world_generation::*};
+
+/// Generates the chunks within `radius` of the origin and prints how many blocks of each ore
+/// they contain, to tune `ORES` without starting the game.
+pub fn print_ore_statistics(logger: &slog::Logger, generator: &dyn WorldGenerator, radius: i32) {
+    info!(logger, "Generating {} chunks...", (2 * radius) * (2 * radius));
+
+    let mut chunks = ChunkArray::default();
+    chunks.new_chunk([0, 0, 0]);
+    let mut blocks = chunks.blocks_array[0].lock().unwrap();
+
+    let mut counts_per_chunk = Vec::new();
+    for chunk_x in -radius..radius {
+        for chunk_z in -radius..radius {
+            let offset = [chunk_x, 0, chunk_z];
+            generator.generate_chunk(&mut blocks, offset);
+
+            let mut counts = [0usize; ORES.len()];
+            for layer in blocks.iter() {
+                for row in layer.iter() {
+                    for block in row.iter() {
+                        let material_type = block.lock().unwrap().material_type;
+                        if let Some(i) = ORES.iter().position(|ore| ore.block_type == material_type) {
+                            counts[i] += 1;
+                        }
+                    }
+                }
+            }
+            counts_per_chunk.push((offset, counts));
+        }
+    }
+
+    let names: Vec<&str> = ORES.iter().map(|ore| ore.block_type.properties().name.as_str()).collect();
+    println!("{:>12} {}", "chunk", names.iter().map(|name| format!("{:>12}", name)).collect::<String>());
+    for (offset, counts) in counts_per_chunk.iter() {
+        let chunk = format!("{},{}", offset[0], offset[2]);
+        println!("{:>12} {}", chunk, counts.iter().map(|count| format!("{:>12}", count)).collect::<String>());
+    }
+
+    println!();
+    println!("{:>12} {:>10} {:>10} {:>10} {:>10}", "ore", "total", "mean", "min", "max");
+    for (i, name) in names.iter().enumerate() {
+        let counts: Vec<usize> = counts_per_chunk.iter().map(|(_, counts)| counts[i]).collect();
+        let total: usize = counts.iter().sum();
+        println!(
+            "{:>12} {:>10} {:>10.1} {:>10} {:>10}",
+            name,
+            total,
+            total as f32 / counts.len() as f32,
+            counts.iter().min().unwrap_or(&0),
+            counts.iter().max().unwrap_or(&0)
+        );
+    }
+}
@@ -3,7 +3,7 @@ extern crate slog;
 extern crate slog_term;
 
 use anyhow::Result;
-use clap::{App, Arg};
+use clap::{value_t, App, Arg, SubCommand};
 use slog::Drain;
 use winit::{
     event::*,
@@ -11,22 +11,45 @@ use winit::{
 };
 
 mod atlas;
+mod biome;
 mod bitmap_font;
 mod block;
+mod block_entity;
+mod block_model;
+mod block_registry;
+mod block_selection;
+mod block_updates;
 mod camera;
+mod caves;
 mod chunk;
 mod coordinate;
+mod crack_overlay;
 mod crosshair;
+mod decoration;
 mod engine;
+mod entity;
+mod explosion;
+mod falling_block;
+mod fluid;
 mod framerate;
+mod generation_stats;
+mod map_export;
 mod noise;
+mod ores;
 mod overlay_info;
+mod pathfinding;
+mod physics;
 mod player;
 mod ray_tracer;
 mod renderer;
+mod spawn;
+#[cfg(test)]
+mod test_utils;
 mod texture;
+mod tick;
 mod world;
 mod world_generation;
+mod world_metadata;
 
 use engine::*;
 
@@ -34,7 +57,6 @@ use engine::*;
 pub struct Config {
     pub wireframe: bool,
     pub display_coordinates: bool,
-    pub flat_world: bool,
 }
 
 impl Default for Config {
@@ -42,7 +64,6 @@ impl Default for Config {
         Self {
             wireframe: false,
             display_coordinates: false,
-            flat_world: false,
         }
     }
 }
@@ -56,9 +77,6 @@ impl Config {
         if matches.is_present("COORDINATES") {
             config.display_coordinates = true;
         }
-        if matches.is_present("FLATWORLD") {
-            config.flat_world = true;
-        }
         return config;
     }
 }
@@ -71,7 +89,14 @@ fn setup_logger() -> slog::Logger {
     return logger;
 }
 
-fn event_loop(logger: slog::Logger, config: Config) -> Result<()> {
+fn event_loop(
+    logger: slog::Logger,
+    config: Config,
+    texture_pack: atlas::TexturePack,
+    generator: Box<dyn world_generation::WorldGenerator>,
+    metadata: world_metadata::WorldMetadata,
+    spawn_point: [i32; 3],
+) -> Result<()> {
     let event_loop = EventLoop::new();
     let title = env!("CARGO_PKG_NAME");
     let start_size = winit::dpi::LogicalSize::new(1000.0, 800.0);
@@ -84,10 +109,13 @@ fn event_loop(logger: slog::Logger, config: Config) -> Result<()> {
 
     use futures::executor::block_on;
     let renderer = block_on(renderer::Renderer::new(logger.clone(), &window))?;
-    let mut engine = Engine::new(logger.clone(), config, renderer)?;
+    let mut engine = Engine::new(logger.clone(), config, renderer, &texture_pack, generator, &metadata, spawn_point)?;
+    // The atlas is on the GPU now, the stitched image is no longer needed.
+    drop(texture_pack);
     engine.resize(start_size.to_physical(1.0));
 
     let mut last_render_time = std::time::Instant::now();
+    let mut tick_clock = tick::TickClock::new();
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
         match event {
@@ -123,7 +151,10 @@ fn event_loop(logger: slog::Logger, config: Config) -> Result<()> {
                 let now = std::time::Instant::now();
                 let dt = now - last_render_time;
                 last_render_time = now;
-                engine.update(dt);
+                for _ in 0..tick_clock.advance(dt) {
+                    engine.tick();
+                }
+                engine.update(dt, tick_clock.alpha());
                 match engine.render() {
                     Ok(_) => {}
                     Err(err) => match err.downcast_ref::<wgpu::SwapChainError>() {
@@ -137,6 +168,11 @@ fn event_loop(logger: slog::Logger, config: Config) -> Result<()> {
                     },
                 }
             }
+            Event::LoopDestroyed => {
+                if let Err(err) = engine.save() {
+                    error!(logger, "{:?}", err);
+                }
+            }
             _ => {}
         }
     });
@@ -170,13 +206,252 @@ fn main() -> Result<()> {
                 .long("flat_world")
                 .required(false)
                 .takes_value(false)
-                .help("Generates a flat world"),
+                .conflicts_with("GENERATOR")
+                .help("Generates a flat world, same as --generator flat"),
+        )
+        .arg(
+            Arg::with_name("GENERATOR")
+                .long("generator")
+                .required(false)
+                .takes_value(true)
+                .value_name("NAME")
+                .possible_values(&world_generation::GENERATOR_NAMES)
+                .help("World generator"),
+        )
+        .arg(
+            Arg::with_name("GENERATOROPTIONS")
+                .long("generator-options")
+                .required(false)
+                .takes_value(true)
+                .value_name("OPTIONS")
+                .help("Generator specific options, e.g. a layer list for flat or a preset for superflat"),
+        )
+        .arg(
+            Arg::with_name("WORLD")
+                .long("world")
+                .required(false)
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Loads the world saved in DIR, or creates it. A saved world keeps its seed, generator and settings"),
+        )
+        .arg(
+            Arg::with_name("WORLDHEIGHT")
+                .long("world-height")
+                .required(false)
+                .takes_value(true)
+                .value_name("BLOCKS")
+                .help("Height of new worlds, at most the chunk height"),
+        )
+        .arg(
+            Arg::with_name("SEALEVEL")
+                .long("sea-level")
+                .required(false)
+                .takes_value(true)
+                .value_name("Y")
+                .help("Sea level of new worlds"),
+        )
+        .arg(
+            Arg::with_name("TERRAINAMPLITUDE")
+                .long("terrain-amplitude")
+                .required(false)
+                .takes_value(true)
+                .value_name("FACTOR")
+                .help("Scales the terrain height of new worlds, 1.0 spans the whole world height"),
+        )
+        .arg(
+            Arg::with_name("NOCAVES")
+                .long("no-caves")
+                .required(false)
+                .takes_value(false)
+                .help("Generates the terrain without caves or ravines"),
+        )
+        .arg(
+            Arg::with_name("TEXTUREPACK")
+                .long("texture-pack")
+                .required(false)
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Loads block textures from a directory of PNGs"),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Prints the number of ore blocks generated per chunk")
+                .arg(
+                    Arg::with_name("SEED")
+                        .long("seed")
+                        .takes_value(true)
+                        .value_name("SEED")
+                        .default_value("0")
+                        .help("World seed"),
+                )
+                .arg(
+                    Arg::with_name("RADIUS")
+                        .long("radius")
+                        .takes_value(true)
+                        .value_name("CHUNKS")
+                        .default_value("4")
+                        .help("Generates the chunks within this many chunks of the origin"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("map")
+                .about("Writes a top-down map of the generated terrain to a PNG")
+                .arg(
+                    Arg::with_name("SEED")
+                        .long("seed")
+                        .takes_value(true)
+                        .value_name("SEED")
+                        .default_value("0")
+                        .help("World seed"),
+                )
+                .arg(
+                    Arg::with_name("AREA")
+                        .long("area")
+                        .takes_value(true)
+                        .value_name("X0,Z0,X1,Z1")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .help("World columns to draw, one pixel per column"),
+                )
+                .arg(
+                    Arg::with_name("OUT")
+                        .long("out")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .default_value("map.png")
+                        .help("Output PNG"),
+                )
+                .arg(
+                    Arg::with_name("MODE")
+                        .long("mode")
+                        .takes_value(true)
+                        .value_name("MODE")
+                        .possible_values(&map_export::MapMode::NAMES)
+                        .default_value("blocks")
+                        .help("Colors pixels by surface block, terrain height or biome"),
+                ),
         )
         .get_matches();
 
+    let texture_pack_path = matches.value_of("TEXTUREPACK").unwrap_or(atlas::DEFAULT_TEXTURE_PACK_PATH).to_string();
+    let generator_name = match matches.value_of("GENERATOR") {
+        Some(name) => name.to_string(),
+        None if matches.is_present("FLATWORLD") => "flat".to_string(),
+        None => world_generation::DEFAULT_GENERATOR.to_string(),
+    };
+    let generator_options = matches.value_of("GENERATOROPTIONS").map(|options| options.to_string());
+    let world_dir = matches.value_of("WORLD").map(std::path::PathBuf::from);
+    let mut settings = world_metadata::WorldSettings::default();
+    if matches.is_present("WORLDHEIGHT") {
+        settings.world_height = value_t!(matches, "WORLDHEIGHT", usize)?;
+        // Keep the default sea level in the middle of the world
+        settings.sea_level = settings.world_height / 2;
+    }
+    if matches.is_present("SEALEVEL") {
+        settings.sea_level = value_t!(matches, "SEALEVEL", usize)?;
+    }
+    if matches.is_present("TERRAINAMPLITUDE") {
+        settings.terrain_amplitude = value_t!(matches, "TERRAINAMPLITUDE", f64)?;
+    }
+    let no_caves = matches.is_present("NOCAVES");
+    let caves = if no_caves {
+        caves::CaveSettings::none()
+    } else {
+        caves::CaveSettings::default()
+    };
+    let stats = match matches.subcommand_matches("stats") {
+        Some(stats_matches) => Some((value_t!(stats_matches, "SEED", u32)?, value_t!(stats_matches, "RADIUS", i32)?)),
+        None => None,
+    };
+    let map = match matches.subcommand_matches("map") {
+        Some(map_matches) => Some((
+            value_t!(map_matches, "SEED", u32)?,
+            map_export::MapArea::parse(map_matches.value_of("AREA").unwrap_or_default())?,
+            map_export::MapMode::from_name(map_matches.value_of("MODE").unwrap_or_default())?,
+            std::path::PathBuf::from(map_matches.value_of("OUT").unwrap_or_default()),
+        )),
+        None => None,
+    };
     let config = Config::new(matches);
 
-    event_loop(logger, config)?;
+    let texture_pack = atlas::TexturePack::load(&texture_pack_path)?;
+    info!(logger, "Loaded texture pack '{}' ({}px tiles)", texture_pack_path, texture_pack.tile_size);
+
+    let block_models = block_model::BlockModels::load(block_model::BLOCK_MODELS_PATH)?;
+    let block_registry = block_registry::BlockRegistry::init(block_registry::BLOCK_REGISTRY_PATH, &block_models, &texture_pack)?;
+    info!(logger, "Loaded {} block definitions", block_registry.block_count());
+
+    let seed = match (stats, &map) {
+        (Some((seed, _)), _) => seed,
+        (_, Some((seed, ..))) => *seed,
+        _ => rand::random::<u32>(),
+    };
+    let mut metadata = match &world_dir {
+        Some(dir) if world_metadata::WorldMetadata::exists(dir) => {
+            let metadata = world_metadata::WorldMetadata::load(dir)?;
+            info!(logger, "Loaded world '{}'", dir.display());
+            if no_caves && metadata.caves != caves {
+                warn!(logger, "Ignoring --no-caves, world '{}' keeps the caves it was created with", dir.display());
+            }
+            metadata
+        }
+        _ => {
+            settings.validate()?;
+            world_metadata::WorldMetadata {
+                seed,
+                generator: generator_name,
+                generator_options,
+                settings,
+                planet_parameters: None,
+                caves,
+                spawn_point: None,
+                dir: world_dir.clone(),
+            }
+        }
+    };
+    world_generation::resolve_generator_options(&mut metadata)?;
+    let generator = world_generation::create_generator(&metadata)?;
+    info!(logger, "Using the '{}' world generator with seed {}", metadata.generator, metadata.seed);
+    let playing = stats.is_none() && map.is_none();
+    let new_world = match &world_dir {
+        Some(dir) => !world_metadata::WorldMetadata::exists(dir),
+        None => true,
+    };
+    let spawn_point = match metadata.spawn_point {
+        Some(spawn_point) => spawn_point,
+        None if playing => {
+            let spawn_point = match spawn::find_spawn_point(generator.as_ref(), &metadata.settings) {
+                Some(spawn_point) => spawn_point,
+                None => {
+                    warn!(logger, "No dry land found near the origin, spawning above the sea");
+                    [0, metadata.settings.sea_level as i32 + 4, 0]
+                }
+            };
+            metadata.spawn_point = Some(spawn_point);
+            spawn_point
+        }
+        None => [0, metadata.settings.sea_level as i32 + 4, 0],
+    };
+    if let Some(dir) = &world_dir {
+        if new_world || playing {
+            metadata.save(dir)?;
+        }
+        if new_world {
+            info!(logger, "Created world '{}'", dir.display());
+        }
+    }
+
+    if let Some((_, radius)) = stats {
+        generation_stats::print_ore_statistics(&logger, generator.as_ref(), radius);
+        return Ok(());
+    }
+    if let Some((_, area, mode, out)) = map {
+        map_export::export_map(&logger, generator.as_ref(), &texture_pack, metadata.settings, area, mode, &out)?;
+        return Ok(());
+    }
+
+    info!(logger, "Spawning at {:?}", spawn_point);
+    event_loop(logger, config, texture_pack, generator, metadata, spawn_point)?;
 
     Ok(())
 }
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::*;
+use rayon::prelude::*;
+
+use crate::{atlas::*, biome::*, block::*, block_registry::*, chunk::*, decoration::*, world_generation::*, world_metadata::*};
+
+/// Largest map side in blocks, a 4096x4096 map generates 65536 chunks.
+const MAX_MAP_SIZE: i32 = 4096;
+
+const WATER_COLOR: [u8; 3] = [48, 88, 200];
+
+/// What the pixels of a map show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapMode {
+    /// Average color of the top texture of the surface block, shaded by the slope.
+    BLOCKS,
+    /// Grayscale terrain height, water is ignored.
+    HEIGHT,
+    BIOME,
+}
+
+impl MapMode {
+    pub const NAMES: [&'static str; 3] = ["blocks", "height", "biome"];
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "blocks" => Ok(MapMode::BLOCKS),
+            "height" => Ok(MapMode::HEIGHT),
+            "biome" => Ok(MapMode::BIOME),
+            _ => bail!("Error unknown map mode '{}', expected one of {:?}", name, MapMode::NAMES),
+        }
+    }
+}
+
+/// Rectangle of world columns, from `min` (inclusive) to `max` (exclusive).
+#[derive(Debug, Clone, Copy)]
+pub struct MapArea {
+    pub min: [i32; 2],
+    pub max: [i32; 2],
+}
+
+impl MapArea {
+    /// Parses `x0,z0,x1,z1` in world coordinates.
+    pub fn parse(area: &str) -> Result<Self> {
+        let coordinates = area
+            .split(',')
+            .map(|coordinate| coordinate.trim().parse::<i32>())
+            .collect::<std::result::Result<Vec<i32>, _>>()
+            .with_context(|| format!("Error invalid map area '{}', expected x0,z0,x1,z1", area))?;
+        if coordinates.len() != 4 {
+            bail!("Error invalid map area '{}', expected x0,z0,x1,z1", area);
+        }
+
+        let area = MapArea {
+            min: [coordinates[0].min(coordinates[2]), coordinates[1].min(coordinates[3])],
+            max: [coordinates[0].max(coordinates[2]), coordinates[1].max(coordinates[3])],
+        };
+        let (width, height) = (area.width(), area.height());
+        if width == 0 || height == 0 || width > MAX_MAP_SIZE || height > MAX_MAP_SIZE {
+            bail!(
+                "Error map area is {}x{} blocks, it must be between 1 and {} blocks wide",
+                width,
+                height,
+                MAX_MAP_SIZE
+            );
+        }
+        Ok(area)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.max[0] - self.min[0]
+    }
+
+    pub fn height(&self) -> i32 {
+        self.max[1] - self.min[1]
+    }
+
+    fn contains(&self, world_x: i32, world_z: i32) -> bool {
+        world_x >= self.min[0] && world_x < self.max[0] && world_z >= self.min[1] && world_z < self.max[1]
+    }
+
+    /// Offsets of the chunks overlapping the area.
+    fn chunk_offsets(&self) -> Vec<[i32; 3]> {
+        let chunk_min_x = self.min[0].div_euclid(CHUNK_X_SIZE as i32);
+        let chunk_max_x = (self.max[0] - 1).div_euclid(CHUNK_X_SIZE as i32);
+        let chunk_min_z = self.min[1].div_euclid(CHUNK_Z_SIZE as i32);
+        let chunk_max_z = (self.max[1] - 1).div_euclid(CHUNK_Z_SIZE as i32);
+
+        let mut offsets = Vec::new();
+        for chunk_x in chunk_min_x..=chunk_max_x {
+            for chunk_z in chunk_min_z..=chunk_max_z {
+                offsets.push([chunk_x, 0, chunk_z]);
+            }
+        }
+        offsets
+    }
+}
+
+/// Topmost block of a column, plants are skipped so they do not hide the ground.
+#[derive(Debug, Clone, Copy)]
+struct Surface {
+    /// Height of the ground, water excluded.
+    height: usize,
+    block_type: BlockType,
+    water_depth: usize,
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Self {
+            height: 0,
+            block_type: BlockType::AIR,
+            water_depth: 0,
+        }
+    }
+}
+
+/// Chunk offset, column surfaces and decoration blocks landing in other chunks.
+type GeneratedChunk = ([i32; 3], Vec<Surface>, Vec<([i32; 3], BlockWrite)>);
+
+fn is_ground(block_type: BlockType) -> bool {
+    block_type != BlockType::AIR && block_type != BlockType::WATER && block_type.model().is_full()
+}
+
+fn column_surface(blocks: &Blocks, x: usize, z: usize) -> Surface {
+    let mut water_depth = 0;
+    for y in (0..CHUNK_Y_SIZE).rev() {
+        let block_type = blocks[y][x][z].lock().unwrap().material_type;
+        if block_type == BlockType::WATER {
+            water_depth += 1;
+        } else if is_ground(block_type) {
+            return Surface {
+                height: y,
+                block_type,
+                water_depth,
+            };
+        }
+    }
+    Surface {
+        water_depth,
+        ..Surface::default()
+    }
+}
+
+/// Average color of the opaque pixels of the top tile of each block type.
+fn block_colors(texture_pack: &TexturePack) -> HashMap<BlockType, [u8; 3]> {
+    let registry = BlockRegistry::get();
+    let (width, height) = texture_pack.image.dimensions();
+
+    let mut colors = HashMap::new();
+    for block_type in registry.block_types() {
+        let tile = match registry.properties(block_type).tiles {
+            Some(tiles) => tiles[FaceDirection::TOP as usize],
+            None => continue,
+        };
+
+        let mut sum = [0u64; 3];
+        let mut count = 0u64;
+        for y in (tile.min[1] * height as f32) as u32..(tile.max[1] * height as f32) as u32 {
+            for x in (tile.min[0] * width as f32) as u32..(tile.max[0] * width as f32) as u32 {
+                let pixel = texture_pack.image.get_pixel(x, y);
+                if pixel[3] > 0 {
+                    for (channel, value) in sum.iter_mut().zip(pixel.0.iter()) {
+                        *channel += *value as u64;
+                    }
+                    count += 1;
+                }
+            }
+        }
+        // Fully transparent tiles keep the fallback color
+        if count == 0 {
+            continue;
+        }
+        colors.insert(block_type, [(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8]);
+    }
+    colors
+}
+
+fn biome_color(biome: Biome) -> [u8; 3] {
+    match biome {
+        Biome::OCEAN => [40, 70, 180],
+        Biome::PLAINS => [120, 190, 80],
+        Biome::FOREST => [40, 120, 40],
+        Biome::DESERT => [230, 210, 140],
+        Biome::SNOW => [240, 245, 250],
+        Biome::MOUNTAINS => [130, 125, 120],
+    }
+}
+
+fn shade(color: [u8; 3], factor: f32) -> [u8; 3] {
+    let shade_channel = |channel: u8| (channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    [shade_channel(color[0]), shade_channel(color[1]), shade_channel(color[2])]
+}
+
+/// Generates the chunks covering `area` with `generator` and writes a top-down map of it to `out`.
+///
+/// Decorations spilling over from chunks outside of the area are not drawn.
+pub fn export_map(
+    logger: &slog::Logger,
+    generator: &dyn WorldGenerator,
+    texture_pack: &TexturePack,
+    settings: WorldSettings,
+    area: MapArea,
+    mode: MapMode,
+    out: &Path,
+) -> Result<()> {
+    if mode == MapMode::BIOME && generator.biome_at(area.min[0], area.min[1]).is_none() {
+        bail!("Error the world generator has no biomes");
+    }
+
+    let chunk_offsets = area.chunk_offsets();
+    info!(
+        logger,
+        "Generating {} chunks for a {}x{} map...",
+        chunk_offsets.len(),
+        area.width(),
+        area.height()
+    );
+
+    let generated: Vec<GeneratedChunk> = chunk_offsets
+        .par_iter()
+        .map_init(
+            || {
+                let mut chunks = ChunkArray::default();
+                chunks.new_chunk([0, 0, 0]);
+                chunks
+            },
+            |chunks, offset| {
+                let mut blocks = chunks.blocks_array[0].lock().unwrap();
+                let overflow = generator.generate_chunk(&mut blocks, *offset);
+
+                let mut surfaces = Vec::with_capacity(CHUNK_X_SIZE * CHUNK_Z_SIZE);
+                for x in 0..CHUNK_X_SIZE {
+                    for z in 0..CHUNK_Z_SIZE {
+                        surfaces.push(column_surface(&blocks, x, z));
+                    }
+                }
+                (*offset, surfaces, overflow)
+            },
+        )
+        .collect();
+
+    let (width, height) = (area.width() as usize, area.height() as usize);
+    let mut surfaces = vec![Surface::default(); width * height];
+    let mut overflows = Vec::new();
+    for (offset, chunk_surfaces, overflow) in generated {
+        for x in 0..CHUNK_X_SIZE {
+            for z in 0..CHUNK_Z_SIZE {
+                let world_x = offset[0] * CHUNK_X_SIZE as i32 + x as i32;
+                let world_z = offset[2] * CHUNK_Z_SIZE as i32 + z as i32;
+                if area.contains(world_x, world_z) {
+                    let index = (world_z - area.min[1]) as usize * width + (world_x - area.min[0]) as usize;
+                    surfaces[index] = chunk_surfaces[x * CHUNK_Z_SIZE + z];
+                }
+            }
+        }
+        overflows.extend(overflow);
+    }
+
+    // Tree tops crossing chunk borders
+    for (target, block_write) in overflows {
+        let [x, y, z] = block_write.position;
+        let world_x = target[0] * CHUNK_X_SIZE as i32 + x as i32;
+        let world_z = target[2] * CHUNK_Z_SIZE as i32 + z as i32;
+        if !area.contains(world_x, world_z) || !is_ground(block_write.block_type) {
+            continue;
+        }
+        let surface = &mut surfaces[(world_z - area.min[1]) as usize * width + (world_x - area.min[0]) as usize];
+        if y > surface.height && surface.water_depth == 0 {
+            surface.height = y;
+            surface.block_type = block_write.block_type;
+        }
+    }
+
+    let colors = match mode {
+        MapMode::BLOCKS => block_colors(texture_pack),
+        _ => HashMap::new(),
+    };
+    let mut image = image::RgbImage::new(width as u32, height as u32);
+    for (i, surface) in surfaces.iter().enumerate() {
+        let (x, z) = (i % width, i / width);
+        let color = match mode {
+            MapMode::BLOCKS => {
+                if surface.water_depth > 0 {
+                    shade(WATER_COLOR, 1.0 - (surface.water_depth as f32 / 40.0).min(0.6))
+                } else {
+                    // Light comes from the north west
+                    let color = colors.get(&surface.block_type).cloned().unwrap_or([255, 0, 255]);
+                    let neighbour = if x > 0 && z > 0 { surfaces[i - width - 1].height } else { surface.height };
+                    shade(color, (1.0 + 0.15 * (surface.height as f32 - neighbour as f32)).clamp(0.6, 1.4))
+                }
+            }
+            MapMode::HEIGHT => {
+                let value = (surface.height * 255 / (settings.world_height - 1)) as u8;
+                [value, value, value]
+            }
+            MapMode::BIOME => {
+                let biome = generator.biome_at(area.min[0] + x as i32, area.min[1] + z as i32).unwrap_or(Biome::PLAINS);
+                biome_color(biome)
+            }
+        };
+        image.put_pixel(x as u32, z as u32, image::Rgb(color));
+    }
+
+    image.save(out).with_context(|| format!("Error could not write map '{}'", out.display()))?;
+    info!(logger, "Wrote map to '{}'", out.display());
+    Ok(())
+}
@@ -1,9 +1,161 @@
 extern crate noise;
 
+use anyhow::*;
 use noise::{utils::*, *};
+use serde::{Deserialize, Serialize};
+use std::{fs::read_to_string, path::Path};
 
 use crate::chunk::*;
 
+pub const PLANET_PARAMETERS_PATH: &str = "./assets/generators/planet.ron";
+
+/// Tunable parameters of the planet terrain, see `assets/generators/planet.ron`.
+///
+/// Elevations are in planetary elevation units, -1.0 for the lowest underwater
+/// trenches and +1.0 for the highest mountain peaks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlanetParameters {
+    /// Frequency of the planet's continents. Higher frequency produces
+    /// smaller, more numerous continents. This value is measured in radians.
+    pub continent_frequency: f64,
+    /// Lacunarity of the planet's continents. Changing this value produces
+    /// slightly different continents. For the best results, this value should
+    /// be random, but close to 2.0.
+    pub continent_lacunarity: f64,
+    /// Lacunarity of the planet's mountains, close to 2.0.
+    pub mountain_lacunarity: f64,
+    /// Lacunarity of the planet's hills, close to 2.0.
+    pub hills_lacunarity: f64,
+    /// Lacunarity of the planet's plains, close to 2.0.
+    pub plains_lacunarity: f64,
+    /// Lacunarity of the planet's badlands, close to 2.0.
+    pub badlands_lacunarity: f64,
+    /// Specifies the "twistiness" of the mountains.
+    pub mountains_twist: f64,
+    /// Specifies the "twistiness" of the hills.
+    pub hills_twist: f64,
+    /// Specifies the "twistiness" of the badlands.
+    pub badlands_twist: f64,
+    /// Specifies the planet's sea level. This value must be between -1.0
+    /// (minimum planet elevation) and +1.0 (maximum planet elevation).
+    pub sea_level: f64,
+    /// Specifies the level on the planet in which continental shelves appear.
+    /// This value must be between -1.0 and +1.0, and must be less than
+    /// `sea_level`.
+    pub shelf_level: f64,
+    /// Determines the amount of mountainous terrain that appears on the
+    /// planet. Values range from 0.0 (no mountains) to 1.0 (all terrain is
+    /// covered in mountains). Mountains terrain will overlap hilly terrain,
+    /// hills cover half of what is left.
+    pub mountains_amount: f64,
+    /// Determines the amount of badlands terrain that covers the planet.
+    /// Values range from 0.0 (no badlands) to 1.0 (all terrain is covered in
+    /// badlands). Badlands terrain will overlap any other type of terrain.
+    pub badlands_amount: f64,
+    /// Offset to apply to the terrain type definition. Low values (< 1.0)
+    /// cause the rough areas to appear only at high elevations. High values
+    /// (> 2.0) cause the rough areas to appear at any elevation. The
+    /// percentage of rough areas on the planet are independent of this value.
+    pub terrain_offset: f64,
+    /// Specifies the amount of "glaciation" on the mountains. This value
+    /// should be close to 1.0 and greater than 1.0.
+    pub mountain_glaciation: f64,
+    /// Maximum depth of the rivers, in planetary elevation units.
+    pub river_depth: f64,
+}
+
+impl Default for PlanetParameters {
+    fn default() -> Self {
+        Self {
+            continent_frequency: 1.0,
+            continent_lacunarity: 2.208984375,
+            mountain_lacunarity: 2.142578125,
+            hills_lacunarity: 2.162109375,
+            plains_lacunarity: 2.314453125,
+            badlands_lacunarity: 2.212890625,
+            mountains_twist: 1.0,
+            hills_twist: 1.0,
+            badlands_twist: 1.0,
+            sea_level: 0.0,
+            shelf_level: -0.375,
+            mountains_amount: 0.5,
+            badlands_amount: 0.3125,
+            terrain_offset: 1.0,
+            mountain_glaciation: 1.375,
+            river_depth: 0.0234375,
+        }
+    }
+}
+
+impl PlanetParameters {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = read_to_string(path).with_context(|| format!("Error could not read planet parameters '{}'", path.display()))?;
+        let parameters: PlanetParameters = ron::de::from_str(&content).with_context(|| format!("Error invalid planet parameters '{}'", path.display()))?;
+        parameters
+            .validate()
+            .with_context(|| format!("Error invalid planet parameters '{}'", path.display()))?;
+        Ok(parameters)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.sea_level < -1.0 || self.sea_level > 1.0 {
+            bail!("Error sea_level must be between -1.0 and 1.0, got {}", self.sea_level);
+        }
+        if self.shelf_level >= self.sea_level {
+            bail!("Error shelf_level ({}) must be less than sea_level ({})", self.shelf_level, self.sea_level);
+        }
+        if self.mountains_amount < 0.0 || self.mountains_amount > 1.0 {
+            bail!("Error mountains_amount must be between 0.0 and 1.0, got {}", self.mountains_amount);
+        }
+        if self.badlands_amount < 0.0 || self.badlands_amount > 1.0 {
+            bail!("Error badlands_amount must be between 0.0 and 1.0, got {}", self.badlands_amount);
+        }
+        if self.continent_frequency <= 0.0 {
+            bail!("Error continent_frequency must be positive, got {}", self.continent_frequency);
+        }
+        Ok(())
+    }
+}
+
+/// Samples the planet terrain of a seed.
+///
+/// Most modules of the graph borrow their sources, so the graph only lives as long as the call
+/// sampling it: each noise map builds its own graph, which also keeps the `Cache` modules (not
+/// thread safe) to the thread using them.
+#[derive(Debug, Clone, Copy)]
+pub struct PlanetNoise {
+    seed: u32,
+    parameters: PlanetParameters,
+}
+
+impl PlanetNoise {
+    pub fn new(seed: u32, parameters: PlanetParameters) -> Self {
+        Self { seed, parameters }
+    }
+
+    /// Elevations of the columns of a chunk and of `border` columns around it, between -1.0 and
+    /// 1.0. The column `[x, z]` of the chunk is at `[x + border, z + border]` in the map.
+    pub fn get_noise_map(&self, chunk_offset: [i32; 3], border: usize) -> NoiseMap {
+        let bound_size = 0.005;
+        let x_step = bound_size / CHUNK_X_SIZE as f64;
+        let x_min = chunk_offset[0] as f64 / 200.0 - border as f64 * x_step;
+        let x_max = x_min + (CHUNK_X_SIZE + 2 * border) as f64 * x_step;
+
+        let z_step = bound_size / CHUNK_Z_SIZE as f64;
+        let z_min = chunk_offset[2] as f64 / 200.0 - border as f64 * z_step;
+        let z_max = z_min + (CHUNK_Z_SIZE + 2 * border) as f64 * z_step;
+        with_planet_graph(self.seed, &self.parameters, |graph| {
+            PlaneMapBuilder::new(graph)
+                .set_size(CHUNK_X_SIZE + 2 * border, CHUNK_Z_SIZE + 2 * border)
+                .set_x_bounds(x_min, x_max)
+                .set_y_bounds(z_min, z_max)
+                .build()
+        })
+    }
+}
+
 /// This example demonstrates how to use the noise-rs library to generate
 /// terrain elevations for a complex planetary surface.
 ///
@@ -61,93 +213,37 @@ use crate::chunk::*;
 /// A description for each group and subgroup can be found above the source
 /// code for that group and subgroup.
 #[allow(non_snake_case)]
-pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
+fn with_planet_graph<T>(seed: u32, parameters: &PlanetParameters, f: impl FnOnce(&dyn NoiseFn<f64, 3>) -> T) -> T {
     let CURRENT_SEED: u32 = seed;
 
-    /// Frequency of the planet's continents. Higher frequency produces
-    /// smaller, more numerous continents. This value is measured in radians.
-    const CONTINENT_FREQUENCY: f64 = 1.0;
-
-    /// Lacunarity of the planet's continents. Changing this value produces
-    /// slightly different continents. For the best results, this value should
-    /// be random, but close to 2.0.
-    const CONTINENT_LACUNARITY: f64 = 2.208984375;
-
-    /// Lacunarity of the planet's mountains. Changing the value produces
-    /// slightly different mountains. For the best results, this value should
-    /// be random, but close to 2.0.
-    const MOUNTAIN_LACUNARITY: f64 = 2.142578125;
-
-    /// Lacunarity of the planet's hills. Changing this value produces
-    /// slightly different hills. For the best results, this value should be
-    /// random, but close to 2.0.
-    const HILLS_LACUNARITY: f64 = 2.162109375;
-
-    /// Lacunarity of the planet's plains. Changing this value produces
-    /// slightly different plains. For the best results, this value should be
-    /// random, but close to 2.0.
-    const PLAINS_LACUNARITY: f64 = 2.314453125;
-
-    /// Lacunarity of the planet's badlands. Changing this value produces
-    /// slightly different badlands. For the best results, this value should
-    /// be random, but close to 2.0.
-    const BADLANDS_LACUNARITY: f64 = 2.212890625;
-
-    /// Specifies the "twistiness" of the mountains.
-    const MOUNTAINS_TWIST: f64 = 1.0;
-
-    /// Specifies the "twistiness" of the hills.
-    const HILLS_TWIST: f64 = 1.0;
-
-    /// Specifies the "twistiness" of the badlands.
-    const BADLANDS_TWIST: f64 = 1.0;
-
-    /// Specifies the planet's sea level. This value must be between -1.0
-    /// (minimum planet elevation) and +1.0 (maximum planet elevation).
-    const SEA_LEVEL: f64 = 0.0;
-
-    /// Specifies the level on the planet in which continental shelves appear.
-    /// This value must be between -1.0 (minimum planet elevation) and +1.0
-    /// (maximum planet elevation), and must be less than `SEA_LEVEL`.
-    const SHELF_LEVEL: f64 = -0.375;
-
-    /// Determines the amount of mountainous terrain that appears on the
-    /// planet. Values range from 0.0 (no mountains) to 1.0 (all terrain is
-    /// covered in mountains). Mountains terrain will overlap hilly terrain.
-    /// Because the badlands terrain may overlap parts of the mountainous
-    /// terrain, setting `MOUNTAINS_AMOUNT` to 1.0 may not completely cover the
-    /// terrain in mountains.
-    const MOUNTAINS_AMOUNT: f64 = 0.5;
-
-    /// Determines the amount of hilly terrain that appears on the planet.
-    /// Values range from 0.0 (no hills) to 1.0 (all terrain is covered in
-    /// hills). This value must be less than `MOUNTAINS_AMOUNT`. Because the
-    /// mountains terrain will overlap parts of the hilly terrain, and the
-    /// badlands terrain may overlap parts of the hilly terrain, setting
-    /// `HILLS_AMOUNT` to 1.0 may not completely cover the terrain in hills.
-    const HILLS_AMOUNT: f64 = (1.0 + MOUNTAINS_AMOUNT) / 2.0;
-
-    /// Determines the amount of badlands terrain that covers the planet.
-    /// Values range from 0.0 (no badlands) to 1.0 (all terrain is covered in
-    /// badlands). Badlands terrain will overlap any other type of terrain.
-    const BADLANDS_AMOUNT: f64 = 0.3125;
-
-    /// Offset to apply to the terrain type definition. Low values (< 1.0)
-    /// cause the rough areas to appear only at high elevations. High values
-    /// (> 2.0) cause the rough areas to appear at any elevation. The
-    /// percentage of rough areas on the planet are independent of this value.
-    const TERRAIN_OFFSET: f64 = 1.0;
-
-    /// Specifies the amount of "glaciation" on the mountains. This value
-    /// should be close to 1.0 and greater than 1.0.
-    const MOUNTAIN_GLACIATION: f64 = 1.375;
-
-    /// Scaling to apply to the base continent elevations, in planetary
-    /// elevation units.
-    const CONTINENT_HEIGHT_SCALE: f64 = (1.0 - SEA_LEVEL) / 4.0;
-
-    /// Maximum depth of the rivers, in planetary elevation units.
-    const RIVER_DEPTH: f64 = 0.0234375;
+    let CONTINENT_FREQUENCY = parameters.continent_frequency;
+    let CONTINENT_LACUNARITY = parameters.continent_lacunarity;
+    let MOUNTAIN_LACUNARITY = parameters.mountain_lacunarity;
+    let HILLS_LACUNARITY = parameters.hills_lacunarity;
+    let PLAINS_LACUNARITY = parameters.plains_lacunarity;
+    let BADLANDS_LACUNARITY = parameters.badlands_lacunarity;
+    let MOUNTAINS_TWIST = parameters.mountains_twist;
+    let HILLS_TWIST = parameters.hills_twist;
+    let BADLANDS_TWIST = parameters.badlands_twist;
+    let SEA_LEVEL = parameters.sea_level;
+    let SHELF_LEVEL = parameters.shelf_level;
+    let MOUNTAINS_AMOUNT = parameters.mountains_amount;
+    let BADLANDS_AMOUNT = parameters.badlands_amount;
+    let TERRAIN_OFFSET = parameters.terrain_offset;
+    let MOUNTAIN_GLACIATION = parameters.mountain_glaciation;
+    let RIVER_DEPTH = parameters.river_depth;
+
+    // Determines the amount of hilly terrain that appears on the planet.
+    // Values range from 0.0 (no hills) to 1.0 (all terrain is covered in
+    // hills). This value must be less than `MOUNTAINS_AMOUNT`. Because the
+    // mountains terrain will overlap parts of the hilly terrain, and the
+    // badlands terrain may overlap parts of the hilly terrain, setting
+    // `HILLS_AMOUNT` to 1.0 may not completely cover the terrain in hills.
+    let HILLS_AMOUNT: f64 = (1.0 + MOUNTAINS_AMOUNT) / 2.0;
+
+    // Scaling to apply to the base continent elevations, in planetary
+    // elevation units.
+    let CONTINENT_HEIGHT_SCALE: f64 = (1.0 - SEA_LEVEL) / 4.0;
 
     // ////////////////////////////////////////////////////////////////////////
     // Function group: continent definition
@@ -166,14 +262,14 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // elevations.
     //
 
-    let baseContinentDef_fb0 = Fbm::new()
+    let baseContinentDef_fb0 = &Fbm::new()
         .set_seed(CURRENT_SEED)
         .set_frequency(CONTINENT_FREQUENCY)
         .set_persistence(0.5)
         .set_lacunarity(CONTINENT_LACUNARITY)
         .set_octaves(14);
 
-    let baseContinentDef_cu = Curve::new(&baseContinentDef_fb0)
+    let baseContinentDef_cu = &Curve::new(baseContinentDef_fb0)
         .add_control_point(-2.0000 + SEA_LEVEL, -1.625 + SEA_LEVEL)
         .add_control_point(-1.0000 + SEA_LEVEL, -1.375 + SEA_LEVEL)
         .add_control_point(0.0000 + SEA_LEVEL, -0.375 + SEA_LEVEL)
@@ -185,23 +281,23 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
         .add_control_point(1.0000 + SEA_LEVEL, 0.500 + SEA_LEVEL)
         .add_control_point(2.0000 + SEA_LEVEL, 0.500 + SEA_LEVEL);
 
-    let baseContinentDef_fb1 = Fbm::new()
+    let baseContinentDef_fb1 = &Fbm::new()
         .set_seed(CURRENT_SEED + 1)
         .set_frequency(CONTINENT_FREQUENCY * 4.34375)
         .set_persistence(0.5)
         .set_lacunarity(CONTINENT_LACUNARITY)
         .set_octaves(11);
 
-    let baseContinentDef_sb = ScaleBias::new(&baseContinentDef_fb1).set_scale(0.375).set_bias(0.625);
+    let baseContinentDef_sb = &ScaleBias::new(baseContinentDef_fb1).set_scale(0.375).set_bias(0.625);
 
-    let baseContinentDef_mi = Min::new(&baseContinentDef_sb, &baseContinentDef_cu);
+    let baseContinentDef_mi = &Min::new(baseContinentDef_sb, baseContinentDef_cu);
 
     //                               100);
-    let baseContinentDef_cl = Clamp::new(&baseContinentDef_mi).set_bounds(-1.0, 1.0);
+    let baseContinentDef_cl = Clamp::new(baseContinentDef_mi).set_bounds(-1.0, 1.0);
 
     // 7: [Base-continent-definition subgroup]: Caches the output value from
     // the clamped-continent module.
-    let baseContinentDef = Cache::new(baseContinentDef_cl);
+    let baseContinentDef = &Cache::new(baseContinentDef_cl);
 
     //    debug::render_noise_module("complexplanet_images/00_5_baseContinentDef.png",
     //                               &baseContinentDef,
@@ -225,7 +321,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Coarse-turbulence module]: This turbulence module warps the output
     // value from the base-continent-definition subgroup, adding some coarse
     // detail to it.
-    let continentDef_tu0 = Turbulence::new(&baseContinentDef)
+    let continentDef_tu0 = Turbulence::new(baseContinentDef)
         .set_seed(CURRENT_SEED + 10)
         .set_frequency(CONTINENT_FREQUENCY * 15.25)
         .set_power(CONTINENT_FREQUENCY / 113.75)
@@ -257,7 +353,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // warps the output value from the intermediate-turbulence module. This
     // turbulence has a higher frequency, but lower power, than the
     // intermediate-turbulence module, adding some fine detail to it.
-    let continentDef_tu2 = Turbulence::new(continentDef_tu1)
+    let continentDef_tu2 = &Turbulence::new(continentDef_tu1)
         .set_seed(CURRENT_SEED + 12)
         .set_frequency(CONTINENT_FREQUENCY * 95.25)
         .set_power(CONTINENT_FREQUENCY / 1019.75)
@@ -279,7 +375,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // transition.  In effect, only the higher areas of the base-continent-
     // definition subgroup become warped; the underwater and coastal areas
     // remain unaffected.
-    let continentDef_se = Select::new(&baseContinentDef, &continentDef_tu2, &baseContinentDef)
+    let continentDef_se = Select::new(baseContinentDef, continentDef_tu2, baseContinentDef)
         .set_bounds(SEA_LEVEL - 0.0375, SEA_LEVEL + 1000.0375)
         .set_falloff(0.0625);
 
@@ -292,7 +388,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 5: [Continent-definition group]: Caches the output value from the
     // clamped-continent module. This is the output value for the entire
     // continent-definition group.
-    let continentDef = Cache::new(continentDef_se);
+    let continentDef = &Cache::new(continentDef_se);
 
     //    debug::render_noise_module("complexplanet_images/01_4_continentDef.png",
     //                               &continentDef,
@@ -325,7 +421,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // rougher terrain from appearing exclusively at higher elevations. Rough
     // areas may now appear in the the ocean, creating rocky islands and
     // fjords.
-    let terrainTypeDef_tu = Turbulence::new(&continentDef)
+    let terrainTypeDef_tu = &Turbulence::new(continentDef)
         .set_seed(CURRENT_SEED + 20)
         .set_frequency(CONTINENT_FREQUENCY * 18.125)
         .set_power(CONTINENT_FREQUENCY / 20.59375 * TERRAIN_OFFSET)
@@ -336,7 +432,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // slope towards the higher-elevation areas. This shrinks the areas in
     // which the rough terrain appears, increasing the "rarity" of rough
     // terrain.
-    let terrainTypeDef_te = Terrace::new(&terrainTypeDef_tu)
+    let terrainTypeDef_te = Terrace::new(terrainTypeDef_tu)
         .add_control_point(-1.00)
         .add_control_point(SHELF_LEVEL + SEA_LEVEL / 2.0)
         .add_control_point(1.00);
@@ -344,7 +440,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 3: [Terrain-type-definition group]: Caches the output value from the
     // roughness-probability-shift module. This is the output value for the
     // entire terrain-type-definition group.
-    let terrainTypeDef = Cache::new(terrainTypeDef_te);
+    let terrainTypeDef = &Cache::new(terrainTypeDef_te);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function group: mountainous terrain
@@ -362,7 +458,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Mountain-ridge module]: This ridged-multifractal-noise function
     // generates the mountain ridges.
-    let mountainBaseDef_rm0 = RidgedMulti::new()
+    let mountainBaseDef_rm0 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 30)
         .set_frequency(1723.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -372,7 +468,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // output value from the mountain-ridge module so that its ridges are not
     // too high. The reason for this is that another subgroup adds actual
     // mountainous terrain to these ridges.
-    let mountainBaseDef_sb0 = ScaleBias::new(&mountainBaseDef_rm0).set_scale(0.5).set_bias(0.375);
+    let mountainBaseDef_sb0 = &ScaleBias::new(mountainBaseDef_rm0).set_scale(0.5).set_bias(0.375);
 
     // 3: [River-valley module]: This ridged-multifractal-noise function
     // generates the river valleys.  It has a much lower frequency than the
@@ -380,7 +476,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // of the valleys. Note that this noise function generates ridged-multifractal
     // noise using only one octave; this information will be important in the
     // next step.
-    let mountainBaseDef_rm1 = RidgedMulti::new()
+    let mountainBaseDef_rm1 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 31)
         .set_frequency(367.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -393,10 +489,10 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // octave ridged-multifractal noise. The negative scaling factor inverts
     // the range of the output value, turning the ridges from the river-valley
     // module into valleys.
-    let mountainBaseDef_sb1 = ScaleBias::new(&mountainBaseDef_rm1).set_scale(-2.0).set_bias(-0.5);
+    let mountainBaseDef_sb1 = &ScaleBias::new(mountainBaseDef_rm1).set_scale(-2.0).set_bias(-0.5);
 
     // 5: [Low-flat module]: This low constant value is used by step 6.
-    let mountainBaseDef_co = Constant::new(-1.0);
+    let mountainBaseDef_co = &Constant::new(-1.0);
 
     // 6: [Mountains-and-valleys module]: This blender module merges the scaled-
     // mountain-ridge module and the scaled-river-valley module together. It
@@ -405,7 +501,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // uses the scaled-river-valley module as the control module, causing the
     // low-flat module to appear in the lower areas and causing the scaled-
     // mountain-ridge module to appear in the higher areas.
-    let mountainBaseDef_bl = Blend::new(&mountainBaseDef_co, &mountainBaseDef_sb0, &mountainBaseDef_sb1);
+    let mountainBaseDef_bl = Blend::new(mountainBaseDef_co, mountainBaseDef_sb0, mountainBaseDef_sb1);
 
     // 7: [Coarse-turbulence module]: This turbulence module warps the output
     // value from the mountain-and-valleys module, adding some coarse detail to
@@ -428,7 +524,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 9: [Mountain-base-definition subgroup]: Caches the output value from the
     // warped-mountains-and-valleys module.
-    let mountainBaseDef = Cache::new(mountainBaseDef_tu1);
+    let mountainBaseDef = &Cache::new(mountainBaseDef_tu1);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function subgroup: high mountainous terrain (5 noise functions)
@@ -443,7 +539,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Mountain-basis-0 module]: This ridged-multifractal-noise function,
     // along with the mountain-basis-1 module, generates the individual
     // mountains.
-    let mountainousHigh_rm0 = RidgedMulti::new()
+    let mountainousHigh_rm0 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 40)
         .set_frequency(2371.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -452,7 +548,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 2: [Mountain-basis-1 module]: This ridged-multifractal-noise function,
     // along with the mountain-basis-0 module, generates the individual
     // mountains.
-    let mountainousHigh_rm1 = RidgedMulti::new()
+    let mountainousHigh_rm1 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 41)
         .set_frequency(2341.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -463,7 +559,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // that only the maximum of the output values from the two ridged-
     // multifractal-noise functions contribute to the output value of this
     // subgroup.
-    let mountainousHigh_ma = Max::new(&mountainousHigh_rm0, &mountainousHigh_rm1);
+    let mountainousHigh_ma = Max::new(mountainousHigh_rm0, mountainousHigh_rm1);
 
     // 4: [Warped-high-mountains module]: This turbulence module warps the
     // output value from the high-mountains module, adding some detail to it.
@@ -475,7 +571,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 5: [High-mountainous-terrain subgroup]: Caches the output value from the
     // warped-high-mountains module.
-    let mountainousHigh = Cache::new(mountainousHigh_tu);
+    let mountainousHigh = &Cache::new(mountainousHigh_tu);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function subgroup: low mountainous terrain (4 noise functions)
@@ -490,7 +586,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Lowland-basis-0 module]: This ridged-multifractal-noise function,
     // along with the lowland-basis-1 module, produces the low mountainous
     // terrain.
-    let mountainousLow_rm0 = RidgedMulti::new()
+    let mountainousLow_rm0 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 50)
         .set_frequency(1381.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -499,7 +595,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Lowland-basis-1 module]: This ridged-multifractal-noise function,
     // along with the lowland-basis-0 module, produces the low mountainous
     // terrain.
-    let mountainousLow_rm1 = RidgedMulti::new()
+    let mountainousLow_rm1 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 51)
         .set_frequency(1427.0)
         .set_lacunarity(MOUNTAIN_LACUNARITY)
@@ -512,11 +608,11 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // - Flat areas appear when a positive and a negative output value are
     //   multiplied together.
     // - Ridges appear when two positive output values are multiplied together.
-    let mountainousLow_mu = Multiply::new(&mountainousLow_rm0, &mountainousLow_rm1);
+    let mountainousLow_mu = Multiply::new(mountainousLow_rm0, mountainousLow_rm1);
 
     // 4: [Low-mountainous-terrain subgroup]: Caches the output value from the
     // low-mountainous-terrain module.
-    let mountainousLow = Cache::new(mountainousLow_mu);
+    let mountainousLow = &Cache::new(mountainousLow_mu);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function subgroup: mountainous terrain (7 noise functions)
@@ -535,19 +631,19 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // areas becoming more-or-less flat with little variation. This will also
     // result in the low mountainous areas appearing at the lowest elevations in
     // this subgroup.
-    let mountainousTerrain_sb0 = ScaleBias::new(&mountainousLow).set_scale(0.03125).set_bias(-0.96875);
+    let mountainousTerrain_sb0 = &ScaleBias::new(mountainousLow).set_scale(0.03125).set_bias(-0.96875);
 
     // 2: [Scaled-high-mountainous-terrain module]: Next, this scale/bias module
     // scales the output value from the high-mountainous-terrain subgroup to 1/4
     // of its initial value and biases it so that its output value is usually
     // positive.
-    let mountainousTerrain_sb1 = ScaleBias::new(&mountainousHigh).set_scale(0.25).set_bias(0.25);
+    let mountainousTerrain_sb1 = &ScaleBias::new(mountainousHigh).set_scale(0.25).set_bias(0.25);
 
     // 3: [Added-high-mountainous-terrain module]: This addition module adds the
     // output value from the scaled-high-mountainous-terrain module to the
     // output value from the mountain-base-definition subgroup. Mountains now
     // appear all over the terrain.
-    let mountainousTerrain_ad = Add::new(&mountainousTerrain_sb1, &mountainBaseDef);
+    let mountainousTerrain_ad = &Add::new(mountainousTerrain_sb1, mountainBaseDef);
 
     // 4: [Combined-mountainous-terrain module]: Note that at this point, the
     // entire terrain is covered in high mountainous terrain, even at the low
@@ -560,14 +656,14 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // definition subgroup is higher than a set amount. Otherwise, this noise
     // module selects the output value from the scaled-low-mountainous-terrain
     // module.
-    let mountainousTerrain_se = Select::new(&mountainousTerrain_sb0, &mountainousTerrain_ad, &mountainBaseDef)
+    let mountainousTerrain_se = &Select::new(mountainousTerrain_sb0, mountainousTerrain_ad, mountainBaseDef)
         .set_bounds(-0.5, 999.5)
         .set_falloff(0.5);
 
     // 5: [Scaled-mountainous-terrain-module]: This scale/bias module slightly
     // reduces the range of the output value from the combined-mountainous-
     // terrain module, decreasing the heights of the mountain peaks.
-    let mountainousTerrain_sb2 = ScaleBias::new(&mountainousTerrain_se).set_scale(0.8).set_bias(0.0);
+    let mountainousTerrain_sb2 = &ScaleBias::new(mountainousTerrain_se).set_scale(0.8).set_bias(0.0);
 
     // 6: [Glaciated-mountainous-terrain-module]: This exponential-curve module
     // applies an exponential curve to the output value from the scaled-
@@ -575,9 +671,9 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // smoothly increase towards higher elevations, as if a glacier ground out
     // those mountains. This exponential-curve module expects the output value
     // to range from -1.0 to +1.0.
-    let mountainousTerrain_ex = Exponent::new(&mountainousTerrain_sb2).set_exponent(MOUNTAIN_GLACIATION);
+    let mountainousTerrain_ex = Exponent::new(mountainousTerrain_sb2).set_exponent(MOUNTAIN_GLACIATION);
 
-    let mountainousTerrain = Cache::new(mountainousTerrain_ex);
+    let mountainousTerrain = &Cache::new(mountainousTerrain_ex);
 
     // ////////////////////////////////////////////////////////////////////////
     // Function group: hilly terrain
@@ -593,7 +689,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     //
 
     // 1: [Hills module]: This billow-noise function generates the hills.
-    let hillyTerrain_bi = Billow::new()
+    let hillyTerrain_bi = &Billow::new()
         .set_seed(CURRENT_SEED + 60)
         .set_frequency(1663.0)
         .set_persistence(0.5)
@@ -604,14 +700,14 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // value from the hills module so that its hilltops are not too high. The
     // reason for this is that these hills are eventually added to the river
     // valleys (see below).
-    let hillyTerrain_sb0 = ScaleBias::new(&hillyTerrain_bi).set_scale(0.5).set_bias(0.5);
+    let hillyTerrain_sb0 = &ScaleBias::new(hillyTerrain_bi).set_scale(0.5).set_bias(0.5);
 
     // 3: [River-valley module]: This ridged-multifractal-noise function generates
     // the river valleys. It has a much lower frequency so that more hills will
     // appear in between the valleys. Note that this noise function generates
     // ridged-multifractal noise using only one octave; this information will be
     // important in the next step.
-    let hillyTerrain_rm = RidgedMulti::new()
+    let hillyTerrain_rm = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 61)
         .set_frequency(367.5)
         .set_lacunarity(HILLS_LACUNARITY)
@@ -624,10 +720,10 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // octave ridged-multifractal noise. The negative scaling factor inverts
     // the range of the output value, turning the ridges from the river-valley
     // module into valleys.
-    let hillyTerrain_sb1 = ScaleBias::new(&hillyTerrain_rm).set_scale(-2.0).set_bias(-1.0);
+    let hillyTerrain_sb1 = &ScaleBias::new(hillyTerrain_rm).set_scale(-2.0).set_bias(-1.0);
 
     // 5: [Low-flat module]: This low constant value is used by step 6.
-    let hillyTerrain_co = Constant::new(-1.0);
+    let hillyTerrain_co = &Constant::new(-1.0);
 
     // 6: [Mountains-and-valleys module]: This blender module merges the scaled-
     // hills module and the scaled-river-valley module together. It causes the
@@ -636,19 +732,19 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // scaled-hills module as the control module, causing the low-flat module to
     // appear in the lower areas and causing the scaled-river-valley module to
     // appear in the higher areas.
-    let hillyTerrain_bl = Blend::new(&hillyTerrain_co, &hillyTerrain_sb1, &hillyTerrain_sb0);
+    let hillyTerrain_bl = &Blend::new(hillyTerrain_co, hillyTerrain_sb1, hillyTerrain_sb0);
 
     // 7: [Scaled-hills-and-valleys module]: This scale/bias module slightly
     // reduces the range of the output value from the hills-and-valleys
     // module, decreasing the heights of the hilltops.
-    let hillyTerrain_sb2 = ScaleBias::new(&hillyTerrain_bl).set_scale(0.75).set_bias(-0.25);
+    let hillyTerrain_sb2 = &ScaleBias::new(hillyTerrain_bl).set_scale(0.75).set_bias(-0.25);
 
     // 8: [Increased-slope-hilly-terrain module]: To increase the hill slopes
     // at higher elevations, this exponential-curve module applies an
     // exponential curve to the output value the scaled-hills-and-valleys
     // module. This exponential-curve module expects the input value to range
     // from -1.0 to 1.0.
-    let hillyTerrain_ex = Exponent::new(&hillyTerrain_sb2).set_exponent(1.375);
+    let hillyTerrain_ex = Exponent::new(hillyTerrain_sb2).set_exponent(1.375);
 
     // 9: [Coarse-turbulence module]: This turbulence module warps the output
     // value from the increased-slope-hilly-terrain module, adding some
@@ -672,7 +768,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 11: [Hilly-terrain group]: Caches the output value from the warped-hilly-
     // terrain module. This is the output value for the entire hilly-terrain
     // group.
-    let hillyTerrain = Cache::new(hillyTerrain_tu1);
+    let hillyTerrain = &Cache::new(hillyTerrain_tu1);
 
     // ////////////////////////////////////////////////////////////////////////
     // Function group: plains terrain
@@ -693,7 +789,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Plains-basis-0 module]: This billow-noise function, along with the
     // plains-basis-1 module, produces the plains.
-    let plainsTerrain_bi0 = Billow::new()
+    let plainsTerrain_bi0 = &Billow::new()
         .set_seed(CURRENT_SEED + 70)
         .set_frequency(1097.5)
         .set_persistence(0.5)
@@ -704,11 +800,11 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // output value from the plains-basis-0 module positive since this output
     // value will be multiplied together with the positive-plains-basis-1
     // module.
-    let plainsTerrain_sb0 = ScaleBias::new(&plainsTerrain_bi0).set_scale(0.5).set_bias(0.5);
+    let plainsTerrain_sb0 = &ScaleBias::new(plainsTerrain_bi0).set_scale(0.5).set_bias(0.5);
 
     // 3: [Plains-basis-1 module]: This billow-noise function, along with the
     // plains-basis-2 module, produces the plains.
-    let plainsTerrain_bi1 = Billow::new()
+    let plainsTerrain_bi1 = &Billow::new()
         .set_seed(CURRENT_SEED + 71)
         .set_frequency(1097.5)
         .set_persistence(0.5)
@@ -719,21 +815,21 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // output value from the plains-basis-1 module positive since this output
     // value will be multiplied together with the positive-plains-basis-0
     // module.
-    let plainsTerrain_sb1 = ScaleBias::new(&plainsTerrain_bi1).set_scale(0.5).set_bias(0.5);
+    let plainsTerrain_sb1 = &ScaleBias::new(plainsTerrain_bi1).set_scale(0.5).set_bias(0.5);
 
     // 5: [Combined-plains-basis module]: This multiplication module combines
     // the two plains basis modules together.
-    let plainsTerrain_mu = Multiply::new(&plainsTerrain_sb0, &plainsTerrain_sb1);
+    let plainsTerrain_mu = &Multiply::new(plainsTerrain_sb0, plainsTerrain_sb1);
 
     // 6: [Rescaled-plains-basis module]: This scale/bias module maps the output
     // value that ranges from 0.0 to 1.0 back to a value that ranges from
     // -1.0 to +1.0.
-    let plainsTerrain_sb2 = ScaleBias::new(&plainsTerrain_mu).set_scale(2.0).set_bias(-1.0);
+    let plainsTerrain_sb2 = ScaleBias::new(plainsTerrain_mu).set_scale(2.0).set_bias(-1.0);
 
     // 7: [Plains-terrain group]: Caches the output value from the rescaled-
     // plains-basis module.  This is the output value for the entire plains-
     // terrain group.
-    let plainsTerrain = Cache::new(plainsTerrain_sb2);
+    let plainsTerrain = &Cache::new(plainsTerrain_sb2);
 
     // ////////////////////////////////////////////////////////////////////////
     // Function group: badlands terrain
@@ -751,7 +847,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Sand-dunes module]: This ridged-multifractal-noise function generates
     // sand dunes. This ridged-multifractal noise is generated with a single
     // octave, which makes very smooth dunes.
-    let badlandsSand_rm = RidgedMulti::new()
+    let badlandsSand_rm = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 80)
         .set_frequency(6163.5)
         .set_lacunarity(BADLANDS_LACUNARITY)
@@ -760,27 +856,27 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 2: [Scaled-sand-dunes module]: This scale/bias module shrinks the dune
     // heights by a small amount. This is necessary so that the subsequent
     // noise functions in this subgroup can add some detail to the dunes.
-    let badlandsSand_sb0 = ScaleBias::new(&badlandsSand_rm).set_scale(0.875).set_bias(0.0);
+    let badlandsSand_sb0 = &ScaleBias::new(badlandsSand_rm).set_scale(0.875).set_bias(0.0);
 
     // 3: [Dune-detail module]: This noise function uses Voronoi polygons to
     // generate the detail to add to the dunes. By enabling the distance
     // algorithm, small polygonal pits are generated; the edges of the pits
     // are joined to the edges of nearby pits.
-    let badlandsSand_wo = Worley::new(CURRENT_SEED + 81).set_frequency(16183.25).set_return_type(ReturnType::Distance);
+    let badlandsSand_wo = &Worley::new(CURRENT_SEED + 81).set_frequency(16183.25).set_return_type(ReturnType::Distance);
 
     // 4: [Scaled-dune-detail module]: This scale/bias module shrinks the dune
     // details by a large amount. This is necessary so that the subsequent
     // noise functions in this subgroup can add this detail to the sand-dunes
     // module.
-    let badlandsSand_sb1 = ScaleBias::new(&badlandsSand_wo).set_scale(0.25).set_bias(0.25);
+    let badlandsSand_sb1 = &ScaleBias::new(badlandsSand_wo).set_scale(0.25).set_bias(0.25);
 
     // 5: [Dunes-with-detail module]: This addition module combines the scaled-
     // sand-dunes module with the scaled-dune-detail module.
-    let badlandsSand_ad = Add::new(&badlandsSand_sb0, &badlandsSand_sb1);
+    let badlandsSand_ad = Add::new(badlandsSand_sb0, badlandsSand_sb1);
 
     // 6: [Badlands-sand subgroup]: Caches the output value from the dunes-with-
     // detail module.
-    let badlandsSand = Cache::new(badlandsSand_ad);
+    let badlandsSand = &Cache::new(badlandsSand_ad);
 
     // ////////////////////////////////////////////////////////////////////////
     // Function subgroup: badlands cliffs (7 noise functions)
@@ -793,7 +889,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Cliff-basis module]: This Perlin-noise function generates some coherent
     // noise that will be used to generate the cliffs.
-    let badlandsCliffs_fb = Fbm::new()
+    let badlandsCliffs_fb = &Fbm::new()
         .set_seed(CURRENT_SEED + 90)
         .set_frequency(CONTINENT_FREQUENCY * 839.0)
         .set_persistence(0.5)
@@ -805,7 +901,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // very shallow, but then its slope increases sharply. At the highest
     // elevations, the curve becomes very flat again. This produces the
     // stereotypical Utah-style desert cliffs.
-    let badlandsCliffs_cu = Curve::new(&badlandsCliffs_fb)
+    let badlandsCliffs_cu = &Curve::new(badlandsCliffs_fb)
         .add_control_point(-2.000, -2.000)
         .add_control_point(-1.000, -1.000)
         .add_control_point(-0.000, -0.750)
@@ -817,12 +913,12 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 3: [Clamped-cliffs module]: This clamping module makes the tops of the
     // cliffs very flat by clamping the output value from the cliff-shaping
     // module.
-    let badlandsCliffs_cl = Clamp::new(&badlandsCliffs_cu).set_bounds(-999.125, 0.875);
+    let badlandsCliffs_cl = &Clamp::new(badlandsCliffs_cu).set_bounds(-999.125, 0.875);
 
     // 4: [Terraced-cliffs module]: Next, this terracing module applies some
     // terraces to the clamped-cliffs module in the lower elevations before the
     // sharp cliff transition.
-    let badlandsCliffs_te = Terrace::new(&badlandsCliffs_cl)
+    let badlandsCliffs_te = Terrace::new(badlandsCliffs_cl)
         .add_control_point(-1.000)
         .add_control_point(-0.875)
         .add_control_point(-0.750)
@@ -850,7 +946,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 7: [Badlands-cliffs subgroup]: Caches the output value from the warped-
     // cliffs module.
-    let badlandsCliffs = Cache::new(badlandsCliffs_tu1);
+    let badlandsCliffs = &Cache::new(badlandsCliffs_tu1);
 
     // ////////////////////////////////////////////////////////////////////////
     // Function subgroup: badlands terrain (3 noise functions)
@@ -870,18 +966,18 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Scaled-sand-dunes module]: This scale/bias module considerably
     // flattens the output value from the badlands-sands subgroup and lowers
     // this value to near -1.0.
-    let badlandsTerrain_sb = ScaleBias::new(&badlandsSand).set_scale(0.25).set_bias(-0.75);
+    let badlandsTerrain_sb = &ScaleBias::new(badlandsSand).set_scale(0.25).set_bias(-0.75);
 
     // 2: [Dunes-and-cliffs module]: This maximum-value module causes the dunes
     // to appear in the low areas and the cliffs to appear in the high areas.
     // It does this by selecting the maximum of the output values from the
     // scaled-sand-dunes module and the badlands-cliffs subgroup.
-    let badlandsTerrain_ma = Max::new(&badlandsCliffs, &badlandsTerrain_sb);
+    let badlandsTerrain_ma = Max::new(badlandsCliffs, badlandsTerrain_sb);
 
     // 3: [Badlands-terrain group]: Caches the output value from the dunes-and-
     // cliffs module. This is the output value for the entire badlands-terrain
     // group.
-    let badlandsTerrain = Cache::new(badlandsTerrain_ma);
+    let badlandsTerrain = &Cache::new(badlandsTerrain_ma);
 
     //    debug::render_noise_module("complexplanet_images/12_2_badlandsTerrain.png",
     //                               &badlandsTerrain,
@@ -904,7 +1000,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Large-river-basis module]: This ridged-multifractal-noise function
     // creates the large, deep rivers.
-    let riverPositions_rm0 = RidgedMulti::new()
+    let riverPositions_rm0 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 100)
         .set_frequency(18.75)
         .set_lacunarity(CONTINENT_LACUNARITY)
@@ -915,7 +1011,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // inverted. This creates the rivers. This curve also compresses the edge of
     // the rivers, producing a sharp transition from the land to the river
     // bottom.
-    let riverPositions_cu0 = Curve::new(&riverPositions_rm0)
+    let riverPositions_cu0 = &Curve::new(riverPositions_rm0)
         .add_control_point(-2.000, 2.000)
         .add_control_point(-1.000, 1.000)
         .add_control_point(-0.125, 0.875)
@@ -925,7 +1021,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 3: [Small-river-basis module]: This ridged-multifractal-noise function
     // creates the small, shallow rivers.
-    let riverPositions_rm1 = RidgedMulti::new()
+    let riverPositions_rm1 = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 101)
         .set_frequency(43.25)
         .set_lacunarity(CONTINENT_LACUNARITY)
@@ -936,7 +1032,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // inverted. This creates the rivers. This curve also compresses the edge of
     // the rivers, producing a sharp transition from the land to the river
     // bottom.
-    let riverPositions_cu1 = Curve::new(&riverPositions_rm1)
+    let riverPositions_cu1 = &Curve::new(riverPositions_rm1)
         .add_control_point(-2.000, 2.0000)
         .add_control_point(-1.000, 1.5000)
         .add_control_point(-0.125, 1.4375)
@@ -948,7 +1044,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // rivers to cut into the large rivers.  It does this by selecting the
     // minimum output values from the large-river-curve module and the small-
     // river-curve module.
-    let riverPositions_mi = Min::new(&riverPositions_cu0, &riverPositions_cu1);
+    let riverPositions_mi = Min::new(riverPositions_cu0, riverPositions_cu1);
 
     // 6: [Warped-rivers module]: This turbulence module warps the output value
     //    from the combined-rivers module, which twists the rivers.  The high
@@ -962,7 +1058,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 7: [River-positions group]: Caches the output value from the warped-
     //    rivers module.  This is the output value for the entire river-
     //    positions group.
-    let riverPositions = Cache::new(riverPositions_tu);
+    let riverPositions = &Cache::new(riverPositions_tu);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function group: scaled mountainous terrain
@@ -988,13 +1084,13 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Base-scaled-mountainous-terrain module]: This scale/bias module
     // scales the output value from the mountainous-terrain group so that the
     // output value is measured in planetary elevation units.
-    let scaledMountainousTerrain_sb0 = ScaleBias::new(&mountainousTerrain).set_scale(0.125).set_bias(0.125);
+    let scaledMountainousTerrain_sb0 = &ScaleBias::new(mountainousTerrain).set_scale(0.125).set_bias(0.125);
 
     // 2: [Base-peak-modulation module]: At this stage, most mountain peaks have
     // roughly the same elevation. This BasicMulti module generates some
     // random values that will be used by subsequent noise functions to randomly
     // change the elevations of the mountain peaks.
-    let scaledMountainousTerrain_fb = Fbm::new()
+    let scaledMountainousTerrain_fb = &Fbm::new()
         .set_seed(CURRENT_SEED + 110)
         .set_frequency(14.5)
         .set_persistence(0.5)
@@ -1007,23 +1103,23 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // number of low values. This means there will be a few peaks with much
     // higher elevations than the majority of the peaks, making the terrain
     // features more varied.
-    let scaledMountainousTerrain_ex = Exponent::new(&scaledMountainousTerrain_fb).set_exponent(1.25);
+    let scaledMountainousTerrain_ex = &Exponent::new(scaledMountainousTerrain_fb).set_exponent(1.25);
 
     // 4: [Scaled-peak-modulation module]: This scale/bias module modifies the
     // range of the output value from the peak-modulation module so that it can
     // be used as the modulator for the peak-height-multiplier module. It is
     // important that this output value is not much lower than 1.0.
-    let scaledMountainousTerrain_sb1 = ScaleBias::new(&scaledMountainousTerrain_ex).set_scale(0.25).set_bias(1.0);
+    let scaledMountainousTerrain_sb1 = &ScaleBias::new(scaledMountainousTerrain_ex).set_scale(0.25).set_bias(1.0);
 
     // 5: [Peak-height-multiplier module]: This multiplier module modulates the
     // heights of the mountain peaks from the base-scaled-mountainous-terrain
     // module using the output value from the scaled-peak-modulation module.
-    let scaledMountainousTerrain_mu = Multiply::new(&scaledMountainousTerrain_sb0, &scaledMountainousTerrain_sb1);
+    let scaledMountainousTerrain_mu = Multiply::new(scaledMountainousTerrain_sb0, scaledMountainousTerrain_sb1);
 
     // 6: [Scaled-mountainous-terrain group]: Caches the output value from the
     // peak-height-multiplier module.  This is the output value for the
     // entire scaled-mountainous-terrain group.
-    let scaledMountainousTerrain = Cache::new(scaledMountainousTerrain_mu);
+    let scaledMountainousTerrain = &Cache::new(scaledMountainousTerrain_mu);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function group: scaled hilly terrain
@@ -1050,13 +1146,13 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Base-scaled-hilly-terrain module]: This scale/bias module scales the
     // output value from the hilly-terrain group so that this output value is
     // measured in planetary elevation units.
-    let scaledHillyTerrain_sb0 = ScaleBias::new(&hillyTerrain).set_scale(0.0625).set_bias(0.0625);
+    let scaledHillyTerrain_sb0 = &ScaleBias::new(hillyTerrain).set_scale(0.0625).set_bias(0.0625);
 
     // 2: [Base-hilltop-modulation module]: At this stage, most hilltops have
     // roughly the same elevation. This BasicMulti module generates some
     // random values that will be used by subsequent noise functions to
     // randomly change the elevations of the hilltops.
-    let scaledHillyTerrain_fb = Fbm::new()
+    let scaledHillyTerrain_fb = &Fbm::new()
         .set_seed(CURRENT_SEED + 120)
         .set_frequency(13.5)
         .set_persistence(0.5)
@@ -1069,23 +1165,23 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // number of low values. This means there will be a few hilltops with
     // much higher elevations than the majority of the hilltops, making the
     // terrain features more varied.
-    let scaledHillyTerrain_ex = Exponent::new(&scaledHillyTerrain_fb).set_exponent(1.25);
+    let scaledHillyTerrain_ex = &Exponent::new(scaledHillyTerrain_fb).set_exponent(1.25);
 
     // 4: [Scaled-hilltop-modulation module]: This scale/bias module modifies
     // the range of the output value from the hilltop-modulation module so that
     // it can be used as the modulator for the hilltop-height-multiplier module.
     // It is important that this output value is not much lower than 1.0.
-    let scaledHillyTerrain_sb1 = ScaleBias::new(&scaledHillyTerrain_ex).set_scale(0.5).set_bias(1.5);
+    let scaledHillyTerrain_sb1 = &ScaleBias::new(scaledHillyTerrain_ex).set_scale(0.5).set_bias(1.5);
 
     // 5: [Hilltop-height-multiplier module]: This multiplier module modulates
     // the heights of the hilltops from the base-scaled-hilly-terrain module
     // using the output value from the scaled-hilltop-modulation module.
-    let scaledHillyTerrain_mu = Multiply::new(&scaledHillyTerrain_sb0, &scaledHillyTerrain_sb1);
+    let scaledHillyTerrain_mu = Multiply::new(scaledHillyTerrain_sb0, scaledHillyTerrain_sb1);
 
     // 6: [Scaled-hilly-terrain group]: Caches the output value from the
     // hilltop-height-multiplier module. This is the output value for the entire
     // scaled-hilly-terrain group.
-    let scaledHillyTerrain = Cache::new(scaledHillyTerrain_mu);
+    let scaledHillyTerrain = &Cache::new(scaledHillyTerrain_mu);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function group: scaled plains terrain
@@ -1111,12 +1207,12 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Scaled-plains-terrain module]: This scale/bias module greatly
     // flattens the output value from the plains terrain.  This output value
     // is measured in planetary elevation units.
-    let scaledPlainsTerrain_sb0 = ScaleBias::new(&plainsTerrain).set_scale(0.00390625).set_bias(0.0078125);
+    let scaledPlainsTerrain_sb0 = ScaleBias::new(plainsTerrain).set_scale(0.00390625).set_bias(0.0078125);
 
     // 2: [Scaled-plains-terrain group]: Caches the output value from the
     // scaled-plains-terrain module. This is the output value for the entire
     // scaled-plains-terrain group.
-    let scaledPlainsTerrain = Cache::new(scaledPlainsTerrain_sb0);
+    let scaledPlainsTerrain = &Cache::new(scaledPlainsTerrain_sb0);
 
     // /////////////////////////////////////////////////////////////////////////
     // Function group: scaled badlands terrain
@@ -1142,12 +1238,12 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Scaled-badlands-terrain module]: This scale/bias module scales the
     // output value from the badlands-terrain group so that it is measured
     // in planetary elevation units.
-    let scaledBadlandsTerrain_sb = ScaleBias::new(&badlandsTerrain).set_scale(0.0625).set_bias(0.0625);
+    let scaledBadlandsTerrain_sb = ScaleBias::new(badlandsTerrain).set_scale(0.0625).set_bias(0.0625);
 
     // 2: [Scaled-badlands-terrain group]: Caches the output value from the
     // scaled-badlands-terrain module. This is the output value for the
     // entire scaled-badlands-terrain group.
-    let scaledBadlandsTerrain = Cache::new(scaledBadlandsTerrain_sb);
+    let scaledBadlandsTerrain = &Cache::new(scaledBadlandsTerrain_sb);
 
     //    debug::render_noise_module("complexplanet_images/17_0_scaledBadlandsTerrain\
     //    .png",
@@ -1177,7 +1273,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // The bottom of this terrace is defined as the bottom of the ocean;
     // subsequent noise functions will later add oceanic trenches to the bottom of
     // the ocean.
-    let continentalShelf_te = Terrace::new(&continentDef)
+    let continentalShelf_te = &Terrace::new(continentDef)
         .add_control_point(-1.0)
         .add_control_point(-0.75)
         .add_control_point(SHELF_LEVEL)
@@ -1194,7 +1290,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // value from the shelf-creator module so that its possible range is from
     // the bottom of the ocean to sea level. This is done because this subgroup
     // is only concerned about the oceans.
-    let continentalShelf_cl = Clamp::new(&continentalShelf_te).set_bounds(-0.75, SEA_LEVEL);
+    let continentalShelf_cl = &Clamp::new(continentalShelf_te).set_bounds(-0.75, SEA_LEVEL);
 
     //    debug::render_noise_module("complexplanet_images/18_1_continentalShelf_cl\
     //    .png",
@@ -1206,7 +1302,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 3: [Oceanic-trench-basis module]: This ridged-multifractal-noise function
     // generates some coherent noise that will be used to generate the oceanic
     // trenches. The ridges represent the bottom of the trenches.
-    let continentalShelf_rm = RidgedMulti::new()
+    let continentalShelf_rm = &RidgedMulti::new()
         .set_seed(CURRENT_SEED + 130)
         .set_frequency(CONTINENT_FREQUENCY * 4.375)
         .set_lacunarity(CONTINENT_LACUNARITY)
@@ -1223,7 +1319,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // from the oceanic-trench-basis-module so that the ridges become trenches.
     // This noise function also reduces the depth of the trenches so that their
     // depths are measured in planetary elevation units.
-    let continentalShelf_sb = ScaleBias::new(&continentalShelf_rm).set_scale(-0.125).set_bias(-0.125);
+    let continentalShelf_sb = &ScaleBias::new(continentalShelf_rm).set_scale(-0.125).set_bias(-0.125);
 
     //    debug::render_noise_module("complexplanet_images/18_3_continentalShelf_sb\
     //    .png",
@@ -1234,11 +1330,11 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 5: [Shelf-and-trenches module]: This addition module adds the oceanic
     // trenches to the clamped-sea-bottom module.
-    let continentalShelf_ad = Add::new(&continentalShelf_sb, &continentalShelf_cl);
+    let continentalShelf_ad = Add::new(continentalShelf_sb, continentalShelf_cl);
 
     // 6: [Continental-shelf subgroup]: Caches the output value from the shelf-
     //    and-trenches module.
-    let continentalShelf = Cache::new(continentalShelf_ad);
+    let continentalShelf = &Cache::new(continentalShelf_ad);
 
     //    debug::render_noise_module("complexplanet_images/18_4_continentalShelf.png",
     //                               &continentalShelf,
@@ -1260,7 +1356,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Base-scaled-continent-elevations module]: This scale/bias module
     // scales the output value from the continent-definition group so that it
     // is measured in planetary elevation units.
-    let baseContinentElev_sb = ScaleBias::new(&continentDef).set_scale(CONTINENT_HEIGHT_SCALE).set_bias(0.0);
+    let baseContinentElev_sb = &ScaleBias::new(continentDef).set_scale(CONTINENT_HEIGHT_SCALE).set_bias(0.0);
 
     //    debug::render_noise_module("complexplanet_images/19_0_baseContinentElev_sb\
     //    .png",
@@ -1276,13 +1372,13 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // continent-definition group is below the shelf level. Otherwise, it
     // selects the output value from the base-scaled-continent-elevations
     // module.
-    let baseContinentElev_se = Select::new(&baseContinentElev_sb, &continentalShelf, &continentDef)
+    let baseContinentElev_se = Select::new(baseContinentElev_sb, continentalShelf, continentDef)
         .set_bounds(SHELF_LEVEL - 1000.0, SHELF_LEVEL)
         .set_falloff(0.03125);
 
     // 3: [Base-continent-elevation subgroup]: Caches the output value from the
     // base-continent-with-oceans module.
-    let baseContinentElev = Cache::new(baseContinentElev_se);
+    let baseContinentElev = &Cache::new(baseContinentElev_se);
 
     //    debug::render_noise_module("complexplanet_images/19_1_baseContinentElev\
     //    .png",
@@ -1304,11 +1400,11 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Continents-with-plains module]: This addition module adds the scaled-
     // plains-terrain group to the base-continent-elevation subgroup.
-    let continentsWithPlains_ad = Add::new(&baseContinentElev, &scaledPlainsTerrain);
+    let continentsWithPlains_ad = Add::new(baseContinentElev, scaledPlainsTerrain);
 
     // 2: [Continents-with-plains subgroup]: Caches the output value from the
     // continents-with-plains module.
-    let continentsWithPlains = Cache::new(continentsWithPlains_ad);
+    let continentsWithPlains = &Cache::new(continentsWithPlains_ad);
 
     //    debug::render_noise_module("complexplanet_images/20_0_continentsWithPlains\
     //    .png",
@@ -1330,7 +1426,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Continents-with-hills module]: This addition module adds the scaled-
     // hilly-terrain group to the base-continent-elevation subgroup.
-    let continentsWithHills_ad = Add::new(&baseContinentElev, &scaledHillyTerrain);
+    let continentsWithHills_ad = &Add::new(baseContinentElev, scaledHillyTerrain);
 
     //    debug::render_noise_module("complexplanet_images/21_0_continentsWithHills_ad.png",
     //                               &continentsWithHills_ad,
@@ -1344,13 +1440,13 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // output value from the terrain-type-definition group is above a certain
     // value. Otherwise, it selects the output value from the continents-with-
     // plains subgroup.
-    let continentsWithHills_se = Select::new(&continentsWithPlains, &continentsWithHills_ad, &terrainTypeDef)
+    let continentsWithHills_se = Select::new(continentsWithPlains, continentsWithHills_ad, terrainTypeDef)
         .set_bounds(1.0 - HILLS_AMOUNT, 1001.0 - HILLS_AMOUNT)
         .set_falloff(0.25);
 
     // 3: [Continents-with-hills subgroup]: Caches the output value from the
     // select-high-elevations module.
-    let continentsWithHills = Cache::new(continentsWithHills_se);
+    let continentsWithHills = &Cache::new(continentsWithHills_se);
 
     //    debug::render_noise_module("complexplanet_images/21_1_continentsWithHills\
     //    .png",
@@ -1373,7 +1469,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Continents-and-mountains module]: This addition module adds the
     // scaled-mountainous-terrain group to the base-continent-elevation
     // subgroup.
-    let continentsWithMountains_ad0 = Add::new(&baseContinentElev, &scaledMountainousTerrain);
+    let continentsWithMountains_ad0 = &Add::new(baseContinentElev, scaledMountainousTerrain);
 
     //    debug::render_noise_module("complexplanet_images/22_0_continentsWithMountains_ad0.png",
     //                               &continentsWithMountains_ad0,
@@ -1386,7 +1482,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // output value is used by a subsequent noise function to add additional
     // height to the mountains based on the current continent elevation. The
     // higher the continent elevation, the higher the mountains.
-    let continentsWithMountains_cu = Curve::new(&continentDef)
+    let continentsWithMountains_cu = &Curve::new(continentDef)
         .add_control_point(-1.0, -0.0625)
         .add_control_point(0.0, 0.0000)
         .add_control_point(1.0 - MOUNTAINS_AMOUNT, 0.0625)
@@ -1401,7 +1497,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 3: [Add-increased-mountain-heights module]: This addition module adds the
     // increased-mountain-heights module to the continents-and-mountains module.
     // The highest continent elevations now have the highest mountains.
-    let continentsWithMountains_ad1 = Add::new(&continentsWithMountains_ad0, &continentsWithMountains_cu);
+    let continentsWithMountains_ad1 = &Add::new(continentsWithMountains_ad0, continentsWithMountains_cu);
 
     //    debug::render_noise_module("complexplanet_images/22_2_continentsWithMountains_ad1.png",
     //                               &continentsWithMountains_ad1,
@@ -1416,13 +1512,13 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // above a certain value. Otherwise, it selects the output value from the
     // continents-with-hills subgroup. Note that the continents-with-hills
     // subgroup also contains the plains terrain.
-    let continentsWithMountains_se = Select::new(&continentsWithHills, &continentsWithMountains_ad1, &terrainTypeDef)
+    let continentsWithMountains_se = Select::new(continentsWithHills, continentsWithMountains_ad1, terrainTypeDef)
         .set_bounds(1.0 - MOUNTAINS_AMOUNT, 1001.0 - MOUNTAINS_AMOUNT)
         .set_falloff(0.25);
 
     // 5: [Continents-with-mountains subgroup]: Caches the output value from the
     // select-high-elevations module.
-    let continentsWithMountains = Cache::new(continentsWithMountains_se);
+    let continentsWithMountains = &Cache::new(continentsWithMountains_se);
 
     //    debug::render_noise_module("complexplanet_images/22_3_continentsWithMountains.png",
     //                               &continentsWithMountains,
@@ -1444,7 +1540,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Badlands-positions module]: This BasicMulti module generates some
     // random noise, which is used by subsequent noise functions to specify the
     // locations of the badlands.
-    let continentsWithBadlands_bm = Fbm::new()
+    let continentsWithBadlands_bm = &Fbm::new()
         .set_seed(CURRENT_SEED + 140)
         .set_frequency(16.5)
         .set_persistence(0.5)
@@ -1460,7 +1556,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 2: [Continents-and-badlands module]:  This addition module adds the
     // scaled-badlands-terrain group to the base-continent-elevation
     // subgroup.
-    let continentsWithBadlands_ad = Add::new(&baseContinentElev, &scaledBadlandsTerrain);
+    let continentsWithBadlands_ad = &Add::new(baseContinentElev, scaledBadlandsTerrain);
 
     //    debug::render_noise_module("complexplanet_images/23_1_continentsWithBadlands_ad.png",
     //                               &continentsWithBadlands_ad,
@@ -1477,7 +1573,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // from the continents-with-mountains subgroup. There is also a wide
     // transition between these two noise functions so that the badlands can blend
     // into the rest of the terrain on the continents.
-    let continentsWithBadlands_se = Select::new(&continentsWithMountains, &continentsWithBadlands_ad, &continentsWithBadlands_bm)
+    let continentsWithBadlands_se = &Select::new(continentsWithMountains, continentsWithBadlands_ad, continentsWithBadlands_bm)
         .set_bounds(1.0 - BADLANDS_AMOUNT, 1001.0 - BADLANDS_AMOUNT)
         .set_falloff(0.25);
 
@@ -1493,11 +1589,11 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // mountains subgroup and the select-badlands-positions modules contribute
     // to the output value of this subgroup. One side effect of this process is
     // that the badlands will not appear in mountainous terrain.
-    let continentsWithBadlands_ma = Max::new(&continentsWithMountains, &continentsWithBadlands_se);
+    let continentsWithBadlands_ma = Max::new(continentsWithMountains, continentsWithBadlands_se);
 
     // 5: [Continents-with-badlands subgroup]: Caches the output value from the
     //    apply-badlands module.
-    let continentsWithBadlands = Cache::new(continentsWithBadlands_ma);
+    let continentsWithBadlands = &Cache::new(continentsWithBadlands_ma);
 
     //    debug::render_noise_module("complexplanet_images/23_3_continentsWithBadlands.png",
     //                               &continentsWithBadlands,
@@ -1519,7 +1615,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // 1: [Scaled-rivers module]: This scale/bias module scales the output value
     // from the river-positions group so that it is measured in planetary
     // elevation units and is negative; this is required for step 2.
-    let continentsWithRivers_sb = ScaleBias::new(&riverPositions).set_scale(RIVER_DEPTH / 2.0).set_bias(-RIVER_DEPTH / 2.0);
+    let continentsWithRivers_sb = &ScaleBias::new(riverPositions).set_scale(RIVER_DEPTH / 2.0).set_bias(-RIVER_DEPTH / 2.0);
 
     //    debug::render_noise_module("complexplanet_images/24_0_continentsWithRivers_sb.png",
     //                               &continentsWithRivers_sb,
@@ -1531,7 +1627,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // rivers to the continents-with-badlands subgroup. Because the scaled-
     // rivers module only outputs a negative value, the scaled-rivers module
     // carves the rivers out of the terrain.
-    let continentsWithRivers_ad = Add::new(&continentsWithBadlands, &continentsWithRivers_sb);
+    let continentsWithRivers_ad = &Add::new(continentsWithBadlands, continentsWithRivers_sb);
 
     //    debug::render_noise_module("complexplanet_images/24_1_continentsWithRivers_ad.png",
     //                               &continentsWithRivers_ad,
@@ -1546,7 +1642,7 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
     // continents-with-badlands subgroup is far from sea level.  Otherwise,
     // this selector module selects the output value from the add-rivers-to-
     // continents module.
-    let continentsWithRivers_se = Select::<f64, 3>::new(&continentsWithBadlands, &continentsWithRivers_ad, &continentsWithBadlands)
+    let continentsWithRivers_se = Select::<f64, 3>::new(continentsWithBadlands, continentsWithRivers_ad, continentsWithBadlands)
         .set_bounds(SEA_LEVEL, CONTINENT_HEIGHT_SCALE + SEA_LEVEL)
         .set_falloff(CONTINENT_HEIGHT_SCALE - SEA_LEVEL);
 
@@ -1563,19 +1659,61 @@ pub fn get_noise_map(chunk_offset: [i32; 3], seed: u32) -> NoiseMap {
 
     // 1: [Unscaled-final-planet subgroup]: Caches the output value from the
     //    continent-with-rivers subgroup.
-    let unscaledFinalPlanet = Cache::new(continentsWithRivers);
+    let unscaledFinalPlanet = &Cache::new(continentsWithRivers);
 
-    let bound_size = 0.005;
-    let x_min = chunk_offset[0] as f64 / 200.0;
-    let x_max = x_min + bound_size;
-
-    let z_min = chunk_offset[2] as f64 / 200.0;
-    let z_max = z_min + bound_size;
-    let noise_map = PlaneMapBuilder::new(&unscaledFinalPlanet)
-        .set_size(CHUNK_X_SIZE, CHUNK_Z_SIZE)
-        .set_x_bounds(x_min, x_max)
-        .set_y_bounds(z_min, z_max)
-        .build();
+    f(unscaledFinalPlanet)
+}
 
-    return noise_map;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(noise: &PlanetNoise, chunk_offset: [i32; 3]) -> Vec<f64> {
+        let noise_map = noise.get_noise_map(chunk_offset, 0);
+        let mut values = Vec::new();
+        for x in 0..CHUNK_X_SIZE {
+            for z in 0..CHUNK_Z_SIZE {
+                values.push(noise_map.get_value(x, z));
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_terrain() {
+        let first = PlanetNoise::new(42, PlanetParameters::default());
+        let second = PlanetNoise::new(42, PlanetParameters::default());
+        for chunk_offset in [[0, 0, 0], [3, 0, -2], [-7, 0, 5]].iter() {
+            let values = samples(&first, *chunk_offset);
+            assert!(values.iter().all(|value| value.is_finite()));
+            assert_eq!(values, samples(&second, *chunk_offset));
+            // Building the graph again gives the same values
+            assert_eq!(values, samples(&first, *chunk_offset));
+        }
+        assert_ne!(
+            samples(&first, [0, 0, 0]),
+            samples(&PlanetNoise::new(43, PlanetParameters::default()), [0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn border_columns_are_the_columns_of_the_neighbouring_chunks() {
+        let noise = PlanetNoise::new(42, PlanetParameters::default());
+        let map = noise.get_noise_map([2, 0, 3], 1);
+        let chunk = noise.get_noise_map([2, 0, 3], 0);
+        let left = noise.get_noise_map([1, 0, 3], 0);
+        let front = noise.get_noise_map([2, 0, 4], 0);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        for x in 0..CHUNK_X_SIZE {
+            for z in 0..CHUNK_Z_SIZE {
+                assert!(close(map.get_value(x + 1, z + 1), chunk.get_value(x, z)));
+            }
+        }
+        for z in 0..CHUNK_Z_SIZE {
+            assert!(close(map.get_value(0, z + 1), left.get_value(CHUNK_X_SIZE - 1, z)));
+        }
+        for x in 0..CHUNK_X_SIZE {
+            assert!(close(map.get_value(x + 1, CHUNK_Z_SIZE + 1), front.get_value(x, 0)));
+        }
+    }
 }
@@ -0,0 +1,102 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{block_registry::*, chunk::*, decoration::position_hash, world_metadata::WorldSettings};
+
+/// Height of a vein bound, deep ores follow the bottom of the world and the others the sea level.
+#[derive(Copy, Clone, Debug)]
+pub enum OreHeight {
+    /// Blocks above the bottom of the world.
+    BOTTOM(i32),
+    /// Blocks above (or below when negative) the sea level.
+    SEA(i32),
+}
+
+impl OreHeight {
+    fn resolve(self, settings: &WorldSettings) -> i32 {
+        match self {
+            OreHeight::BOTTOM(height) => height,
+            OreHeight::SEA(height) => settings.sea_level as i32 + height,
+        }
+    }
+}
+
+/// A kind of vein scattered through the rock.
+#[derive(Copy, Clone, Debug)]
+pub struct OreDefinition {
+    pub block_type: BlockType,
+    /// Veins start between `min_y` (inclusive) and `max_y` (exclusive).
+    pub min_y: OreHeight,
+    pub max_y: OreHeight,
+    pub veins_per_chunk: u32,
+    /// Number of steps of the random walk drawing a vein, roughly its block count.
+    pub vein_size: u32,
+}
+
+/// Ores from the most common to the rarest. Gravel pockets are generated the same way.
+pub const ORES: [OreDefinition; 5] = [
+    OreDefinition {
+        block_type: BlockType::GRAVEL,
+        min_y: OreHeight::BOTTOM(5),
+        max_y: OreHeight::SEA(40),
+        veins_per_chunk: 6,
+        vein_size: 24,
+    },
+    OreDefinition {
+        block_type: BlockType::COAL_ORE,
+        min_y: OreHeight::BOTTOM(5),
+        max_y: OreHeight::SEA(40),
+        veins_per_chunk: 16,
+        vein_size: 12,
+    },
+    OreDefinition {
+        block_type: BlockType::IRON_ORE,
+        min_y: OreHeight::BOTTOM(5),
+        max_y: OreHeight::SEA(0),
+        veins_per_chunk: 10,
+        vein_size: 8,
+    },
+    OreDefinition {
+        block_type: BlockType::GOLD_ORE,
+        min_y: OreHeight::BOTTOM(5),
+        max_y: OreHeight::BOTTOM(40),
+        veins_per_chunk: 3,
+        vein_size: 6,
+    },
+    OreDefinition {
+        block_type: BlockType::DIAMOND_ORE,
+        min_y: OreHeight::BOTTOM(5),
+        max_y: OreHeight::BOTTOM(20),
+        veins_per_chunk: 1,
+        vein_size: 5,
+    },
+];
+
+/// Replaces rock with ore veins. Veins are seeded per chunk and ore, and clipped to the chunk.
+pub fn place_ores(blocks: &Blocks, offset: [i32; 3], seed: u32, settings: &WorldSettings) {
+    for (i, ore) in ORES.iter().enumerate() {
+        let min_y = ore.min_y.resolve(settings).max(0);
+        let max_y = ore.max_y.resolve(settings).min(settings.world_height as i32);
+        if min_y >= max_y {
+            continue;
+        }
+
+        let mut rng = StdRng::seed_from_u64(position_hash(seed, offset[0], offset[2], 100 + i as u32));
+        for _ in 0..ore.veins_per_chunk {
+            let mut x = rng.gen_range(0..CHUNK_X_SIZE as i32);
+            let mut y = rng.gen_range(min_y..max_y);
+            let mut z = rng.gen_range(0..CHUNK_Z_SIZE as i32);
+
+            for _ in 0..ore.vein_size {
+                if x >= 0 && z >= 0 && y >= min_y && x < CHUNK_X_SIZE as i32 && z < CHUNK_Z_SIZE as i32 && y < settings.world_height as i32 {
+                    let mut block = blocks[y as usize][x as usize][z as usize].lock().unwrap();
+                    if block.material_type == BlockType::ROCK {
+                        block.update(ore.block_type);
+                    }
+                }
+                x += rng.gen_range(-1..=1);
+                y += rng.gen_range(-1..=1);
+                z += rng.gen_range(-1..=1);
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::*;
 use cgmath::*;
 use wgpu::util::DeviceExt;
 
-use crate::{bitmap_font::*, renderer::*};
+use crate::{biome::*, bitmap_font::*, renderer::*, tick::TickStats};
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct OverlayInfoVertex {
@@ -99,7 +99,8 @@ impl OverlayInfo {
     pub fn new(renderer: &Renderer) -> Result<Self> {
         let bitmap_font = BitmapFont::new(&renderer.device, &renderer.queue)?;
 
-        let display_string = "FPS=999999|X=999999|Y=999999|Z=999999".to_string();
+        // Longest possible string, the vertex buffer is sized from it
+        let display_string = "FPS=999999|TPS=99|MSPT=9999.9|X=999999|Y=999999|Z=999999|BIOME=MOUNTAINS".to_string();
         let default_param = DisplayParameters::new(display_string.clone(), -1.0, 0.9, 0.5, 0.5);
 
         let vertices = create_vertices(&bitmap_font, default_param)?;
@@ -151,10 +152,16 @@ impl OverlayInfo {
         self.screen_width = new_size.width;
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue, fps: u32, camera_position: Point3<f32>) -> Result<()> {
+    pub fn update(&mut self, queue: &wgpu::Queue, fps: u32, tick_stats: &TickStats, camera_position: Point3<f32>, biome: Option<Biome>) -> Result<()> {
         self.display_string = format!(
-            "FPS={}|X={}|Y={}|Z={}",
-            fps, camera_position.x as i32, camera_position.y as i32, camera_position.z as i32
+            "FPS={}|TPS={}|MSPT={:.1}|X={}|Y={}|Z={}|BIOME={}",
+            fps,
+            tick_stats.current_tps,
+            tick_stats.current_mspt.min(9999.9),
+            camera_position.x as i32,
+            camera_position.y as i32,
+            camera_position.z as i32,
+            biome.map_or("NONE", |biome| biome.name())
         );
 
         let x_scale = OVERLAY_INFO_PIXEL_SIZE / self.screen_width as f32;
@@ -0,0 +1,335 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{block_registry::*, block_updates::BlockGrid};
+
+/// Free blocks needed above a walkable block, mobs are less than 2 blocks tall.
+pub const CLEARANCE: i32 = 2;
+/// Blocks a mob climbs with a jump.
+pub const JUMP_HEIGHT: i32 = 1;
+/// Blocks a mob steps down at once.
+pub const MAX_DROP: i32 = 3;
+/// Extra cost of a jump, mobs prefer walking around a block to climbing it.
+const JUMP_COST: u32 = 1;
+/// Nodes a single search expands before giving up, the goal is then considered unreachable.
+pub const MAX_SEARCH_NODES: usize = 1000;
+/// Nodes expanded by all the searches of a tick, searches over it wait for the next tick.
+pub const SEARCH_BUDGET_PER_TICK: usize = 4000;
+/// Ticks a path stays cached, blocks changing next to it drop it sooner.
+const PATH_CACHE_TICKS: u64 = 200;
+
+const DIRECTIONS: [[i32; 3]; 4] = [[1, 0, 0], [-1, 0, 0], [0, 0, 1], [0, 0, -1]];
+
+fn add(position: [i32; 3], offset: [i32; 3]) -> [i32; 3] {
+    [position[0] + offset[0], position[1] + offset[1], position[2] + offset[2]]
+}
+
+/// Blocks a mob can be in: anything not solid but water. Unloaded blocks are not.
+fn is_passable(grid: &impl BlockGrid, position: [i32; 3]) -> bool {
+    matches!(grid.block_type(position), Some(block_type) if !block_type.is_solid() && block_type != BlockType::WATER)
+}
+
+fn is_ground(grid: &impl BlockGrid, position: [i32; 3]) -> bool {
+    matches!(grid.block_type(position), Some(block_type) if block_type.is_solid())
+}
+
+/// Whether a mob can stand with its feet in the block at `position`.
+pub fn is_walkable(grid: &impl BlockGrid, position: [i32; 3]) -> bool {
+    is_ground(grid, add(position, [0, -1, 0])) && (0..CLEARANCE).all(|y| is_passable(grid, add(position, [0, y, 0])))
+}
+
+/// Blocks reachable in one move from `position` with the cost of the move: walking to a side,
+/// jumping up onto a block or stepping down a few blocks.
+fn neighbours(grid: &impl BlockGrid, position: [i32; 3]) -> Vec<([i32; 3], u32)> {
+    let mut neighbours = Vec::with_capacity(DIRECTIONS.len());
+    for direction in DIRECTIONS.iter() {
+        let next = add(position, *direction);
+
+        // Jumping needs room above the head before moving sideways
+        let jump = (1..=JUMP_HEIGHT)
+            .take_while(|height| is_passable(grid, add(position, [0, CLEARANCE + height - 1, 0])))
+            .map(|height| add(next, [0, height, 0]))
+            .find(|target| is_walkable(grid, *target));
+        if let Some(target) = jump {
+            neighbours.push((target, 1 + JUMP_COST));
+            continue;
+        }
+
+        if !(0..CLEARANCE).all(|y| is_passable(grid, add(next, [0, y, 0]))) {
+            continue;
+        }
+        for drop in 0..=MAX_DROP {
+            let target = add(next, [0, -drop, 0]);
+            if !is_passable(grid, target) {
+                break;
+            }
+            if is_ground(grid, add(target, [0, -1, 0])) {
+                neighbours.push((target, 1));
+                break;
+            }
+        }
+    }
+    neighbours
+}
+
+/// Every move goes one block sideways for a cost of at least 1, so the horizontal distance never
+/// overestimates the cost left.
+fn heuristic(position: [i32; 3], goal: [i32; 3]) -> u32 {
+    ((position[0] - goal[0]).abs() + (position[2] - goal[2]).abs()) as u32
+}
+
+/// A* search from `start` to `goal`, both being feet positions. Returns the blocks to go through
+/// after `start`, `goal` last, and the number of nodes expanded, `None` when the goal was not
+/// reached within `max_nodes`.
+pub fn find_path(grid: &impl BlockGrid, start: [i32; 3], goal: [i32; 3], max_nodes: usize) -> (Option<Vec<[i32; 3]>>, usize) {
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<[i32; 3], [i32; 3]> = HashMap::new();
+    let mut costs: HashMap<[i32; 3], u32> = HashMap::new();
+    costs.insert(start, 0);
+    // Ties go to the node closest to the goal, then to the lowest position to stay deterministic
+    open.push(Reverse((heuristic(start, goal), heuristic(start, goal), start)));
+
+    let mut expanded = 0;
+    while let Some(Reverse((estimate, _, position))) = open.pop() {
+        // Queued again since with a lower cost, it was already expanded from there
+        let cost = costs[&position];
+        if estimate > cost + heuristic(position, goal) {
+            continue;
+        }
+        if position == goal {
+            let mut path = vec![goal];
+            let mut current = goal;
+            while let Some(previous) = came_from.get(&current) {
+                if *previous == start {
+                    break;
+                }
+                path.push(*previous);
+                current = *previous;
+            }
+            path.reverse();
+            return (Some(path), expanded);
+        }
+        if expanded >= max_nodes {
+            break;
+        }
+        expanded += 1;
+
+        for (next, move_cost) in neighbours(grid, position) {
+            let next_cost = cost + move_cost;
+            if !matches!(costs.get(&next), Some(known_cost) if *known_cost <= next_cost) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, position);
+                let remaining = heuristic(next, goal);
+                open.push(Reverse((next_cost + remaining, remaining, next)));
+            }
+        }
+    }
+    (None, expanded)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathResult {
+    /// Blocks to go through, the goal last.
+    FOUND(Vec<[i32; 3]>),
+    UNREACHABLE,
+    /// The search budget of this tick is spent, ask again on the next one.
+    DEFERRED,
+}
+
+#[derive(Debug)]
+struct CachedPath {
+    path: Option<Vec<[i32; 3]>>,
+    tick: u64,
+}
+
+/// Paths asked by the mobs, cached and searched within a budget of nodes per tick so that many
+/// mobs looking for a path at once do not slow the tick down.
+#[derive(Debug, Default)]
+pub struct Pathfinder {
+    tick: u64,
+    budget: usize,
+    /// Paths and unreachable goals by (start, goal).
+    cache: HashMap<([i32; 3], [i32; 3]), CachedPath>,
+}
+
+impl Pathfinder {
+    /// Starts a tick with a new search budget and forgets the old paths.
+    pub fn tick(&mut self) {
+        self.tick += 1;
+        self.budget = SEARCH_BUDGET_PER_TICK;
+        let tick = self.tick;
+        self.cache.retain(|_, cached| tick - cached.tick < PATH_CACHE_TICKS);
+    }
+
+    /// Path from `start` to `goal`, from the cache or searched if the budget left for this tick
+    /// covers a whole search.
+    pub fn find_path(&mut self, grid: &impl BlockGrid, start: [i32; 3], goal: [i32; 3]) -> PathResult {
+        if let Some(cached) = self.cache.get(&(start, goal)) {
+            return match &cached.path {
+                Some(path) => PathResult::FOUND(path.clone()),
+                None => PathResult::UNREACHABLE,
+            };
+        }
+        if self.budget < MAX_SEARCH_NODES {
+            return PathResult::DEFERRED;
+        }
+
+        let (path, expanded) = find_path(grid, start, goal, MAX_SEARCH_NODES);
+        self.budget -= expanded;
+        self.cache.insert(
+            (start, goal),
+            CachedPath {
+                path: path.clone(),
+                tick: self.tick,
+            },
+        );
+        match path {
+            Some(path) => PathResult::FOUND(path),
+            None => PathResult::UNREACHABLE,
+        }
+    }
+
+    /// Drops the paths going next to a block that changed, and the unreachable goals that may now
+    /// be reachable.
+    pub fn block_changed(&mut self, position: [i32; 3]) {
+        self.cache.retain(|_, cached| match &cached.path {
+            Some(path) => !path.iter().any(|node| (0..3).all(|axis| (node[axis] - position[axis]).abs() <= CLEARANCE)),
+            None => false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const START: [i32; 3] = [0, 1, 0];
+
+    /// Rock ground under y = 1 from -`size` to `size`.
+    fn flat(size: i32) -> MapGrid {
+        let mut grid = MapGrid::new([-size, -8, -size], [size, 8, size]);
+        grid.fill([-size, 0, -size], [size, 0, size], BlockType::ROCK);
+        grid
+    }
+
+    /// Whether every move of the path goes one block sideways.
+    fn is_connected(start: [i32; 3], path: &[[i32; 3]]) -> bool {
+        let mut previous = start;
+        path.iter().all(|position| {
+            let sideways = (position[0] - previous[0]).abs() + (position[2] - previous[2]).abs();
+            previous = *position;
+            sideways == 1
+        })
+    }
+
+    #[test]
+    fn path_on_flat_ground() {
+        let grid = flat(16);
+        let (path, _) = find_path(&grid, START, [5, 1, 3], MAX_SEARCH_NODES);
+        let path = path.unwrap();
+        assert_eq!(path.len(), 8);
+        assert_eq!(path.last(), Some(&[5, 1, 3]));
+        assert!(is_connected(START, &path));
+        assert!(path.iter().all(|position| position[1] == 1));
+    }
+
+    #[test]
+    fn jumps_up_one_block_but_not_two() {
+        let mut grid = flat(16);
+        grid.fill([3, 1, -16], [16, 1, 16], BlockType::ROCK);
+        let (path, _) = find_path(&grid, START, [5, 2, 0], MAX_SEARCH_NODES);
+        let path = path.unwrap();
+        assert_eq!(path, vec![[1, 1, 0], [2, 1, 0], [3, 2, 0], [4, 2, 0], [5, 2, 0]]);
+
+        grid.fill([3, 2, -16], [16, 2, 16], BlockType::ROCK);
+        let (path, expanded) = find_path(&grid, START, [5, 3, 0], MAX_SEARCH_NODES);
+        assert_eq!(path, None);
+        assert!(expanded < MAX_SEARCH_NODES);
+    }
+
+    #[test]
+    fn steps_down_three_blocks_but_not_four() {
+        let mut grid = MapGrid::new([-16, -8, -16], [16, 8, 16]);
+        grid.fill([-16, 0, -16], [2, 0, 16], BlockType::ROCK);
+        grid.fill([3, -3, -16], [16, -3, 16], BlockType::ROCK);
+        let (path, _) = find_path(&grid, START, [5, -2, 0], MAX_SEARCH_NODES);
+        assert_eq!(path, Some(vec![[1, 1, 0], [2, 1, 0], [3, -2, 0], [4, -2, 0], [5, -2, 0]]));
+
+        grid.fill([3, -3, -16], [16, -3, 16], BlockType::AIR);
+        grid.fill([3, -4, -16], [16, -4, 16], BlockType::ROCK);
+        let (path, _) = find_path(&grid, START, [5, -3, 0], MAX_SEARCH_NODES);
+        assert_eq!(path, None);
+    }
+
+    #[test]
+    fn goes_around_water() {
+        let mut grid = flat(16);
+        grid.fill([3, 0, -2], [3, 0, 2], BlockType::WATER);
+        let (path, _) = find_path(&grid, START, [5, 1, 0], MAX_SEARCH_NODES);
+        let path = path.unwrap();
+        assert!(is_connected(START, &path));
+        assert!(path.len() > 5);
+        assert!(!path.iter().any(|position| position[0] == 3 && position[2].abs() <= 2));
+
+        // Water across the whole way cannot be crossed
+        grid.fill([3, 0, -16], [3, 0, 16], BlockType::WATER);
+        let (path, _) = find_path(&grid, START, [5, 1, 0], MAX_SEARCH_NODES);
+        assert_eq!(path, None);
+    }
+
+    /// Open ground with goals walled in around [40, 1, 40].
+    fn walled_goals() -> (MapGrid, Vec<[i32; 3]>) {
+        let mut grid = flat(64);
+        grid.fill([38, 1, 38], [42, 2, 42], BlockType::ROCK);
+        grid.fill([39, 1, 39], [41, 2, 41], BlockType::AIR);
+        (grid, vec![[39, 1, 39], [40, 1, 40], [41, 1, 41]])
+    }
+
+    #[test]
+    fn searches_give_up_after_max_nodes() {
+        let (grid, goals) = walled_goals();
+        let (path, expanded) = find_path(&grid, START, goals[0], MAX_SEARCH_NODES);
+        assert_eq!(path, None);
+        assert_eq!(expanded, MAX_SEARCH_NODES);
+
+        let mut pathfinder = Pathfinder::default();
+        pathfinder.tick();
+        assert_eq!(pathfinder.find_path(&grid, START, goals[0]), PathResult::UNREACHABLE);
+    }
+
+    #[test]
+    fn paths_are_cached_within_a_budget() {
+        let (grid, goals) = walled_goals();
+        let mut pathfinder = Pathfinder::default();
+        pathfinder.tick();
+        let found = pathfinder.find_path(&grid, START, [5, 1, 0]);
+        let path = match &found {
+            PathResult::FOUND(path) => path.clone(),
+            _ => panic!("no path on flat ground"),
+        };
+
+        // Unreachable goals spend the budget of the tick
+        for goal in goals.iter() {
+            assert_eq!(pathfinder.find_path(&grid, START, *goal), PathResult::UNREACHABLE);
+        }
+        assert_eq!(pathfinder.find_path(&grid, START, [-5, 1, 0]), PathResult::DEFERRED);
+        assert_eq!(pathfinder.find_path(&grid, START, [5, 1, 0]), found);
+        assert_eq!(pathfinder.find_path(&grid, START, goals[0]), PathResult::UNREACHABLE);
+
+        // Changes away from a path keep it, unreachable goals are searched again
+        pathfinder.block_changed([-20, 1, -20]);
+        assert_eq!(pathfinder.find_path(&grid, START, [5, 1, 0]), found);
+        assert_eq!(pathfinder.find_path(&grid, START, goals[0]), PathResult::DEFERRED);
+
+        pathfinder.block_changed(path[2]);
+        assert_eq!(pathfinder.find_path(&grid, START, [5, 1, 0]), PathResult::DEFERRED);
+
+        pathfinder.tick();
+        assert_eq!(pathfinder.find_path(&grid, START, [5, 1, 0]), found);
+        assert_eq!(
+            pathfinder.find_path(&grid, START, [-5, 1, 0]),
+            PathResult::FOUND(vec![[-1, 1, 0], [-2, 1, 0], [-3, 1, 0], [-4, 1, 0], [-5, 1, 0]])
+        );
+    }
+}
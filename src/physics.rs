@@ -0,0 +1,208 @@
+use cgmath::*;
+
+use crate::{block::FaceDirection, block_updates::BlockGrid};
+
+/// Downward speed gained every tick, in blocks per tick.
+pub const GRAVITY: f32 = 0.04;
+/// Fraction of the speed kept every tick while in the air.
+pub const DRAG: f32 = 0.98;
+
+/// Axis aligned bounding box in world coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    /// Box of `size` standing on `feet`, centered horizontally.
+    pub fn from_feet(feet: Vector3<f32>, size: Vector3<f32>) -> Self {
+        Self {
+            min: Vector3::new(feet.x - size.x / 2.0, feet.y, feet.z - size.z / 2.0),
+            max: Vector3::new(feet.x + size.x / 2.0, feet.y + size.y, feet.z + size.z / 2.0),
+        }
+    }
+
+    pub fn translate(&self, offset: Vector3<f32>) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Box larger by `amount` on every side.
+    pub fn grow(&self, amount: f32) -> Self {
+        let amount = Vector3::new(amount, amount, amount);
+        Self {
+            min: self.min - amount,
+            max: self.max + amount,
+        }
+    }
+
+    /// Smallest box holding both boxes.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    /// Whether the boxes overlap, touching boxes do not.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] < other.max[axis] && self.max[axis] > other.min[axis])
+    }
+
+    /// Distance along `ray_direction`, in lengths of it, at which a ray enters the box and the
+    /// normal of the face it enters through. `None` if it misses the box, a distance of 0 and a
+    /// zero normal if it starts inside.
+    pub fn ray_hit(&self, ray_origin: Vector3<f32>, ray_direction: Vector3<f32>) -> Option<(f32, Vector3<i32>)> {
+        let mut enter = f32::NEG_INFINITY;
+        let mut exit = f32::INFINITY;
+        let mut normal = Vector3::zero();
+        for axis in 0..3 {
+            if ray_direction[axis] == 0.0 {
+                if ray_origin[axis] < self.min[axis] || ray_origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let to_min = (self.min[axis] - ray_origin[axis]) / ray_direction[axis];
+            let to_max = (self.max[axis] - ray_origin[axis]) / ray_direction[axis];
+            let (axis_enter, axis_exit) = if to_min < to_max { (to_min, to_max) } else { (to_max, to_min) };
+            if axis_enter > enter {
+                enter = axis_enter;
+                normal = Vector3::zero();
+                normal[axis] = if ray_direction[axis] > 0.0 { -1 } else { 1 };
+            }
+            exit = exit.min(axis_exit);
+        }
+        if enter > exit || exit < 0.0 {
+            return None;
+        }
+        if enter < 0.0 {
+            return Some((0.0, Vector3::zero()));
+        }
+        Some((enter, normal))
+    }
+
+    /// Positions of the blocks the box overlaps or touches.
+    pub fn block_positions(&self) -> Vec<[i32; 3]> {
+        let mut positions = Vec::new();
+        for x in self.min.x.floor() as i32..=self.max.x.floor() as i32 {
+            for y in self.min.y.floor() as i32..=self.max.y.floor() as i32 {
+                for z in self.min.z.floor() as i32..=self.max.z.floor() as i32 {
+                    positions.push([x, y, z]);
+                }
+            }
+        }
+        positions
+    }
+
+    /// Limits `offset` along `axis` so that the box stops against `obstacle`. Obstacles the box
+    /// already overlaps or that are not in the way are ignored.
+    fn clip(&self, obstacle: &Aabb, axis: usize, offset: f32) -> f32 {
+        let overlaps_other_axes = (0..3)
+            .filter(|other_axis| *other_axis != axis)
+            .all(|other_axis| self.min[other_axis] < obstacle.max[other_axis] && self.max[other_axis] > obstacle.min[other_axis]);
+        if !overlaps_other_axes {
+            return offset;
+        }
+        if offset > 0.0 && obstacle.min[axis] >= self.max[axis] {
+            offset.min(obstacle.min[axis] - self.max[axis])
+        } else if offset < 0.0 && obstacle.max[axis] <= self.min[axis] {
+            offset.max(obstacle.max[axis] - self.min[axis])
+        } else {
+            offset
+        }
+    }
+}
+
+/// Boxes of the model of the block at `position` in the world, `collision` ones or the shape.
+pub fn block_boxes(grid: &impl BlockGrid, position: [i32; 3], collision: bool) -> Vec<Aabb> {
+    let block_type = match grid.block_type(position) {
+        Some(block_type) => block_type,
+        None => return Vec::new(),
+    };
+    let state = grid.block_state(position);
+    let connected = |direction: FaceDirection| {
+        let offset = direction.to_vec();
+        let neighbour = grid.block_type([position[0] + offset.x, position[1] + offset.y, position[2] + offset.z]);
+        matches!(neighbour, Some(neighbour) if block_type.connects_to(neighbour))
+    };
+    let boxes = if collision {
+        block_type.model().collision_boxes(state, connected)
+    } else {
+        block_type.model().shape(state, connected)
+    };
+    let origin = Vector3::new(position[0] as f32, position[1] as f32, position[2] as f32);
+    boxes.iter().map(|block_box| block_box.translate(origin)).collect()
+}
+
+/// Collision boxes of the solid blocks overlapping `area`, unloaded chunks do not collide. Blocks
+/// below are included since models like fences stand higher than their block.
+pub fn solid_boxes(grid: &impl BlockGrid, area: &Aabb) -> Vec<Aabb> {
+    let area_and_below = Aabb {
+        min: area.min - Vector3::unit_y(),
+        max: area.max,
+    };
+    area_and_below
+        .block_positions()
+        .into_iter()
+        .filter(|position| matches!(grid.block_type(*position), Some(block_type) if block_type.is_solid()))
+        .flat_map(|position| block_boxes(grid, position, true))
+        .collect()
+}
+
+/// Moves `aabb` by `offset` and returns how far it went, stopping against solid blocks one axis at a
+/// time, vertical first. The player and the entities all move through this.
+pub fn move_aabb(grid: &impl BlockGrid, aabb: Aabb, offset: Vector3<f32>) -> Vector3<f32> {
+    let obstacles = solid_boxes(grid, &aabb.union(&aabb.translate(offset)));
+
+    let mut aabb = aabb;
+    let mut moved = Vector3::zero();
+    for axis in [1, 0, 2].iter() {
+        let mut axis_offset = offset[*axis];
+        for obstacle in obstacles.iter() {
+            axis_offset = aabb.clip(obstacle, *axis, axis_offset);
+        }
+        moved[*axis] = axis_offset;
+
+        let mut axis_translation = Vector3::zero();
+        axis_translation[*axis] = axis_offset;
+        aabb = aabb.translate(axis_translation);
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block_registry::*, test_utils::*};
+
+    fn fence() -> BlockType {
+        BlockRegistry::get().block_type("fence").unwrap()
+    }
+
+    #[test]
+    fn fences_connect_inside_of_a_chunk() {
+        let mut grid = MapGrid::new([-16, 0, -16], [31, 16, 16]);
+        grid.set([13, 4, 0], fence());
+        grid.set([14, 4, 0], fence());
+        assert_eq!(block_boxes(&grid, [13, 4, 0], true).len(), 2);
+        assert_eq!(block_boxes(&grid, [14, 4, 0], true).len(), 2);
+    }
+
+    #[test]
+    fn fences_connect_across_chunk_borders() {
+        let mut grid = MapGrid::new([-16, 0, -16], [31, 16, 16]);
+        grid.set([15, 4, 0], fence());
+        grid.set([16, 4, 0], fence());
+        grid.set([0, 4, -1], fence());
+        grid.set([0, 4, 0], fence());
+        for position in [[15, 4, 0], [16, 4, 0], [0, 4, -1], [0, 4, 0]].iter() {
+            assert_eq!(block_boxes(&grid, *position, true).len(), 2);
+            // The post and the two rails towards the neighbour
+            assert_eq!(block_boxes(&grid, *position, false).len(), 3);
+        }
+    }
+}
@@ -1,16 +1,35 @@
 use cgmath::*;
 use winit::event::*;
 
-use crate::{atlas::*, camera::*, ray_tracer::*, world::*};
+use crate::{block::*, block_registry::*, camera::*, physics::Aabb, ray_tracer::*, spawn::EYE_HEIGHT, tick::tick_duration, world::*};
 
 pub struct Player {
     pos_ray: Ray,
-    block_pos_in_view: Option<Vector3<i32>>,
-    block_face_direction_in_view: Vector3<i32>,
+    pub block_pos_in_view: Option<Vector3<i32>>,
+    pub block_face_direction_in_view: Vector3<i32>,
+    /// Where the ray of the player hits the block in view.
+    hit_point_in_view: Vector3<f32>,
     selected_block: BlockType,
+
+    /// Sign whose text the keyboard types, until enter or escape.
+    pub editing_sign: Option<Vector3<i32>>,
+
+    breaking: bool,
+    pub break_progress: f32,
+    /// Break progress before the last tick, to interpolate the crack between ticks.
+    previous_break_progress: f32,
 }
 
 pub const RAY_MAX_DISTANCE: f32 = 6.0;
+/// Width and height of the box colliding with blocks.
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+
+/// Box of the player whose eyes are at `camera_position`.
+pub fn player_aabb(camera_position: Point3<f32>) -> Aabb {
+    let feet = camera_position.to_vec() - Vector3::unit_y() * EYE_HEIGHT;
+    Aabb::from_feet(feet, Vector3::new(PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_WIDTH))
+}
 
 impl Player {
     pub fn new(camera: &Camera) -> Self {
@@ -21,7 +40,13 @@ impl Player {
             },
             block_pos_in_view: None,
             block_face_direction_in_view: Vector3::new(0, 0, 0),
+            hit_point_in_view: Vector3::new(0.0, 0.0, 0.0),
             selected_block: BlockType::DEBUG,
+            editing_sign: None,
+
+            breaking: false,
+            break_progress: 0.0,
+            previous_break_progress: 0.0,
         }
     }
 
@@ -31,48 +56,139 @@ impl Player {
             direction: camera.direction,
         };
 
+        // Blocks under water can be reached, entities hide what is behind them
+        let skip = RaySkip {
+            fluids: true,
+            non_solid: false,
+        };
         let mut block_pos = None;
-        if let Some(ray_collision) = ray_block(self.pos_ray, RAY_MAX_DISTANCE, world) {
-            block_pos = Some(ray_collision.block_pos);
-            self.block_face_direction_in_view = ray_collision.block_face_direction;
+        if let Some(ray_collision) = ray_cast(self.pos_ray, RAY_MAX_DISTANCE, &*world, &world.entity_boxes(), skip) {
+            if let RayTarget::BLOCK(pos) = ray_collision.target {
+                block_pos = Some(pos);
+                self.block_face_direction_in_view = ray_collision.normal;
+                self.hit_point_in_view = ray_collision.point;
+            }
         }
 
+        // Looking at another block restarts the breaking timer
+        if block_pos != self.block_pos_in_view {
+            self.break_progress = 0.0;
+            self.previous_break_progress = 0.0;
+        }
         self.block_pos_in_view = block_pos;
     }
 
-    pub fn input(&mut self, event: &DeviceEvent, queue: &wgpu::Queue, world: &mut World) {
+    /// Blocks break on ticks.
+    pub fn tick(&mut self, world: &mut World, queue: &wgpu::Queue) {
+        self.previous_break_progress = self.break_progress;
+        if self.breaking {
+            self.update_breaking(world, queue, tick_duration());
+        } else {
+            self.break_progress = 0.0;
+        }
+    }
+
+    /// Break progress between the last two ticks, `tick_alpha` being the fraction of a tick elapsed
+    /// since the last one.
+    pub fn interpolated_break_progress(&self, tick_alpha: f32) -> f32 {
+        self.previous_break_progress + (self.break_progress - self.previous_break_progress) * tick_alpha.min(1.0)
+    }
+
+    fn update_breaking(&mut self, world: &mut World, queue: &wgpu::Queue, dt: std::time::Duration) {
+        let hardness = self
+            .block_pos_in_view
+            .and_then(|pos| world.get_block(pos))
+            .and_then(|block| block.material_type.hardness());
+
+        match hardness {
+            Some(hardness) => {
+                if hardness > 0.0 {
+                    self.break_progress += dt.as_secs_f32() / hardness;
+                } else {
+                    self.break_progress = 1.0;
+                }
+
+                if self.break_progress >= 1.0 {
+                    self.destroy_block(queue, world);
+                    self.break_progress = 0.0;
+                    self.previous_break_progress = 0.0;
+                }
+            }
+            None => self.break_progress = 0.0,
+        }
+    }
+
+    /// Returns the position of the block entity the player interacted with.
+    pub fn input(&mut self, event: &DeviceEvent, queue: &wgpu::Queue, world: &mut World) -> Option<Vector3<i32>> {
         match event {
             DeviceEvent::Button {
                 button: 0, // Left Mouse Button
                 state,
             } => {
-                if let ElementState::Pressed = state {
-                    self.destroy_block(queue, world);
-                }
+                self.breaking = *state == ElementState::Pressed;
             }
             DeviceEvent::Button {
                 button: 1, // right Mouse Button
                 state,
             } => {
                 if let ElementState::Pressed = state {
-                    self.place_block(queue, world);
+                    return self.use_block(queue, world);
                 }
             }
             _ => {}
         }
+        None
+    }
+
+    /// Interacts with the block entity in view, or places the selected block against the block in
+    /// view when it has none.
+    fn use_block(&mut self, queue: &wgpu::Queue, world: &mut World) -> Option<Vector3<i32>> {
+        let pos = self.block_pos_in_view?;
+        let held = self.selected_block;
+        match world.with_block_entity(pos, |block_entity| block_entity.interact(held)) {
+            Some(starts_typing) => {
+                if starts_typing {
+                    self.editing_sign = Some(pos);
+                }
+                Some(pos)
+            }
+            None => {
+                if matches!(world.get_block(pos), Some(block) if block.material_type.behaviour() == BlockBehaviour::EXPLOSIVE) {
+                    world.ignite(pos);
+                } else {
+                    self.place_block(queue, world);
+                }
+                None
+            }
+        }
     }
 
     fn destroy_block(&mut self, queue: &wgpu::Queue, world: &mut World) {
         if let Some(pos) = self.block_pos_in_view {
-            world.set_block(pos, BlockType::AIR, queue);
+            world.break_block(pos, queue);
         }
     }
 
+    /// Picked up items become the block placed with the right button.
+    pub fn pick_up(&mut self, block_type: BlockType) {
+        self.selected_block = block_type;
+    }
+
+    /// Height of the point in view on the face of the block in view, as a fraction of the block.
+    fn hit_height(&self, block_pos: Vector3<i32>) -> f32 {
+        (self.hit_point_in_view.y - block_pos.y as f32).clamp(0.0, 1.0)
+    }
+
+    /// Places the selected block against the face in view, oriented from where the player looks.
     fn place_block(&mut self, queue: &wgpu::Queue, world: &mut World) {
-        if let Some(pos) = self.block_pos_in_view {
-            let pos = pos + self.block_face_direction_in_view;
-            if world.block_is_air(pos) {
-                world.set_block(pos, self.selected_block, queue);
+        if let Some(block_pos) = self.block_pos_in_view {
+            let face = self.block_face_direction_in_view;
+            let pos = block_pos + face;
+            let replaced = world.get_block(pos).map(|block| block.material_type);
+            if replaced == Some(BlockType::AIR) || replaced == Some(BlockType::WATER) {
+                let in_water = replaced == Some(BlockType::WATER);
+                let state = BlockState::for_placement(self.selected_block, face, self.pos_ray.direction, self.hit_height(block_pos), in_water);
+                world.set_block_with_state(pos, self.selected_block, state, queue);
             }
         }
     }
@@ -1,13 +1,18 @@
 use cgmath::*;
 use num::*;
 
-use crate::world::*;
+use crate::{block_registry::*, block_updates::BlockGrid, physics::*};
 
 // http://citeseerx.ist.psu.edu/viewdoc/download?doi=10.1.1.42.3443&rep=rep1&type=pdf
 
+/// Distance to the first block boundary on each axis, infinite on the axes the ray does not move along.
 fn init_tmax(s: Vector3<f32>, ds: Vector3<f32>) -> Vector3<f32> {
     let mut tmax = Vector3::new(0.0, 0.0, 0.0);
     for i in 0..3 {
+        if ds[i] == 0.0 {
+            tmax[i] = f32::INFINITY;
+            continue;
+        }
         let t = if ds[i] > 0.0 { s[i].ceil() - s[i] } else { s[i] - s[i].floor() };
         tmax[i] = t / ds[i].abs();
     }
@@ -20,67 +25,272 @@ pub struct Ray {
     pub direction: Vector3<f32>,
 }
 
+/// Blocks a ray goes through besides air.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RaySkip {
+    /// Water.
+    pub fluids: bool,
+    /// Blocks nothing collides with: water, plants.
+    pub non_solid: bool,
+}
+
+impl RaySkip {
+    fn skips(&self, block_type: BlockType) -> bool {
+        block_type == BlockType::AIR || (self.fluids && block_type == BlockType::WATER) || (self.non_solid && !block_type.is_solid())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayTarget {
+    BLOCK(Vector3<i32>),
+    /// Index of the entity box the ray hit.
+    ENTITY(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct RayCollision {
-    pub block_pos: Vector3<i32>,
-    pub block_face_direction: Vector3<i32>,
+    pub target: RayTarget,
+    /// Where the ray enters the block or the entity.
+    pub point: Vector3<f32>,
+    /// From the origin of the ray to `point`.
+    pub distance: f32,
+    /// Normal of the face hit, zero when the ray starts inside what it hits.
+    pub normal: Vector3<i32>,
 }
 
-pub fn ray_block(ray: Ray, max_distance: f32, world: &mut World) -> Option<RayCollision> {
-    let mut face_direction_vec: Vector3<i32> = Vector3::new(0, 0, 0);
+/// First block the ray hits within `max_distance`, tested against the boxes of the block models.
+pub fn ray_block(ray: Ray, max_distance: f32, grid: &impl BlockGrid, skip: RaySkip) -> Option<RayCollision> {
     let mut p: Vector3<i32> = Vector3::new(ray.origin.x.floor() as i32, ray.origin.y.floor() as i32, ray.origin.z.floor() as i32);
     let dir = ray.direction;
+    let length = dir.magnitude();
+    if length == 0.0 {
+        return None;
+    }
 
     let step: Vector3<i32> = Vector3::new(signum(dir.x) as i32, signum(dir.y) as i32, signum(dir.z) as i32);
 
     let mut tmax = init_tmax(ray.origin, dir);
-    let tdelta: Vector3<f32> = step.cast().unwrap();
-    let tdelta = tdelta.div_element_wise(dir);
+    let mut tdelta = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    for i in 0..3 {
+        if dir[i] != 0.0 {
+            tdelta[i] = 1.0 / dir[i].abs();
+        }
+    }
 
-    let radius = max_distance / (dir.dot(dir).sqrt());
+    let radius = max_distance / length;
 
     loop {
-        if world.is_hitting_block(p) {
-            return Some(RayCollision {
-                block_pos: p,
-                block_face_direction: face_direction_vec,
-            });
-        }
-        if tmax.x < tmax.y {
-            if tmax.x < tmax.z {
-                if tmax.x > radius {
+        if matches!(grid.block_type(p.into()), Some(block_type) if !skip.skips(block_type)) {
+            // The boxes of a model are inside its block, the nearest one is the first hit
+            let hit = block_boxes(grid, p.into(), false)
+                .iter()
+                .filter_map(|block_box| block_box.ray_hit(ray.origin, dir))
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            if let Some((t, normal)) = hit {
+                if t > radius {
                     return None;
                 }
+                return Some(RayCollision {
+                    target: RayTarget::BLOCK(p),
+                    point: ray.origin + dir * t,
+                    distance: t * length,
+                    normal,
+                });
+            }
+        }
 
-                p.x += step.x;
-                tmax.x += tdelta.x;
-                face_direction_vec = vec3(-step.x, 0, 0);
+        // Next block along the axis whose boundary is the closest
+        let axis = if tmax.x < tmax.y {
+            if tmax.x < tmax.z {
+                0
             } else {
-                if tmax.z > radius {
-                    return None;
-                }
-
-                p.z += step.z;
-                tmax.z += tdelta.z;
-                face_direction_vec = vec3(0, 0, -step.z);
+                2
             }
+        } else if tmax.y < tmax.z {
+            1
         } else {
-            if tmax.y < tmax.z {
-                if tmax.y > radius {
-                    return None;
-                }
+            2
+        };
+        if tmax[axis] > radius {
+            return None;
+        }
+        p[axis] += step[axis];
+        tmax[axis] += tdelta[axis];
+    }
+}
 
-                p.y += step.y;
-                tmax.y += tdelta.y;
-                face_direction_vec = vec3(0, -step.y, 0);
-            } else {
-                if tmax.z > radius {
-                    return None;
-                }
+/// Nearest block or entity the ray hits within `max_distance`, `entities` being the boxes of the
+/// entities that stop the ray.
+pub fn ray_cast(ray: Ray, max_distance: f32, grid: &impl BlockGrid, entities: &[Aabb], skip: RaySkip) -> Option<RayCollision> {
+    let length = ray.direction.magnitude();
+    let block_hit = ray_block(ray, max_distance, grid, skip);
+    let limit = block_hit.map_or(max_distance, |hit| hit.distance);
 
-                p.z += step.z;
-                tmax.z += tdelta.z;
-                face_direction_vec = vec3(0, 0, -step.z);
-            }
+    let entity_hit = entities
+        .iter()
+        .enumerate()
+        .filter_map(|(i, aabb)| aabb.ray_hit(ray.origin, ray.direction).map(|(t, normal)| (i, t, normal)))
+        .filter(|(_, t, _)| t * length < limit)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    match entity_hit {
+        Some((i, t, normal)) => Some(RayCollision {
+            target: RayTarget::ENTITY(i),
+            point: ray.origin + ray.direction * t,
+            distance: t * length,
+            normal,
+        }),
+        None => block_hit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: origin.into(),
+            direction: direction.into(),
         }
     }
+
+    fn grid() -> MapGrid {
+        MapGrid::new([-16, 0, -16], [16, 16, 16])
+    }
+
+    fn block_type(name: &str) -> BlockType {
+        BlockRegistry::get().block_type(name).unwrap()
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let mut grid = grid();
+        grid.set([5, 4, 0], BlockType::ROCK);
+        grid.set([0, 1, 0], BlockType::ROCK);
+        grid.set([0, 4, 7], BlockType::ROCK);
+
+        let hit = ray_block(ray([0.5, 4.5, 0.5], [1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(5, 4, 0)));
+        assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+        assert!((hit.point - Vector3::new(5.0, 4.5, 0.5)).magnitude() < 1e-5);
+
+        let hit = ray_block(ray([0.5, 4.5, 0.5], [0.0, -1.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(0, 1, 0)));
+        assert_eq!(hit.normal, Vector3::new(0, 1, 0));
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        let hit = ray_block(ray([0.5, 4.5, 0.5], [0.0, 0.0, 1.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(0, 4, 7)));
+        assert_eq!(hit.normal, Vector3::new(0, 0, -1));
+        assert!((hit.distance - 6.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rays_stop_at_max_distance() {
+        let mut grid = grid();
+        grid.set([5, 4, 0], BlockType::ROCK);
+        assert!(ray_block(ray([0.5, 4.5, 0.5], [1.0, 0.0, 0.0]), 4.0, &grid, RaySkip::default()).is_none());
+        // The length of the direction does not change the distance
+        let hit = ray_block(ray([0.5, 4.5, 0.5], [3.0, 0.0, 0.0]), 5.0, &grid, RaySkip::default()).unwrap();
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_rays_go_through_every_block_they_cross() {
+        let mut grid = grid();
+        grid.set([3, 7, 3], BlockType::ROCK);
+        let hit = ray_block(ray([0.5, 4.5, 0.5], [1.0, 1.0, 1.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(3, 7, 3)));
+        assert!((hit.distance - 2.5 * 3f32.sqrt()).abs() < 1e-4);
+
+        // On a 2D diagonal the ray enters the block beside it first, then this one through its side
+        grid.set([2, 4, 1], BlockType::ROCK);
+        let hit = ray_block(ray([0.5, 4.5, 0.2], [1.0, 0.0, 0.5]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(2, 4, 1)));
+        assert_eq!(hit.normal, Vector3::new(0, 0, -1));
+        assert!((hit.point - Vector3::new(2.1, 4.5, 1.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn rays_with_zero_components_stay_on_their_row() {
+        let mut grid = grid();
+        grid.set([-4, 4, 3], BlockType::ROCK);
+        // Starting on block boundaries with two zero components
+        let hit = ray_block(ray([0.0, 4.0, 3.0], [-1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(-4, 4, 3)));
+        assert!((hit.distance - 3.0).abs() < 1e-5);
+
+        // A single zero component
+        grid.set([3, 4, -3], BlockType::ROCK);
+        let hit = ray_block(ray([0.5, 4.5, 0.5], [1.0, 0.0, -1.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(3, 4, -3)));
+
+        assert!(ray_block(ray([0.5, 4.5, 0.5], [0.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).is_none());
+    }
+
+    #[test]
+    fn rays_hit_the_boxes_of_the_model() {
+        let mut grid = grid();
+        grid.set([3, 4, 0], block_type("rock_slab"));
+        assert!(ray_block(ray([0.5, 4.75, 0.5], [1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).is_none());
+        let hit = ray_block(ray([0.5, 4.25, 0.5], [1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(3, 4, 0)));
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        // Going down onto the top of the slab
+        let hit = ray_block(ray([3.5, 6.5, 0.5], [0.0, -1.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.normal, Vector3::new(0, 1, 0));
+        assert!((hit.point.y - 4.5).abs() < 1e-5);
+
+        // A fence alone is only its post
+        grid.set([3, 4, 5], block_type("fence"));
+        assert!(ray_block(ray([0.5, 4.5, 5.1], [1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).is_none());
+        let hit = ray_block(ray([0.5, 4.5, 5.5], [1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert!((hit.point.x - (3.0 + 6.0 / 16.0)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn skipped_blocks_let_the_ray_through() {
+        let mut grid = grid();
+        grid.set([2, 4, 0], BlockType::WATER);
+        grid.set([4, 4, 0], BlockType::TALL_GRASS);
+        grid.set([6, 4, 0], BlockType::ROCK);
+        let origin = [0.5, 4.5, 0.5];
+
+        let hit = ray_block(ray(origin, [1.0, 0.0, 0.0]), 10.0, &grid, RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(2, 4, 0)));
+
+        let skip = RaySkip {
+            fluids: true,
+            non_solid: false,
+        };
+        let hit = ray_block(ray(origin, [1.0, 0.0, 0.0]), 10.0, &grid, skip).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(4, 4, 0)));
+
+        let skip = RaySkip {
+            fluids: false,
+            non_solid: true,
+        };
+        let hit = ray_block(ray(origin, [1.0, 0.0, 0.0]), 10.0, &grid, skip).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(6, 4, 0)));
+    }
+
+    #[test]
+    fn entities_in_front_of_a_block_are_hit_first() {
+        let mut grid = grid();
+        grid.fill([5, 0, -1], [5, 8, 1], BlockType::ROCK);
+        let origin = [0.5, 4.5, 0.5];
+        let behind = Aabb::from_feet(Vector3::new(8.5, 4.0, 0.5), Vector3::new(0.8, 0.8, 0.8));
+        let in_front = Aabb::from_feet(Vector3::new(3.5, 4.0, 0.5), Vector3::new(0.8, 0.8, 0.8));
+
+        let hit = ray_cast(ray(origin, [1.0, 0.0, 0.0]), 10.0, &grid, &[behind], RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::BLOCK(Vector3::new(5, 4, 0)));
+
+        let hit = ray_cast(ray(origin, [1.0, 0.0, 0.0]), 10.0, &grid, &[behind, in_front], RaySkip::default()).unwrap();
+        assert_eq!(hit.target, RayTarget::ENTITY(1));
+        assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+        assert!((hit.distance - 2.6).abs() < 1e-5);
+    }
 }
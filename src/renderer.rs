@@ -3,7 +3,7 @@ use std::iter;
 use anyhow::*;
 use winit::window::Window;
 
-use crate::{camera::*, coordinate::*, crosshair::*, overlay_info::*, texture::*, world::*};
+use crate::{block_selection::*, camera::*, coordinate::*, crack_overlay::*, crosshair::*, overlay_info::*, texture::*, world::*};
 
 pub trait Draw {
     fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, uniforms: &'a wgpu::BindGroup) -> Result<()>;
@@ -143,7 +143,16 @@ impl Renderer {
         self.depth_texture = Texture::create_depth_texture(&self.device, &self.sc_desc, "depth_texture");
     }
 
-    pub fn render(&mut self, camera: &Camera, world: &World, coordinate: &Coordinate, overlay_info: &OverlayInfo, crosshair: &Crosshair) -> Result<()> {
+    pub fn render(
+        &mut self,
+        camera: &Camera,
+        world: &World,
+        coordinate: &Coordinate,
+        block_selection: &BlockSelection,
+        crack_overlay: &CrackOverlay,
+        overlay_info: &OverlayInfo,
+        crosshair: &Crosshair,
+    ) -> Result<()> {
         let frame = self.swap_chain.get_current_frame()?.output;
 
         {
@@ -179,6 +188,8 @@ impl Renderer {
 
                 world.draw(&mut render_pass, &camera.uniform_bind_group)?;
                 coordinate.draw(&mut render_pass, &camera.uniform_bind_group)?;
+                block_selection.draw(&mut render_pass, &camera.uniform_bind_group)?;
+                crack_overlay.draw(&mut render_pass, &camera.uniform_bind_group)?;
                 overlay_info.draw(&mut render_pass, &camera.uniform_bind_group)?;
                 crosshair.draw(&mut render_pass, &camera.uniform_bind_group)?;
             }
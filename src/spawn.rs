@@ -0,0 +1,90 @@
+use cgmath::*;
+
+use crate::{block_registry::*, chunk::*, world_generation::*, world_metadata::*};
+
+/// Rings of chunks searched around the origin before giving up.
+const SPAWN_SEARCH_RADIUS: i32 = 8;
+/// Blocks of free space needed above the ground.
+const SPAWN_CLEARANCE: usize = 2;
+/// Height of the camera above the feet of the player.
+pub const EYE_HEIGHT: f32 = 1.6;
+
+/// Ground the player can stand on: no water, leaves or plants.
+fn is_spawn_ground(block_type: BlockType) -> bool {
+    block_type.is_solid() && block_type.is_opaque_cube()
+}
+
+/// Highest block of a column if the player can stand on it, in block coordinates.
+fn spawn_height(blocks: &Blocks, x: usize, z: usize, settings: &WorldSettings) -> Option<usize> {
+    // Plants do not count as ground, water does so that flooded columns are skipped
+    let ground_y = (0..settings.world_height).rev().find(|y| {
+        let block_type = blocks[*y][x][z].lock().unwrap().material_type;
+        block_type.is_solid() || block_type == BlockType::WATER
+    })?;
+    if ground_y < settings.sea_level || ground_y + SPAWN_CLEARANCE >= settings.world_height {
+        return None;
+    }
+    if !is_spawn_ground(blocks[ground_y][x][z].lock().unwrap().material_type) {
+        return None;
+    }
+    let blocked = (ground_y + 1..=ground_y + SPAWN_CLEARANCE).any(|y| blocks[y][x][z].lock().unwrap().material_type.is_solid());
+    if blocked {
+        return None;
+    }
+    Some(ground_y)
+}
+
+/// Chunk offsets at `ring` chunks from the origin, ring 0 being the chunk holding the origin.
+fn ring_chunk_offsets(ring: i32) -> Vec<[i32; 3]> {
+    let mut offsets = Vec::new();
+    for chunk_x in -ring..=ring {
+        for chunk_z in -ring..=ring {
+            if chunk_x.abs() == ring || chunk_z.abs() == ring {
+                offsets.push([chunk_x, 0, chunk_z]);
+            }
+        }
+    }
+    offsets
+}
+
+/// Searches outward from the origin for a dry column with room to stand, and returns the world
+/// position of the block the player stands in (the one above the ground).
+///
+/// Chunks are generated headless with `generator`, decorations spilling over from neighbouring
+/// chunks are not taken into account.
+pub fn find_spawn_point(generator: &dyn WorldGenerator, settings: &WorldSettings) -> Option<[i32; 3]> {
+    let mut chunks = ChunkArray::default();
+    chunks.new_chunk([0, 0, 0]);
+    let mut blocks = chunks.blocks_array[0].lock().unwrap();
+
+    for ring in 0..=SPAWN_SEARCH_RADIUS {
+        let mut best: Option<([i32; 3], i32)> = None;
+        for offset in ring_chunk_offsets(ring) {
+            generator.generate_chunk(&mut blocks, offset);
+            for x in 0..CHUNK_X_SIZE {
+                for z in 0..CHUNK_Z_SIZE {
+                    let ground_y = match spawn_height(&blocks, x, z, settings) {
+                        Some(ground_y) => ground_y,
+                        None => continue,
+                    };
+                    let world_x = offset[0] * CHUNK_X_SIZE as i32 + x as i32;
+                    let world_z = offset[2] * CHUNK_Z_SIZE as i32 + z as i32;
+                    let distance = world_x * world_x + world_z * world_z;
+                    match best {
+                        Some((_, best_distance)) if best_distance <= distance => {}
+                        _ => best = Some(([world_x, ground_y as i32 + 1, world_z], distance)),
+                    }
+                }
+            }
+        }
+        if let Some((spawn_point, _)) = best {
+            return Some(spawn_point);
+        }
+    }
+    None
+}
+
+/// Camera position for a player standing at `spawn_point`, in the middle of the block.
+pub fn spawn_camera_position(spawn_point: [i32; 3]) -> Point3<f32> {
+    Point3::new(spawn_point[0] as f32 + 0.5, spawn_point[1] as f32 + EYE_HEIGHT, spawn_point[2] as f32 + 0.5)
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Once};
+
+use crate::{atlas::*, block::*, block_model::*, block_registry::*, block_updates::BlockGrid, chunk::*, fluid::*};
+
+static REGISTRY: Once = Once::new();
+
+/// Loads the block registry of the assets, once for all the tests.
+pub fn init_registry() {
+    REGISTRY.call_once(|| {
+        let models = BlockModels::load(BLOCK_MODELS_PATH).unwrap();
+        let texture_pack = TexturePack::load(DEFAULT_TEXTURE_PACK_PATH).unwrap();
+        BlockRegistry::init(BLOCK_REGISTRY_PATH, &models, &texture_pack).unwrap();
+    });
+}
+
+/// Blocks of a chunk, all of `block_type`.
+pub fn chunk_blocks(block_type: BlockType) -> Blocks {
+    init_registry();
+    (0..CHUNK_Y_SIZE)
+        .map(|y| {
+            (0..CHUNK_X_SIZE)
+                .map(|x| {
+                    (0..CHUNK_Z_SIZE)
+                        .map(|z| Arc::new(Mutex::new(Block::new(block_type, [x as i32, y as i32, z as i32]))))
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Type of the block at `[x, y, z]` in the chunk.
+pub fn chunk_block(blocks: &Blocks, [x, y, z]: [usize; 3]) -> BlockType {
+    blocks[y][x][z].lock().unwrap().material_type
+}
+
+/// Every block type of the chunk, in `[y][x][z]` order.
+pub fn chunk_block_types(blocks: &Blocks) -> Vec<BlockType> {
+    blocks.iter().flatten().flatten().map(|block| block.lock().unwrap().material_type).collect()
+}
+
+/// Blocks kept in a `HashMap`, air where nothing was set inside of the loaded box and unloaded
+/// outside of it.
+#[derive(Debug, Clone)]
+pub struct MapGrid {
+    /// Block type, state and fluid level by position.
+    pub blocks: HashMap<[i32; 3], (BlockType, BlockState, u8)>,
+    /// Lowest loaded position.
+    pub min: [i32; 3],
+    /// Highest loaded position.
+    pub max: [i32; 3],
+    /// Blocks turned into falling blocks, in order.
+    pub falling: Vec<([i32; 3], BlockType)>,
+    /// Blocks that can fall at once.
+    pub max_falling: usize,
+}
+
+impl MapGrid {
+    /// Empty grid loaded from `min` to `max` included.
+    pub fn new(min: [i32; 3], max: [i32; 3]) -> Self {
+        init_registry();
+        Self {
+            blocks: HashMap::new(),
+            min,
+            max,
+            falling: Vec::new(),
+            max_falling: usize::MAX,
+        }
+    }
+
+    fn is_loaded(&self, position: [i32; 3]) -> bool {
+        (0..3).all(|axis| position[axis] >= self.min[axis] && position[axis] <= self.max[axis])
+    }
+
+    /// Sets a block in its default state, water as a source.
+    pub fn set(&mut self, position: [i32; 3], block_type: BlockType) {
+        self.set_with_state(position, block_type, BlockState::default());
+    }
+
+    pub fn set_with_state(&mut self, position: [i32; 3], block_type: BlockType, state: BlockState) {
+        let level = if block_type == BlockType::WATER { FLUID_SOURCE_LEVEL } else { 0 };
+        self.blocks.insert(position, (block_type, state, level));
+    }
+
+    /// Sets every block from `min` to `max` included.
+    pub fn fill(&mut self, min: [i32; 3], max: [i32; 3], block_type: BlockType) {
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                for z in min[2]..=max[2] {
+                    self.set([x, y, z], block_type);
+                }
+            }
+        }
+    }
+
+    /// Fluid level of the block at `position`, 0 for anything but water.
+    pub fn level(&self, position: [i32; 3]) -> u8 {
+        match self.blocks.get(&position) {
+            Some((BlockType::WATER, _, level)) => *level,
+            _ => 0,
+        }
+    }
+}
+
+impl BlockGrid for MapGrid {
+    fn block_type(&self, position: [i32; 3]) -> Option<BlockType> {
+        if !self.is_loaded(position) {
+            return None;
+        }
+        Some(self.blocks.get(&position).map_or(BlockType::AIR, |block| block.0))
+    }
+
+    fn block_state(&self, position: [i32; 3]) -> BlockState {
+        self.blocks.get(&position).map_or(BlockState::default(), |block| block.1)
+    }
+
+    fn replace_block(&mut self, position: [i32; 3], block_type: BlockType) {
+        if self.is_loaded(position) {
+            self.set(position, block_type);
+        }
+    }
+
+    fn spawn_falling_block(&mut self, position: [i32; 3], block_type: BlockType) -> bool {
+        if self.falling.len() >= self.max_falling {
+            return false;
+        }
+        self.falling.push((position, block_type));
+        true
+    }
+
+    fn loaded_chunk_offsets(&self) -> Vec<[i32; 3]> {
+        let chunk = |position: i32, size: usize| (position as f32 / size as f32).floor() as i32;
+        let mut chunk_offsets = Vec::new();
+        for x in chunk(self.min[0], CHUNK_X_SIZE)..=chunk(self.max[0], CHUNK_X_SIZE) {
+            for z in chunk(self.min[2], CHUNK_Z_SIZE)..=chunk(self.max[2], CHUNK_Z_SIZE) {
+                chunk_offsets.push([x, 0, z]);
+            }
+        }
+        chunk_offsets
+    }
+
+    fn world_height(&self) -> usize {
+        (self.max[1] + 1) as usize
+    }
+}
+
+impl FluidGrid for MapGrid {
+    fn fluid_block(&self, position: [i32; 3]) -> Option<(BlockType, u8)> {
+        let block_type = self.block_type(position)?;
+        Some((block_type, self.level(position)))
+    }
+
+    fn set_fluid(&mut self, position: [i32; 3], level: u8) {
+        let block_type = if level > 0 { BlockType::WATER } else { BlockType::AIR };
+        self.blocks.insert(position, (block_type, BlockState::default(), level));
+    }
+}
@@ -96,4 +96,80 @@ impl Texture {
 
         Ok(Self { texture, view, sampler })
     }
+
+    /// Uploads `img` along with `mip_level_count` levels, each one a 2x2 box filter of the previous.
+    pub fn from_image_with_mipmaps(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::RgbaImage,
+        mip_level_count: u32,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let (width, height) = img.dimensions();
+        if mip_level_count == 0 || (width >> (mip_level_count - 1)) == 0 || (height >> (mip_level_count - 1)) == 0 {
+            bail!("Error cannot create {} mip levels for a {}x{} texture", mip_level_count, width, height);
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d { width, height, depth: 1 },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+
+        let mut level_image = img.clone();
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                level_image = downsample(&level_image);
+            }
+            let (width, height) = level_image.dimensions();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &level_image,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: height,
+                },
+                wgpu::Extent3d { width, height, depth: 1 },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            // Keep the pixel art look up close, smooth it out in the distance
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            lod_max_clamp: (mip_level_count - 1) as f32,
+            ..Default::default()
+        });
+
+        Ok(Self { texture, view, sampler })
+    }
+}
+
+/// Halves the image by averaging each 2x2 block of pixels.
+fn downsample(img: &image::RgbaImage) -> image::RgbaImage {
+    let (width, height) = img.dimensions();
+    image::RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            let pixel = img.get_pixel((2 * x + dx).min(width - 1), (2 * y + dy).min(height - 1));
+            for (channel, value) in sum.iter_mut().zip(pixel.0.iter()) {
+                *channel += *value as u32;
+            }
+        }
+        image::Rgba([(sum[0] / 4) as u8, (sum[1] / 4) as u8, (sum[2] / 4) as u8, (sum[3] / 4) as u8])
+    })
 }
@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+/// World updates per second, independent of the framerate.
+pub const TICKS_PER_SECOND: u32 = 20;
+/// Ticks run at most this many times per frame, a long frame does not freeze the game catching up.
+const MAX_TICKS_PER_FRAME: u32 = 10;
+
+pub fn tick_duration() -> Duration {
+    Duration::from_secs(1) / TICKS_PER_SECOND
+}
+
+/// Fixed timestep accumulator, turns variable frame times into a whole number of ticks.
+#[derive(Debug, Default)]
+pub struct TickClock {
+    /// Time not yet simulated by ticks.
+    accumulator: Duration,
+}
+
+impl TickClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the time of a frame and returns the number of ticks to run.
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        let tick_duration = tick_duration();
+        self.accumulator += dt;
+
+        let ticks = self.accumulator.as_nanos() / tick_duration.as_nanos();
+        self.accumulator = Duration::from_nanos((self.accumulator.as_nanos() % tick_duration.as_nanos()) as u64);
+        // Drop what cannot be caught up with
+        ticks.min(MAX_TICKS_PER_FRAME as u128) as u32
+    }
+
+    /// Fraction of a tick elapsed since the last tick, to interpolate rendering between ticks.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / tick_duration().as_secs_f32()
+    }
+}
+
+/// Ticks per second and time spent per tick, measured over the last second.
+#[derive(Debug)]
+pub struct TickStats {
+    pub current_tps: u32,
+    /// Milliseconds per tick.
+    pub current_mspt: f32,
+    window_start: Instant,
+    tick_count: u32,
+    tick_time: Duration,
+}
+
+impl TickStats {
+    pub fn new() -> Self {
+        Self {
+            current_tps: 0,
+            current_mspt: 0.0,
+            window_start: Instant::now(),
+            tick_count: 0,
+            tick_time: Duration::default(),
+        }
+    }
+
+    /// Records a tick that took `duration` to run.
+    pub fn record(&mut self, duration: Duration) {
+        self.tick_count += 1;
+        self.tick_time += duration;
+
+        // per second
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.current_tps = self.tick_count;
+            self.current_mspt = self.tick_time.as_secs_f32() * 1000.0 / self.tick_count as f32;
+            self.window_start = Instant::now();
+            self.tick_count = 0;
+            self.tick_time = Duration::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_add_up_to_whole_ticks() {
+        let mut clock = TickClock::new();
+        assert_eq!(clock.advance(tick_duration() / 2), 0);
+        assert!((clock.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(clock.advance(tick_duration()), 1);
+        assert!((clock.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(clock.advance(tick_duration() * 3 / 2), 2);
+        assert_eq!(clock.alpha(), 0.0);
+    }
+
+    #[test]
+    fn long_frames_are_capped_and_keep_the_remainder() {
+        let mut clock = TickClock::new();
+        assert_eq!(clock.advance(tick_duration() * 25 + tick_duration() / 4), MAX_TICKS_PER_FRAME);
+        assert!((clock.alpha() - 0.25).abs() < 1e-6);
+
+        // Hours long frames do not take longer to account for
+        assert_eq!(clock.advance(Duration::from_secs(3600 * 24 * 365)), MAX_TICKS_PER_FRAME);
+        assert!(clock.alpha() < 1.0);
+    }
+}
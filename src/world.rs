@@ -1,22 +1,89 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use anyhow::*;
 use cgmath::*;
 use core::panic;
-use rand::Rng;
 use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
 use wgpu::util::DeviceExt;
 
-use crate::{atlas::*, block::*, chunk::*, renderer::*, world_generation::*, Config};
+use crate::{
+    atlas::*, biome::*, block::*, block_entity::*, block_registry::*, block_updates::*, chunk::*, decoration::*, entity::*, explosion::*, falling_block::*,
+    fluid::*, physics::Aabb, renderer::*, world_generation::*, world_metadata::*, Config,
+};
 
 pub const WORLD_SIZE: usize = 10;
 pub const WORLD_ARRAY_SIZE: usize = WORLD_SIZE * WORLD_SIZE;
+/// Sides of a chunk, towards the chunks next to it.
+const HORIZONTAL_DIRECTIONS: [FaceDirection; 4] = [FaceDirection::RIGHT, FaceDirection::LEFT, FaceDirection::FRONT, FaceDirection::BACK];
 
 fn vec3_mod(a: Vector3<i32>, b: Vector3<i32>) -> Vector3<i32> {
     Vector3::new(a[0] % b[0], a[1] % b[1], a[2] % b[2])
 }
 
+/// Falling blocks generated with nothing solid below them, in world coordinates.
+fn unsupported_falling_blocks(blocks: &Blocks, chunk_offset: [i32; 3]) -> Vec<[i32; 3]> {
+    let mut positions = Vec::new();
+    for y in 1..CHUNK_Y_SIZE {
+        for (x, (row, row_below)) in blocks[y].iter().zip(blocks[y - 1].iter()).enumerate() {
+            for (z, (block, below)) in row.iter().zip(row_below.iter()).enumerate() {
+                let block_type = block.lock().unwrap().material_type;
+                if block_type.behaviour() == BlockBehaviour::FALLING && !below.lock().unwrap().material_type.is_solid() {
+                    positions.push(Block::world_position([x as i32, y as i32, z as i32], chunk_offset));
+                }
+            }
+        }
+    }
+    positions
+}
+
+/// Surface height of water at `level` in `block_pos`, water under more water fills its block.
+fn water_surface_height(blocks: &Blocks, block_pos: Vector3<i32>, level: u8) -> f32 {
+    let above = block_pos + Vector3::unit_y();
+    if ChunkArray::pos_in_chunk_bounds(above) && blocks[above.y as usize][above.x as usize][above.z as usize].lock().unwrap().material_type == BlockType::WATER
+    {
+        return 1.0;
+    }
+    fluid_height(level)
+}
+
+/// Water faces of a waterlogged block that does not fill its cell, hidden against water and opaque
+/// blocks like the faces of water blocks.
+fn waterlogged_vertices(blocks: &Blocks, block: &Block, world_pos: [i32; 3]) -> Vec<BlockVertex> {
+    if !block.state.waterlogged() || block.material_type.is_opaque_cube() {
+        return Vec::new();
+    }
+    let block_pos = Vector3::new(block.position[0], block.position[1], block.position[2]);
+    let surface = water_surface_height(blocks, block_pos, FLUID_SOURCE_LEVEL);
+    let mut vertices = Vec::with_capacity(4 * 6);
+    for face_direction in FaceDirection::ALL.iter() {
+        let neighbour_pos = block_pos + face_direction.to_vec();
+        if ChunkArray::pos_in_chunk_bounds(neighbour_pos) {
+            let neighbour = blocks[neighbour_pos.y as usize][neighbour_pos.x as usize][neighbour_pos.z as usize]
+                .lock()
+                .unwrap();
+            if neighbour.material_type == BlockType::WATER || neighbour.state.waterlogged() || neighbour.material_type.is_opaque_cube() {
+                continue;
+            }
+        }
+        let mut face_vertices = face_direction.get_vertices(BlockType::WATER, world_pos);
+        for vertex in face_vertices.iter_mut() {
+            vertex.lower_top((block_pos.y + 1) as f32, block_pos.y as f32 + surface);
+        }
+        vertices.extend_from_slice(&face_vertices);
+    }
+    vertices
+}
+
+/// Appends quads, 4 vertices each, to a mesh being built.
+fn push_quads(vertices: &mut Vec<BlockVertex>, indices: &mut Vec<u16>, quads: Vec<BlockVertex>) {
+    let first_index = vertices.len() as u16;
+    for quad in 0..(quads.len() / 4) as u16 {
+        indices.extend(quad_indices(quad).iter().map(|i| i + first_index));
+    }
+    vertices.extend(quads);
+}
+
 pub struct ChunkBuffer {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
@@ -40,6 +107,19 @@ impl ChunkBuffer {
         }
     }
 
+    /// Buffer for up to `max_boxes` boxes drawn outside of chunks, only their vertices change.
+    pub fn for_boxes(device: &wgpu::Device, max_boxes: usize) -> Self {
+        let indices = (0..(max_boxes * 6) as u16).flat_map(|quad| quad_indices(quad).to_vec()).collect();
+        let vertices = vec![BlockVertex::new([0.0; 3], [0.0; 2]); max_boxes * 4 * 6];
+        ChunkBuffer::new(device, vertices, indices, 0)
+    }
+
+    /// Replaces the vertices of a buffer made with `ChunkBuffer::for_boxes`.
+    pub fn update_boxes(&mut self, queue: &wgpu::Queue, vertices: &[BlockVertex]) {
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+        self.num_elements = (vertices.len() / 4 * 6) as u32;
+    }
+
     pub fn update(&mut self, queue: &wgpu::Queue, mesh: &Mesh) {
         queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&mesh.vertices));
         queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&mesh.indices));
@@ -59,14 +139,32 @@ pub struct World {
     center_offset: Vector3<i32>,
     chunks_origin: Vector3<i32>,
     pub atlas: Atlas,
-    world_seed: u32,
+    generator: Box<dyn WorldGenerator>,
+    settings: WorldSettings,
+    deferred_writes: Mutex<DeferredWrites>,
+    fluids: FluidSimulation,
+    block_updates: BlockUpdates,
+    falling_blocks: FallingBlocks,
+    falling_block_buffer: ChunkBuffer,
+    entities: Entities,
+    entity_buffer: ChunkBuffer,
+    fuses: Fuses,
+    block_entity_store: Mutex<BlockEntityStore>,
     logger: slog::Logger,
     config: Config,
 }
 
 impl World {
-    pub fn new(logger: slog::Logger, config: Config, renderer: &Renderer, uniform_bind_group_layout: &wgpu::BindGroupLayout) -> Result<Self> {
-        let atlas = Atlas::new(&renderer.device, &renderer.queue)?;
+    pub fn new(
+        logger: slog::Logger,
+        config: Config,
+        renderer: &Renderer,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        texture_pack: &TexturePack,
+        generator: Box<dyn WorldGenerator>,
+        metadata: &WorldMetadata,
+    ) -> Result<Self> {
+        let atlas = Atlas::new(&renderer.device, &renderer.queue, texture_pack)?;
 
         let mut chunk_buffers = vec![];
         let mut chunks = ChunkArray::default();
@@ -100,12 +198,12 @@ impl World {
             config.wireframe,
         );
 
+        let falling_block_buffer = ChunkBuffer::for_boxes(&renderer.device, MAX_FALLING_BLOCKS);
+        let entity_buffer = ChunkBuffer::for_boxes(&renderer.device, MAX_ENTITIES);
+
         let center_offset = vec3(0, 0, 0);
         let chunks_origin = center_offset - Vector3::new(WORLD_SIZE as i32 / 2, 0, WORLD_SIZE as i32 / 2);
 
-        let mut rng = rand::thread_rng();
-        let world_seed = rng.gen::<u32>();
-
         let mut world = Self {
             chunks,
             chunk_indices: Arc::new(Mutex::new(chunk_indices)),
@@ -115,7 +213,17 @@ impl World {
             chunk_render_pipeline,
 
             atlas,
-            world_seed,
+            generator,
+            settings: metadata.settings,
+            deferred_writes: Mutex::new(DeferredWrites::default()),
+            fluids: FluidSimulation::default(),
+            block_updates: BlockUpdates::new(metadata.seed),
+            falling_blocks: FallingBlocks::default(),
+            falling_block_buffer,
+            entities: Entities::new(metadata.seed, metadata.dir.clone()),
+            entity_buffer,
+            fuses: Fuses::default(),
+            block_entity_store: Mutex::new(BlockEntityStore::new(metadata.dir.clone())),
             chunks_origin,
             center_offset,
             logger,
@@ -128,6 +236,11 @@ impl World {
     }
 
     pub fn load_empty_chunks(&mut self, queue: &wgpu::Queue) {
+        // Chunks that need a new mesh: the generated ones and the loaded ones decorations grew into
+        let dirty_chunk_offsets: Mutex<HashSet<[i32; 3]>> = Mutex::new(HashSet::new());
+        let unsupported_blocks: Mutex<Vec<[i32; 3]>> = Mutex::new(Vec::new());
+        let generated_chunk_offsets: Mutex<Vec<[i32; 3]>> = Mutex::new(Vec::new());
+
         (0..WORLD_ARRAY_SIZE).into_par_iter().for_each(|i| {
             let chunk_index = self.chunk_indices.lock().unwrap()[i].clone();
             if let None = chunk_index {
@@ -139,15 +252,25 @@ impl World {
                     }
 
                     *self.chunks.offset_array[new_index].lock().unwrap() = chunk_offset.into();
-                    generate_chunk(
-                        &mut self.chunks.blocks_array[new_index].lock().unwrap(),
-                        chunk_offset.into(),
-                        self.world_seed,
-                        self.config.flat_world,
-                    );
-
-                    let mesh = self.compute_mesh(&self.chunks.blocks_array[new_index].lock().unwrap());
-                    *self.chunks.mesh_array[new_index].lock().unwrap() = mesh;
+                    let mut blocks = self.chunks.blocks_array[new_index].lock().unwrap();
+                    let overflow = self.generator.generate_chunk(&mut blocks, chunk_offset.into());
+                    self.restore_block_entities(new_index, &blocks, chunk_offset.into());
+                    unsupported_blocks
+                        .lock()
+                        .unwrap()
+                        .extend(unsupported_falling_blocks(&blocks, chunk_offset.into()));
+                    drop(blocks);
+
+                    let targets = self.deferred_writes.lock().unwrap().insert(chunk_offset.into(), overflow);
+                    generated_chunk_offsets.lock().unwrap().push(chunk_offset.into());
+                    let mut dirty_chunk_offsets = dirty_chunk_offsets.lock().unwrap();
+                    dirty_chunk_offsets.insert(chunk_offset.into());
+                    dirty_chunk_offsets.extend(targets);
+                    // Blocks of the chunks around may connect to the new chunk
+                    for direction in HORIZONTAL_DIRECTIONS.iter() {
+                        let offset = direction.to_vec();
+                        dirty_chunk_offsets.insert([chunk_offset.x + offset.x, 0, chunk_offset.z + offset.z]);
+                    }
 
                     self.chunk_indices.lock().unwrap()[i] = Some(new_index);
                 } else {
@@ -156,13 +279,115 @@ impl World {
             }
         });
 
+        // Every chunk of this batch is generated, decorations crossing borders can be written
+        let dirty_chunks: Vec<([i32; 3], usize)> = dirty_chunk_offsets
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter(|chunk_offset| self.chunk_in_bounds((*chunk_offset).into()))
+            .filter_map(|chunk_offset| self.get_chunk_index(chunk_offset.into()).map(|chunk_index| (chunk_offset, chunk_index)))
+            .collect();
+        dirty_chunks.par_iter().for_each(|(chunk_offset, chunk_index)| {
+            let blocks = self.chunks.blocks_array[*chunk_index].lock().unwrap();
+            self.deferred_writes.lock().unwrap().apply(*chunk_offset, &blocks);
+        });
+        // Meshes read the borders of the chunks around, once all of them are written
+        dirty_chunks.par_iter().for_each(|(chunk_offset, chunk_index)| {
+            let mesh = self.compute_chunk_mesh(*chunk_index, *chunk_offset);
+            *self.chunks.mesh_array[*chunk_index].lock().unwrap() = mesh;
+        });
+
         (0..WORLD_ARRAY_SIZE).for_each(|i| {
             self.chunk_buffers[i].update(queue, &self.chunks.mesh_array[i].lock().unwrap());
         });
+
+        // Sand generated above caves falls as soon as the world ticks
+        for position in unsupported_blocks.into_inner().unwrap() {
+            self.block_updates.schedule(position, FALLING_DELAY);
+        }
+
+        // Entities spawn on the generated terrain, or come back from where they were saved
+        let mut generated_chunk_offsets = generated_chunk_offsets.into_inner().unwrap();
+        generated_chunk_offsets.sort_unstable();
+        let mut entities = std::mem::take(&mut self.entities);
+        for chunk_offset in generated_chunk_offsets {
+            if let Err(err) = entities.load_chunk(self, chunk_offset) {
+                warn!(self.logger, "{:?}", err);
+            }
+        }
+        self.entities = entities;
     }
 
+    /// Gives a generated chunk back the block entities it had when it unloaded, with their blocks.
+    fn restore_block_entities(&self, chunk_index: usize, blocks: &Blocks, chunk_offset: [i32; 3]) {
+        let restored = match self.block_entity_store.lock().unwrap().take_chunk(chunk_offset) {
+            Ok(restored) => restored,
+            Err(err) => {
+                warn!(self.logger, "{:?}", err);
+                Vec::new()
+            }
+        };
+        let mut block_entities = self.chunks.block_entities_array[chunk_index].lock().unwrap();
+        block_entities.clear();
+        for block_entity in restored {
+            let block_pos = World::world_pos_to_block_pos(block_entity.position.into());
+            if !ChunkArray::pos_in_chunk_bounds(block_pos) {
+                continue;
+            }
+            blocks[block_pos.y as usize][block_pos.x as usize][block_pos.z as usize]
+                .lock()
+                .unwrap()
+                .update_with_state(block_entity.block_type, block_entity.state);
+            block_entities.insert(block_entity.position, block_entity);
+        }
+    }
+
+    /// Mesh of a loaded chunk. The borders of the chunks around are read before locking the chunk,
+    /// so that chunks meshed in parallel never wait for each other.
+    fn compute_chunk_mesh(&self, chunk_index: usize, chunk_offset: [i32; 3]) -> Mesh {
+        let border = self.border_block_types(chunk_offset);
+        self.compute_mesh(&self.chunks.blocks_array[chunk_index].lock().unwrap(), chunk_offset, &border)
+    }
+
+    /// Types of the blocks of the loaded chunks around a chunk that touch its sides, by position
+    /// relative to the chunk.
+    fn border_block_types(&self, chunk_offset: [i32; 3]) -> HashMap<[i32; 3], BlockType> {
+        let mut border = HashMap::new();
+        for direction in HORIZONTAL_DIRECTIONS.iter() {
+            let offset = direction.to_vec();
+            let neighbour_offset = Vector3::new(chunk_offset[0] + offset.x, 0, chunk_offset[2] + offset.z);
+            if !self.chunk_in_bounds(neighbour_offset) {
+                continue;
+            }
+            let chunk_index = match self.get_chunk_index(neighbour_offset) {
+                Some(chunk_index) => chunk_index,
+                None => continue,
+            };
+
+            let side: Vec<[i32; 2]> = if offset.x != 0 {
+                let x = if offset.x > 0 { CHUNK_X_SIZE as i32 } else { -1 };
+                (0..CHUNK_Z_SIZE as i32).map(|z| [x, z]).collect()
+            } else {
+                let z = if offset.z > 0 { CHUNK_Z_SIZE as i32 } else { -1 };
+                (0..CHUNK_X_SIZE as i32).map(|x| [x, z]).collect()
+            };
+            let blocks = self.chunks.blocks_array[chunk_index].lock().unwrap();
+            for (y, layer) in blocks.iter().enumerate() {
+                for [x, z] in side.iter() {
+                    let block = layer[x.rem_euclid(CHUNK_X_SIZE as i32) as usize][z.rem_euclid(CHUNK_Z_SIZE as i32) as usize]
+                        .lock()
+                        .unwrap();
+                    border.insert([*x, y as i32, *z], block.material_type);
+                }
+            }
+        }
+        border
+    }
+
+    /// Mesh of the blocks of a chunk, `border` holds the types of the blocks around the chunk that
+    /// its blocks connect to.
     //TODO: clean this up ?
-    pub fn compute_mesh(&self, blocks: &Blocks) -> Mesh {
+    pub fn compute_mesh(&self, blocks: &Blocks, chunk_offset: [i32; 3], border: &HashMap<[i32; 3], BlockType>) -> Mesh {
         let mut vertices: Vec<BlockVertex> = Vec::with_capacity(4 * 6 * TOTAL_CHUNK_SIZE);
         let mut indices: Vec<u16> = Vec::with_capacity(6 * 6 * TOTAL_CHUNK_SIZE);
         for y in 0..CHUNK_Y_SIZE {
@@ -170,43 +395,79 @@ impl World {
                 for x in 0..CHUNK_X_SIZE {
                     let block = blocks[y][x][z].lock().unwrap();
 
-                    match block.material_type {
-                        BlockType::AIR => {}
-                        _ => {
-                            let block_pos = Vector3::new(block.position[0], block.position[1], block.position[2]);
-                            let blocks = &blocks;
-
-                            let mut block_vertices = Vec::with_capacity(4 * 6);
-                            let mut block_indices = Vec::with_capacity(6 * 6);
-                            let mut face_counter: u16 = 0;
-                            for face in block.faces.iter() {
-                                let direction = face.direction.to_vec();
-                                let neighbour_pos = block_pos + direction;
-
-                                let mut visible = false;
-                                if ChunkArray::pos_in_chunk_bounds(neighbour_pos) {
-                                    let neighbour = blocks[neighbour_pos.y as usize][neighbour_pos.x as usize][neighbour_pos.z as usize]
-                                        .lock()
-                                        .unwrap();
-
-                                    if let BlockType::AIR = neighbour.material_type {
-                                        visible = true;
+                    if !block.material_type.is_visible() {
+                        continue;
+                    }
+
+                    let world_pos = Block::world_position(block.position, chunk_offset);
+                    push_quads(&mut vertices, &mut indices, waterlogged_vertices(blocks, &block, world_pos));
+
+                    let block_pos = Vector3::new(block.position[0], block.position[1], block.position[2]);
+                    let blocks = &blocks;
+                    let water_surface = if block.material_type == BlockType::WATER {
+                        Some(water_surface_height(blocks, block_pos, block.fluid_level))
+                    } else {
+                        None
+                    };
+                    let connected = |direction: FaceDirection| {
+                        let neighbour_pos = block_pos + direction.to_vec();
+                        let neighbour_type = if ChunkArray::pos_in_chunk_bounds(neighbour_pos) {
+                            blocks[neighbour_pos.y as usize][neighbour_pos.x as usize][neighbour_pos.z as usize]
+                                .lock()
+                                .unwrap()
+                                .material_type
+                        } else {
+                            let neighbour_pos: [i32; 3] = neighbour_pos.into();
+                            match border.get(&neighbour_pos) {
+                                Some(neighbour_type) => *neighbour_type,
+                                None => return false,
+                            }
+                        };
+                        block.material_type.connects_to(neighbour_type)
+                    };
+
+                    let quads = block.material_type.model().quads(block.material_type, block.state, world_pos, connected);
+                    let mut block_vertices = Vec::with_capacity(4 * quads.len());
+                    for quad in quads.iter() {
+                        let mut visible = true;
+                        if let Some(cull) = quad.cull {
+                            let direction = cull.to_vec();
+                            let neighbour_pos = block_pos + direction;
+                            if ChunkArray::pos_in_chunk_bounds(neighbour_pos) {
+                                let neighbour = blocks[neighbour_pos.y as usize][neighbour_pos.x as usize][neighbour_pos.z as usize]
+                                    .lock()
+                                    .unwrap();
+
+                                // Only full blocks hide a face, transparent ones only the faces of the same block
+                                let neighbour_type = neighbour.material_type;
+                                visible = !(neighbour_type.model().is_full() && (!neighbour_type.is_transparent() || neighbour_type == block.material_type));
+
+                                // Water does not show against the water of a waterlogged block
+                                if block.material_type == BlockType::WATER && neighbour.state.waterlogged() {
+                                    visible = false;
+                                }
+
+                                // Sides of water standing higher than the flowing water next to it
+                                if let Some(surface) = water_surface {
+                                    if neighbour.material_type == BlockType::WATER && direction.y == 0 {
+                                        visible = water_surface_height(blocks, neighbour_pos, neighbour.fluid_level) < surface;
                                     }
-                                } else {
-                                    visible = true;
                                 }
+                            }
+                        }
 
-                                if visible {
-                                    block_vertices.extend_from_slice(&face.vertices);
-                                    block_indices.extend_from_slice(&face.get_indices(face_counter));
-                                    face_counter += 1;
+                        if visible {
+                            let mut face_vertices = quad.vertices;
+                            if let Some(surface) = water_surface {
+                                let top_y = (block_pos.y + 1) as f32;
+                                for vertex in face_vertices.iter_mut() {
+                                    vertex.lower_top(top_y, block_pos.y as f32 + surface);
                                 }
                             }
-                            let block_indices: Vec<u16> = block_indices.iter().map(|x| x + vertices.len() as u16).collect();
-                            vertices.extend_from_slice(&block_vertices);
-                            indices.extend_from_slice(&block_indices);
+                            block_vertices.extend_from_slice(&face_vertices);
                         }
                     }
+                    push_quads(&mut vertices, &mut indices, block_vertices);
                 }
             }
         }
@@ -290,6 +551,10 @@ impl World {
                         let new_chunk_world_index = self.get_chunk_world_index(chunk_offset.into());
                         self.chunk_indices.lock().unwrap()[new_chunk_world_index] = Some(chunk_index);
                     } else {
+                        let block_entities = std::mem::take(&mut *self.chunks.block_entities_array[chunk_index].lock().unwrap());
+                        if let Err(err) = self.block_entity_store.lock().unwrap().store_chunk(chunk_offset, &block_entities) {
+                            warn!(self.logger, "{:?}", err);
+                        }
                         self.free_chunk_indices.lock().unwrap().push_back(chunk_index);
                     }
                 }
@@ -298,24 +563,251 @@ impl World {
         }
 
         self.load_empty_chunks(queue);
+        self.unload_entities();
+        let chunks_max = self.chunks_origin + Vector3::new(WORLD_SIZE as i32 - 1, 0, WORLD_SIZE as i32 - 1);
+        self.deferred_writes.lock().unwrap().evict(self.chunks_origin.into(), chunks_max.into());
+    }
+
+    /// Saves the entities that are no longer in a loaded chunk.
+    fn unload_entities(&mut self) {
+        let loaded_chunk_offsets = self.loaded_chunk_offsets();
+        if let Err(err) = self.entities.unload_chunks(&loaded_chunk_offsets) {
+            warn!(self.logger, "{:?}", err);
+        }
+    }
+
+    /// Writes the entities and the block entities of the loaded chunks to the world directory, if
+    /// there is one.
+    pub fn save(&self) -> Result<()> {
+        self.entities.save()?;
+        let block_entity_store = self.block_entity_store.lock().unwrap();
+        for chunk_index in self.chunk_indices.lock().unwrap().iter().flatten() {
+            let chunk_offset = *self.chunks.offset_array[*chunk_index].lock().unwrap();
+            block_entity_store.write_chunk(chunk_offset, &self.chunks.block_entities_array[*chunk_index].lock().unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Runs `f` on the block entity at `world_pos`, `None` if there is none.
+    pub fn with_block_entity<R>(&self, world_pos: Vector3<i32>, f: impl FnOnce(&mut BlockEntity) -> R) -> Option<R> {
+        let (chunk_index, _) = self.loaded_block(world_pos)?;
+        let position: [i32; 3] = world_pos.into();
+        let mut block_entities = self.chunks.block_entities_array[chunk_index].lock().unwrap();
+        block_entities.get_mut(&position).map(f)
+    }
+
+    /// Moves the entities by one tick and returns the items picked up by the player, whose box is `player`.
+    pub fn tick_entities(&mut self, player: &Aabb) -> Vec<BlockType> {
+        let mut entities = std::mem::take(&mut self.entities);
+        let picked_up = entities.tick(self, player);
+        self.entities = entities;
+        self.unload_entities();
+        picked_up
+    }
+
+    /// Breaks the block at `world_pos`, dropping it as an item along with what its block entity
+    /// held.
+    pub fn break_block(&mut self, world_pos: Vector3<i32>, queue: &wgpu::Queue) {
+        if let Some(block) = self.get_block(world_pos) {
+            let block_type = block.material_type;
+            let contents = self.with_block_entity(world_pos, |block_entity| block_entity.drops()).unwrap_or_default();
+            let left = if block.state.waterlogged() { BlockType::WATER } else { BlockType::AIR };
+            self.set_block(world_pos, left, queue);
+            if block_type.is_visible() && block_type != BlockType::WATER {
+                self.entities.spawn_item(block_type, 1, world_pos.into());
+            }
+            for stack in contents {
+                self.entities.spawn_item(stack.block_type, stack.count, world_pos.into());
+            }
+        }
+    }
+
+    /// Lights the fuse of the explosive block at `world_pos`.
+    pub fn ignite(&mut self, world_pos: Vector3<i32>) {
+        self.fuses.ignite(world_pos.into(), FUSE_TICKS);
+    }
+
+    /// Blows up the blocks around `world_pos` in a single batch, the block itself included unless
+    /// explosions cannot destroy it, and returns how many were destroyed. Destroyed blocks drop
+    /// nothing but what their block entity held, explosives caught in the blast go off shortly
+    /// after.
+    pub fn explode(&mut self, world_pos: Vector3<i32>, power: f32, queue: &wgpu::Queue) -> usize {
+        let center = world_pos.cast().expect("Cannot convert vec3<i32> to vec3<f32>") + Vector3::new(0.5, 0.5, 0.5);
+        let blast = blast(self, center, power);
+        let position: [i32; 3] = world_pos.into();
+        let mut destroyed = blast.destroyed;
+        let destructible = matches!(self.block_type(position), Some(block_type) if block_type != BlockType::AIR && block_type.blast_resistance().is_some());
+        if destructible && !destroyed.contains(&position) {
+            destroyed.push(position);
+        }
+
+        let edits: Vec<(Vector3<i32>, BlockType, BlockState)> = destroyed
+            .iter()
+            .map(|position| {
+                let left = if self.block_state(*position).waterlogged() {
+                    BlockType::WATER
+                } else {
+                    BlockType::AIR
+                };
+                ((*position).into(), left, BlockState::default())
+            })
+            .collect();
+        let contents: Vec<([i32; 3], Vec<ItemStack>)> = destroyed
+            .iter()
+            .filter_map(|position| {
+                let stacks = self.with_block_entity((*position).into(), |block_entity| block_entity.drops())?;
+                Some((*position, stacks))
+            })
+            .collect();
+        self.set_blocks(&edits, queue);
+        for (position, stacks) in contents {
+            for stack in stacks {
+                self.entities.spawn_item(stack.block_type, stack.count, position);
+            }
+        }
+        for ignited in blast.ignited.into_iter().filter(|ignited| *ignited != position) {
+            self.fuses.ignite(ignited, CHAIN_FUSE_TICKS);
+        }
+        edits.len()
+    }
+
+    /// Biome the terrain at `world_pos` was generated with, `None` if the generator has no biomes.
+    pub fn biome_at(&self, world_pos: Vector3<f32>) -> Option<Biome> {
+        self.generator.biome_at(world_pos.x.floor() as i32, world_pos.z.floor() as i32)
     }
 
     pub fn set_block(&mut self, world_pos: Vector3<i32>, block_type: BlockType, queue: &wgpu::Queue) {
-        let world_pos_f = world_pos.cast().expect("Cannot convert vec3<i32> to vec3<f32>");
-        let chunk_offset = World::world_pos_to_chunk_offset(world_pos_f);
+        self.set_block_with_state(world_pos, block_type, BlockState::default(), queue);
+    }
+
+    pub fn set_block_with_state(&mut self, world_pos: Vector3<i32>, block_type: BlockType, state: BlockState, queue: &wgpu::Queue) {
+        self.set_blocks(&[(world_pos, block_type, state)], queue);
+    }
 
-        if let Some(chunk_index) = self.get_chunk_index(chunk_offset) {
-            let block_pos = World::world_pos_to_block_pos(world_pos)
-                .cast()
-                .expect("Cannot convert vec3<i32> to vec3<usize>");
-            self.chunks.change_block(chunk_index, block_pos.into(), block_type);
+    /// Sets every block of `edits` then remeshes each chunk they touched once, edits outside of
+    /// the loaded world or above the world height are ignored.
+    pub fn set_blocks(&mut self, edits: &[(Vector3<i32>, BlockType, BlockState)], queue: &wgpu::Queue) {
+        let mut chunk_indices = Vec::new();
+        for (world_pos, block_type, state) in edits.iter() {
+            if self.write_block(*world_pos, *block_type, *state).is_some() {
+                chunk_indices.extend(self.meshing_chunk_indices(*world_pos));
+            }
+        }
+        self.remesh_chunks(chunk_indices, queue);
+    }
 
-            let mesh = self.compute_mesh(&self.chunks.blocks_array[chunk_index].lock().unwrap());
+    /// Indices of the loaded chunks whose mesh depends on the block at `world_pos`: its own chunk,
+    /// and the chunks next to it when the block is on a border since their blocks connect to it.
+    fn meshing_chunk_indices(&self, world_pos: Vector3<i32>) -> Vec<usize> {
+        let mut chunk_indices: Vec<usize> = self.loaded_block(world_pos).map(|(chunk_index, _)| chunk_index).into_iter().collect();
+        for direction in HORIZONTAL_DIRECTIONS.iter() {
+            if let Some((chunk_index, _)) = self.loaded_block(world_pos + direction.to_vec()) {
+                if !chunk_indices.contains(&chunk_index) {
+                    chunk_indices.push(chunk_index);
+                }
+            }
+        }
+        chunk_indices
+    }
+
+    /// Changes a block without remeshing its chunk, returns the index of the chunk if the block is
+    /// loaded.
+    fn write_block(&mut self, world_pos: Vector3<i32>, block_type: BlockType, state: BlockState) -> Option<usize> {
+        let (chunk_index, block_pos) = self.loaded_block(world_pos)?;
+        self.chunks.change_block(chunk_index, block_pos.into(), block_type, state);
+        let position: [i32; 3] = world_pos.into();
+        let mut block_entities = self.chunks.block_entities_array[chunk_index].lock().unwrap();
+        match BlockEntity::new(block_type, state, position) {
+            Some(block_entity) => block_entities.insert(position, block_entity),
+            None => block_entities.remove(&position),
+        };
+        drop(block_entities);
+        self.fluids.block_changed(position);
+        self.block_updates.block_changed(position);
+        self.entities.block_changed(position);
+        Some(chunk_index)
+    }
+
+    /// Computes the meshes of the chunks at `chunk_indices` again, once per chunk.
+    fn remesh_chunks(&mut self, mut chunk_indices: Vec<usize>, queue: &wgpu::Queue) {
+        chunk_indices.sort_unstable();
+        chunk_indices.dedup();
+        for chunk_index in chunk_indices {
+            let chunk_offset = *self.chunks.offset_array[chunk_index].lock().unwrap();
+            let mesh = self.compute_chunk_mesh(chunk_index, chunk_offset);
             *self.chunks.mesh_array[chunk_index].lock().unwrap() = mesh;
             self.chunk_buffers[chunk_index].update(queue, &self.chunks.mesh_array[chunk_index].lock().unwrap());
         }
     }
 
+    /// Runs the block updates and the water flow for one tick, and remeshes the chunks they changed.
+    pub fn tick(&mut self, queue: &wgpu::Queue) {
+        let mut block_updates = std::mem::take(&mut self.block_updates);
+        let mut changed = block_updates.tick(self);
+        self.block_updates = block_updates;
+        for position in changed.iter() {
+            self.fluids.block_changed(*position);
+        }
+
+        let mut falling_blocks = std::mem::take(&mut self.falling_blocks);
+        let landed = falling_blocks.tick(self);
+        self.falling_blocks = falling_blocks;
+        for (position, block_type) in landed.placed {
+            self.set_block(position.into(), block_type, queue);
+        }
+        for (position, block_type) in landed.dropped {
+            self.entities.spawn_item(block_type, 1, position);
+        }
+
+        let mut fluids = std::mem::take(&mut self.fluids);
+        let fluid_changed = fluids.tick(self);
+        self.fluids = fluids;
+        for position in fluid_changed.iter() {
+            self.block_updates.block_changed(*position);
+        }
+        changed.extend(fluid_changed);
+        for position in changed.iter() {
+            self.entities.block_changed(*position);
+        }
+
+        let chunk_indices = changed.into_iter().flat_map(|position| self.meshing_chunk_indices(position.into())).collect();
+        self.remesh_chunks(chunk_indices, queue);
+
+        for position in self.fuses.tick() {
+            // Fuses of explosives removed meanwhile go out
+            if matches!(self.block_type(position), Some(block_type) if block_type.behaviour() == BlockBehaviour::EXPLOSIVE) {
+                self.explode(position.into(), EXPLOSION_POWER, queue);
+            }
+        }
+    }
+
+    /// Moves the falling blocks and the entities to where they are between the last two ticks.
+    pub fn update_moving(&mut self, queue: &wgpu::Queue, tick_alpha: f32) {
+        if !self.falling_blocks.is_empty() || self.falling_block_buffer.num_elements > 0 {
+            self.falling_block_buffer.update_boxes(queue, &self.falling_blocks.vertices(tick_alpha));
+        }
+        if !self.entities.is_empty() || self.entity_buffer.num_elements > 0 {
+            self.entity_buffer.update_boxes(queue, &self.entities.vertices(tick_alpha));
+        }
+    }
+
+    /// Index in `self.chunks` and block position in the chunk of a loaded block below the world height.
+    fn loaded_block(&self, world_pos: Vector3<i32>) -> Option<(usize, Vector3<usize>)> {
+        // Nothing can be built above the world height
+        if world_pos.y < 0 || world_pos.y >= self.settings.world_height as i32 {
+            return None;
+        }
+        let chunk_offset = World::world_pos_to_chunk_offset(world_pos.cast().expect("Cannot convert vec3<i32> to vec3<f32>"));
+        if !self.chunk_in_bounds(chunk_offset) {
+            return None;
+        }
+        let chunk_index = self.get_chunk_index(chunk_offset)?;
+        let block_pos = World::world_pos_to_block_pos(world_pos)
+            .cast()
+            .expect("Cannot convert vec3<i32> to vec3<usize>");
+        Some((chunk_index, block_pos))
+    }
+
     pub fn get_block(&self, world_pos: Vector3<i32>) -> Option<Block> {
         let world_pos_f = world_pos.cast().expect("Cannot convert vec3<i32> to vec3<f32>");
         let chunk_offset = World::world_pos_to_chunk_offset(world_pos_f);
@@ -330,23 +822,75 @@ impl World {
         return None;
     }
 
-    pub fn block_is_air(&mut self, world_pos: Vector3<i32>) -> bool {
-        if let Some(block) = self.get_block(world_pos) {
-            if let BlockType::AIR = block.material_type {
-                return true;
-            }
+    /// Boxes of the entities that stop the rays of the player.
+    pub fn entity_boxes(&self) -> Vec<Aabb> {
+        self.entities.boxes()
+    }
+}
+
+impl FluidGrid for World {
+    fn fluid_block(&self, position: [i32; 3]) -> Option<(BlockType, u8)> {
+        let (chunk_index, block_pos) = self.loaded_block(position.into())?;
+        let blocks = self.chunks.blocks_array[chunk_index].lock().unwrap();
+        let block = blocks[block_pos.y][block_pos.x][block_pos.z].lock().unwrap();
+        // Water in a waterlogged block is a source that stays
+        if block.state.waterlogged() {
+            return Some((BlockType::WATER, FLUID_SOURCE_LEVEL));
         }
-        return false;
+        Some((block.material_type, block.fluid_level))
+    }
+
+    fn set_fluid(&mut self, position: [i32; 3], level: u8) {
+        if let Some((chunk_index, block_pos)) = self.loaded_block(position.into()) {
+            let block_type = if level > 0 { BlockType::WATER } else { BlockType::AIR };
+            self.chunks.change_block(chunk_index, block_pos.into(), block_type, BlockState::default());
+
+            let blocks = self.chunks.blocks_array[chunk_index].lock().unwrap();
+            blocks[block_pos.y][block_pos.x][block_pos.z].lock().unwrap().fluid_level = level;
+        }
+    }
+}
+
+impl BlockGrid for World {
+    fn block_type(&self, position: [i32; 3]) -> Option<BlockType> {
+        let (chunk_index, block_pos) = self.loaded_block(position.into())?;
+        let blocks = self.chunks.blocks_array[chunk_index].lock().unwrap();
+        let block_type = blocks[block_pos.y][block_pos.x][block_pos.z].lock().unwrap().material_type;
+        Some(block_type)
     }
 
-    pub fn is_hitting_block(&mut self, pos: Vector3<i32>) -> bool {
-        if let Some(block) = self.get_block(pos) {
-            if let BlockType::AIR = block.material_type {
-                return false;
+    fn block_state(&self, position: [i32; 3]) -> BlockState {
+        match self.loaded_block(position.into()) {
+            Some((chunk_index, block_pos)) => {
+                let blocks = self.chunks.blocks_array[chunk_index].lock().unwrap();
+                let state = blocks[block_pos.y][block_pos.x][block_pos.z].lock().unwrap().state;
+                state
             }
-            return true;
+            None => BlockState::default(),
+        }
+    }
+
+    fn replace_block(&mut self, position: [i32; 3], block_type: BlockType) {
+        if let Some((chunk_index, block_pos)) = self.loaded_block(position.into()) {
+            self.chunks.change_block(chunk_index, block_pos.into(), block_type, BlockState::default());
         }
-        return false;
+    }
+
+    fn spawn_falling_block(&mut self, position: [i32; 3], block_type: BlockType) -> bool {
+        self.falling_blocks.spawn(block_type, position)
+    }
+
+    fn loaded_chunk_offsets(&self) -> Vec<[i32; 3]> {
+        let chunk_indices = self.chunk_indices.lock().unwrap();
+        chunk_indices
+            .iter()
+            .flatten()
+            .map(|chunk_index| *self.chunks.offset_array[*chunk_index].lock().unwrap())
+            .collect()
+    }
+
+    fn world_height(&self) -> usize {
+        self.settings.world_height
     }
 }
 
@@ -362,6 +906,14 @@ impl Draw for World {
             render_pass.set_index_buffer(chunk_buffer.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
             render_pass.draw_indexed(0..chunk_buffer.num_elements as u32, 0, 0..1);
         }
+
+        for box_buffer in [&self.falling_block_buffer, &self.entity_buffer].iter() {
+            if box_buffer.num_elements > 0 {
+                render_pass.set_vertex_buffer(0, box_buffer.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(box_buffer.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                render_pass.draw_indexed(0..box_buffer.num_elements, 0, 0..1);
+            }
+        }
         return Ok(());
     }
 }
@@ -1,60 +1,426 @@
+use anyhow::*;
 use rayon::prelude::*;
 
-use crate::{atlas::*, chunk::*, noise::*};
+use crate::{biome::*, block_registry::*, caves::*, chunk::*, decoration::*, noise::*, ores::*, world_metadata::*};
 
-fn normalize_noise(val: f64) -> usize {
-    return ((val + 1.0) * CHUNK_Y_SIZE as f64 / 2.0) as usize;
+pub const DEFAULT_GENERATOR: &str = "planet";
+pub const GENERATOR_NAMES: [&str; 5] = ["planet", "flat", "superflat", "void", "debug"];
+
+/// Named layer lists for the `superflat` generator, from the bottom up.
+const SUPERFLAT_PRESETS: [(&str, &str); 5] = [
+    ("classic", "bedrock,dirt*2,grass"),
+    ("tunnelers", "bedrock,rock*94,dirt*4,grass"),
+    ("water", "bedrock,rock*5,sand*5,water*90"),
+    ("desert", "bedrock,rock*3,sand*52"),
+    ("snowy", "bedrock,rock*59,dirt*3,snow"),
+];
+
+/// Fills chunks with blocks. Generators do not touch the GPU, they can be used headless.
+pub trait WorldGenerator: Send + Sync {
+    /// Sets every block of the chunk at `offset`.
+    ///
+    /// Returns the decoration blocks that belong to neighbouring chunks along with their chunk offset.
+    fn generate_chunk(&self, blocks: &mut Blocks, offset: [i32; 3]) -> Vec<([i32; 3], BlockWrite)>;
+
+    /// Biome of a world column, `None` for generators without biomes.
+    fn biome_at(&self, _world_x: i32, _world_z: i32) -> Option<Biome> {
+        None
+    }
 }
 
-pub fn generate_chunk(blocks: &mut Blocks, offset: [i32; 3], seed: u32, flat_world: bool) {
-    if flat_world {
-        generate_flat_world(blocks, offset);
-        return;
+/// Reads the files the generator options point to into the metadata, once for the world, so that
+/// it is generated from the values saved with it.
+pub fn resolve_generator_options(metadata: &mut WorldMetadata) -> Result<()> {
+    if metadata.generator == "planet" && metadata.planet_parameters.is_none() {
+        let path = metadata.generator_options.as_deref().unwrap_or(PLANET_PARAMETERS_PATH);
+        metadata.planet_parameters = Some(PlanetParameters::load(path)?);
     }
+    Ok(())
+}
+
+/// Creates the generator of a world, its options are interpreted by the generator:
+/// - `planet`: path to the terrain parameters (`assets/generators/planet.ron` by default)
+/// - `flat`: layer list from the bottom up, e.g. `bedrock,dirt*3,grass`
+/// - `superflat`: preset name (`classic` by default)
+/// - `void`: no options
+/// - `debug`: no options
+pub fn create_generator(metadata: &WorldMetadata) -> Result<Box<dyn WorldGenerator>> {
+    let (name, options, seed, settings) = (
+        metadata.generator.as_str(),
+        metadata.generator_options.as_deref(),
+        metadata.seed,
+        metadata.settings,
+    );
+    settings.validate()?;
+
+    let no_options = || match options {
+        Some(options) => bail!("Error generator '{}' takes no options, got '{}'", name, options),
+        None => Ok(()),
+    };
+
+    let generator: Box<dyn WorldGenerator> = match name {
+        "planet" => {
+            let parameters = match metadata.planet_parameters {
+                Some(parameters) => parameters,
+                None => PlanetParameters::load(options.unwrap_or(PLANET_PARAMETERS_PATH))?,
+            };
+            Box::new(PlanetGenerator::new(seed, parameters, metadata.caves, settings))
+        }
+        "flat" => match options {
+            Some(layers) => Box::new(FlatGenerator::parse(layers, settings)?),
+            None => Box::new(FlatGenerator::new(settings)),
+        },
+        "superflat" => {
+            let preset = options.unwrap_or("classic");
+            match SUPERFLAT_PRESETS.iter().find(|(preset_name, _)| *preset_name == preset) {
+                Some((_, layers)) => Box::new(FlatGenerator::parse(layers, settings)?),
+                None => bail!(
+                    "Error unknown superflat preset '{}', expected one of {:?}",
+                    preset,
+                    SUPERFLAT_PRESETS.iter().map(|(preset_name, _)| *preset_name).collect::<Vec<_>>()
+                ),
+            }
+        }
+        "void" => {
+            no_options()?;
+            Box::new(VoidGenerator)
+        }
+        "debug" => {
+            no_options()?;
+            Box::new(DebugGridGenerator::new(settings))
+        }
+        _ => bail!("Error unknown generator '{}', expected one of {:?}", name, GENERATOR_NAMES),
+    };
+
+    Ok(generator)
+}
 
-    let noise_map = get_noise_map(offset, seed);
+/// Calls `block_type_at(x, y, z)` for every block of the chunk, in parallel.
+fn fill_chunk<F>(blocks: &mut Blocks, block_type_at: F)
+where
+    F: Fn(usize, usize, usize) -> BlockType + Send + Sync,
+{
     (0..TOTAL_CHUNK_SIZE).into_par_iter().for_each(|i| {
         let z = i / (CHUNK_X_SIZE * CHUNK_Y_SIZE);
         let y = (i - z * CHUNK_X_SIZE * CHUNK_Y_SIZE) / CHUNK_X_SIZE;
         let x = i - CHUNK_X_SIZE * (y + CHUNK_Y_SIZE * z);
 
-        let noise_height = noise_map.get_value(x, z);
-        let new_height = normalize_noise(noise_height);
+        blocks[y][x][z].lock().unwrap().update(block_type_at(x, y, z));
+    });
+}
+
+/// Index of the column at `[x, z]` in the chunk, from -1 to the chunk size included, in the
+/// columns of `PlanetGenerator::generate_columns`.
+fn column_index(x: i32, z: i32) -> usize {
+    (x + 1) as usize * (CHUNK_Z_SIZE + 2) + (z + 1) as usize
+}
+
+/// Terrain height and blocks of a single (x, z) column of a chunk.
+struct Column {
+    height: usize,
+    surface: BlockType,
+    subsurface: BlockType,
+    subsurface_depth: usize,
+}
+
+/// Noise terrain with biomes, ores, caves and decorations.
+pub struct PlanetGenerator {
+    seed: u32,
+    settings: WorldSettings,
+    /// Planet elevation of the sea level, maps to `settings.sea_level`.
+    planet_sea_level: f64,
+    noise: PlanetNoise,
+    biome_map: BiomeMap,
+    cave_carver: CaveCarver,
+}
+
+impl PlanetGenerator {
+    pub fn new(seed: u32, parameters: PlanetParameters, cave_settings: CaveSettings, settings: WorldSettings) -> Self {
+        Self {
+            seed,
+            settings,
+            planet_sea_level: parameters.sea_level,
+            noise: PlanetNoise::new(seed, parameters),
+            biome_map: BiomeMap::new(seed),
+            cave_carver: CaveCarver::new(seed, cave_settings, settings),
+        }
+    }
+
+    /// Columns of a chunk and of the border around it, see `column_index`.
+    fn generate_columns(&self, offset: [i32; 3]) -> Vec<Column> {
+        let noise_map = self.noise.get_noise_map(offset, 1);
+
+        let mut columns = Vec::with_capacity((CHUNK_X_SIZE + 2) * (CHUNK_Z_SIZE + 2));
+        for x in 0..CHUNK_X_SIZE + 2 {
+            for z in 0..CHUNK_Z_SIZE + 2 {
+                let world_x = offset[0] * CHUNK_X_SIZE as i32 + x as i32 - 1;
+                let world_z = offset[2] * CHUNK_Z_SIZE as i32 + z as i32 - 1;
+
+                let (height_scale, height_offset) = self.biome_map.blended_height_modifiers(world_x, world_z);
+                let elevation = noise_map.get_value(x, z) * height_scale + height_offset;
+                let height = self.settings.terrain_height(elevation, self.planet_sea_level);
+
+                let parameters = self.biome_map.biome_at(world_x, world_z).parameters();
+                // No grass or snow under water
+                let surface = if height < self.settings.sea_level {
+                    parameters.subsurface
+                } else {
+                    parameters.surface
+                };
+
+                columns.push(Column {
+                    height,
+                    surface,
+                    subsurface: parameters.subsurface,
+                    subsurface_depth: parameters.subsurface_depth,
+                });
+            }
+        }
+
+        columns
+    }
+}
 
-        let block_type = if y > new_height {
-            if y <= SEA_LEVEL {
-                BlockType::WATER
+impl WorldGenerator for PlanetGenerator {
+    fn generate_chunk(&self, blocks: &mut Blocks, offset: [i32; 3]) -> Vec<([i32; 3], BlockWrite)> {
+        let columns = self.generate_columns(offset);
+        fill_chunk(blocks, |x, y, z| {
+            let column = &columns[column_index(x as i32, z as i32)];
+            if y > column.height {
+                if y <= self.settings.sea_level {
+                    BlockType::WATER
+                } else {
+                    BlockType::AIR
+                }
+            } else if y == 0 {
+                BlockType::BEDROCK
+            } else if y == column.height {
+                column.surface
+            } else if y + column.subsurface_depth >= column.height {
+                column.subsurface
             } else {
-                BlockType::AIR
+                BlockType::ROCK
             }
-        } else if y == new_height {
-            BlockType::GRASS
-        } else if y == 0 {
-            BlockType::ROCK
-        } else {
-            BlockType::DIRT
-        };
+        });
 
-        blocks[y][x][z].lock().unwrap().update(block_type, offset);
-    });
+        place_ores(blocks, offset, self.seed, &self.settings);
+        // Water of the neighbouring chunks, which may not be generated yet
+        let is_water_outside = |x: i32, y: usize, z: i32| y > columns[column_index(x, z)].height && y <= self.settings.sea_level;
+        self.cave_carver.carve(blocks, offset, is_water_outside);
+
+        decorate(blocks, offset, self.seed, &self.biome_map, self.settings.world_height)
+    }
+
+    fn biome_at(&self, world_x: i32, world_z: i32) -> Option<Biome> {
+        Some(self.biome_map.biome_at(world_x, world_z))
+    }
 }
 
-pub fn generate_flat_world(blocks: &mut Blocks, offset: [i32; 3]) {
-    (0..TOTAL_CHUNK_SIZE).into_par_iter().for_each(|i| {
-        let z = i / (CHUNK_X_SIZE * CHUNK_Y_SIZE);
-        let y = (i - z * CHUNK_X_SIZE * CHUNK_Y_SIZE) / CHUNK_X_SIZE;
-        let x = i - CHUNK_X_SIZE * (y + CHUNK_Y_SIZE * z);
+/// The same column of blocks everywhere.
+pub struct FlatGenerator {
+    /// Block at each height, from the bottom up.
+    column: Vec<BlockType>,
+}
+
+impl FlatGenerator {
+    /// Grass at sea level on top of dirt.
+    pub fn new(settings: WorldSettings) -> Self {
+        let mut column = vec![BlockType::BEDROCK];
+        column.resize(settings.sea_level, BlockType::DIRT);
+        column.push(BlockType::GRASS);
+        Self { column }
+    }
+
+    /// Parses a comma separated list of block names from the bottom up, `name*count` repeats a layer.
+    pub fn parse(layers: &str, settings: WorldSettings) -> Result<Self> {
+        let registry = BlockRegistry::get();
+        let mut column = Vec::new();
+        for layer in layers.split(',').map(|layer| layer.trim()) {
+            let mut parts = layer.splitn(2, '*');
+            let name = parts.next().unwrap_or("").trim();
+            let count = match parts.next() {
+                Some(count) => count
+                    .trim()
+                    .parse::<usize>()
+                    .with_context(|| format!("Error invalid layer count in '{}'", layer))?,
+                None => 1,
+            };
+            let block_type = match registry.block_type(name) {
+                Some(block_type) => block_type,
+                None => bail!("Error unknown block '{}' in layer list", name),
+            };
+            column.resize(column.len() + count, block_type);
+        }
+
+        if column.len() > settings.world_height {
+            bail!(
+                "Error layer list is {} blocks high, the world is {} blocks high",
+                column.len(),
+                settings.world_height
+            );
+        }
+        Ok(Self { column })
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn generate_chunk(&self, blocks: &mut Blocks, _offset: [i32; 3]) -> Vec<([i32; 3], BlockWrite)> {
+        fill_chunk(blocks, |_, y, _| self.column.get(y).cloned().unwrap_or(BlockType::AIR));
+        Vec::new()
+    }
+}
+
+/// Nothing but air.
+pub struct VoidGenerator;
+
+impl WorldGenerator for VoidGenerator {
+    fn generate_chunk(&self, blocks: &mut Blocks, _offset: [i32; 3]) -> Vec<([i32; 3], BlockWrite)> {
+        fill_chunk(blocks, |_, _, _| BlockType::AIR);
+        Vec::new()
+    }
+}
+
+/// Every registered block, one every other block on a square grid starting at the origin.
+pub struct DebugGridGenerator {
+    block_types: Vec<BlockType>,
+    row_size: i32,
+    /// Height of the grid, at sea level just below the spawn point.
+    grid_y: usize,
+}
+
+impl DebugGridGenerator {
+    pub fn new(settings: WorldSettings) -> Self {
+        let block_types: Vec<BlockType> = BlockRegistry::get().block_types().filter(|block_type| *block_type != BlockType::AIR).collect();
+        let row_size = (block_types.len() as f32).sqrt().ceil() as i32;
+        Self {
+            block_types,
+            row_size,
+            grid_y: settings.sea_level,
+        }
+    }
+}
+
+impl WorldGenerator for DebugGridGenerator {
+    fn generate_chunk(&self, blocks: &mut Blocks, offset: [i32; 3]) -> Vec<([i32; 3], BlockWrite)> {
+        fill_chunk(blocks, |x, y, z| {
+            let world_x = offset[0] * CHUNK_X_SIZE as i32 + x as i32;
+            let world_z = offset[2] * CHUNK_Z_SIZE as i32 + z as i32;
+            if y != self.grid_y || world_x < 0 || world_z < 0 || world_x % 2 != 0 || world_z % 2 != 0 || world_x / 2 >= self.row_size {
+                return BlockType::AIR;
+            }
+            let index = (world_z / 2 * self.row_size + world_x / 2) as usize;
+            self.block_types.get(index).cloned().unwrap_or(BlockType::AIR)
+        });
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn layers_are_stacked_from_the_bottom_up() {
+        let mut blocks = chunk_blocks(BlockType::DEBUG);
+        let generator = FlatGenerator::parse("bedrock, dirt*3 ,grass", WorldSettings::default()).unwrap();
+        assert!(generator.generate_chunk(&mut blocks, [2, 0, -1]).is_empty());
+
+        let expected = [
+            BlockType::BEDROCK,
+            BlockType::DIRT,
+            BlockType::DIRT,
+            BlockType::DIRT,
+            BlockType::GRASS,
+            BlockType::AIR,
+        ];
+        for x in 0..CHUNK_X_SIZE {
+            for z in 0..CHUNK_Z_SIZE {
+                for (y, block_type) in expected.iter().enumerate() {
+                    assert_eq!(chunk_block(&blocks, [x, y, z]), *block_type);
+                }
+            }
+        }
+        assert_eq!(
+            chunk_block_types(&blocks).iter().filter(|block_type| **block_type != BlockType::AIR).count(),
+            5 * CHUNK_X_SIZE * CHUNK_Z_SIZE
+        );
+    }
+
+    #[test]
+    fn invalid_layers_are_rejected() {
+        init_registry();
+        let settings = WorldSettings::default();
+        let error = |layers: &str| format!("{:#}", FlatGenerator::parse(layers, settings).err().unwrap());
+        assert!(error("bedrock,marble").contains("unknown block 'marble'"));
+        assert!(error("bedrock,dirt*x").contains("invalid layer count in 'dirt*x'"));
+        assert!(error("dirt*-1").contains("invalid layer count"));
+        assert!(error("bedrock,rock*250").contains("251 blocks high"));
+        assert!(FlatGenerator::parse("rock*200", settings).is_ok());
+    }
 
-        let block_type = if y > SEA_LEVEL {
-            BlockType::AIR
-        } else if y == SEA_LEVEL {
-            BlockType::GRASS
-        } else if y == 0 {
-            BlockType::ROCK
-        } else {
-            BlockType::DIRT
+    #[test]
+    fn superflat_presets_are_valid() {
+        init_registry();
+        for (_, layers) in SUPERFLAT_PRESETS.iter() {
+            assert!(FlatGenerator::parse(layers, WorldSettings::default()).is_ok());
+        }
+    }
+
+    #[test]
+    fn void_is_only_air() {
+        let mut blocks = chunk_blocks(BlockType::DIRT);
+        assert!(VoidGenerator.generate_chunk(&mut blocks, [0, 0, 0]).is_empty());
+        assert!(chunk_block_types(&blocks).iter().all(|block_type| *block_type == BlockType::AIR));
+    }
+
+    #[test]
+    fn debug_grid_has_every_block_every_other_block() {
+        init_registry();
+        let settings = WorldSettings::default();
+        let generator = DebugGridGenerator::new(settings);
+        let block_types: Vec<BlockType> = BlockRegistry::get().block_types().filter(|block_type| *block_type != BlockType::AIR).collect();
+        let row_size = generator.row_size as usize;
+        assert!(row_size * row_size >= block_types.len());
+        assert!(row_size * 2 <= CHUNK_X_SIZE);
+
+        let mut blocks = chunk_blocks(BlockType::DEBUG);
+        generator.generate_chunk(&mut blocks, [0, 0, 0]);
+        for (index, block_type) in block_types.iter().enumerate() {
+            let (x, z) = (index % row_size * 2, index / row_size * 2);
+            assert_eq!(chunk_block(&blocks, [x, settings.sea_level, z]), *block_type);
+            assert_eq!(chunk_block(&blocks, [x + 1, settings.sea_level, z]), BlockType::AIR);
+            assert_eq!(chunk_block(&blocks, [x, settings.sea_level + 1, z]), BlockType::AIR);
+        }
+        let placed = chunk_block_types(&blocks).iter().filter(|block_type| **block_type != BlockType::AIR).count();
+        assert_eq!(placed, block_types.len());
+
+        // Nothing on the negative side of the origin
+        generator.generate_chunk(&mut blocks, [-1, 0, 0]);
+        assert!(chunk_block_types(&blocks).iter().all(|block_type| *block_type == BlockType::AIR));
+    }
+
+    #[test]
+    fn planets_with_the_same_seed_are_the_same() {
+        init_registry();
+        let settings = WorldSettings::default();
+        let generate = |seed: u32, offset: [i32; 3]| {
+            let generator = PlanetGenerator::new(seed, PlanetParameters::default(), CaveSettings::default(), settings);
+            let mut blocks = chunk_blocks(BlockType::DEBUG);
+            let overflow = generator.generate_chunk(&mut blocks, offset);
+            let overflow: Vec<([i32; 3], [usize; 3], BlockType)> = overflow
+                .into_iter()
+                .map(|(target, block_write)| (target, block_write.position, block_write.block_type))
+                .collect();
+            (chunk_block_types(&blocks), overflow)
         };
 
-        blocks[y][x][z].lock().unwrap().update(block_type, offset);
-    });
+        for offset in [[0, 0, 0], [5, 0, -3]].iter() {
+            let (blocks, overflow) = generate(7, *offset);
+            assert_eq!(blocks, generate(7, *offset).0);
+            assert_eq!(overflow, generate(7, *offset).1);
+            assert!(blocks.iter().all(|block_type| *block_type != BlockType::DEBUG));
+            assert_eq!(blocks[0], BlockType::BEDROCK);
+        }
+    }
 }
@@ -0,0 +1,117 @@
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{caves::CaveSettings, chunk::*, noise::PlanetParameters};
+
+/// Name of the metadata file in a world directory.
+pub const WORLD_METADATA_FILE: &str = "world.ron";
+
+/// Vertical layout of a world, fixed when the world is created.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WorldSettings {
+    /// Terrain is generated and blocks are placed below this height, at most `CHUNK_Y_SIZE`.
+    pub world_height: usize,
+    /// Oceans and rivers are filled with water up to this height.
+    pub sea_level: usize,
+    /// Scales how high the terrain rises above and sinks below the sea level, 1.0 spans the whole
+    /// world height.
+    pub terrain_amplitude: f64,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            world_height: CHUNK_Y_SIZE,
+            sea_level: CHUNK_Y_SIZE / 2,
+            terrain_amplitude: 1.0,
+        }
+    }
+}
+
+impl WorldSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.world_height < 16 || self.world_height > CHUNK_Y_SIZE {
+            bail!("Error world height must be between 16 and {}, got {}", CHUNK_Y_SIZE, self.world_height);
+        }
+        if self.sea_level < 8 || self.sea_level + 8 > self.world_height {
+            bail!(
+                "Error sea level must be between 8 and {} for a world height of {}, got {}",
+                self.world_height - 8,
+                self.world_height,
+                self.sea_level
+            );
+        }
+        if self.terrain_amplitude <= 0.0 || self.terrain_amplitude > 1.0 {
+            bail!("Error terrain amplitude must be in ]0.0, 1.0], got {}", self.terrain_amplitude);
+        }
+        Ok(())
+    }
+
+    /// Block height of a planet elevation between -1.0 and 1.0, `planet_sea_level` being the
+    /// elevation that lands on the sea level.
+    pub fn terrain_height(&self, elevation: f64, planet_sea_level: f64) -> usize {
+        let elevation = elevation.clamp(-1.0, 1.0);
+        let height = if elevation >= planet_sea_level {
+            let above = (elevation - planet_sea_level) / (1.0 - planet_sea_level).max(f64::EPSILON);
+            self.sea_level as f64 + above * (self.world_height - 1 - self.sea_level) as f64 * self.terrain_amplitude
+        } else {
+            let below = (planet_sea_level - elevation) / (1.0 + planet_sea_level).max(f64::EPSILON);
+            self.sea_level as f64 - below * self.sea_level as f64 * self.terrain_amplitude
+        };
+        (height as usize).clamp(1, self.world_height - 1)
+    }
+}
+
+/// What a world is made of, saved as `world.ron` in the world directory so the same world is
+/// generated again when it is loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldMetadata {
+    pub seed: u32,
+    pub generator: String,
+    pub generator_options: Option<String>,
+    /// Terrain of the `planet` generator, read from the file of `generator_options` when the world
+    /// is created so that editing the file does not change the world.
+    #[serde(default)]
+    pub planet_parameters: Option<PlanetParameters>,
+    pub settings: WorldSettings,
+    /// Caves carved by the generators that have some, worlds saved without them have every cave.
+    #[serde(default)]
+    pub caves: CaveSettings,
+    /// Block the player stands in when spawning, found once when the world is first played.
+    #[serde(default)]
+    pub spawn_point: Option<[i32; 3]>,
+    /// Directory the world is saved in, `None` for worlds that are not saved.
+    #[serde(skip)]
+    pub dir: Option<PathBuf>,
+}
+
+impl WorldMetadata {
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let path = dir.as_ref().join(WORLD_METADATA_FILE);
+        let content = read_to_string(&path).with_context(|| format!("Error could not read world metadata '{}'", path.display()))?;
+        let mut metadata: WorldMetadata = ron::de::from_str(&content).with_context(|| format!("Error invalid world metadata '{}'", path.display()))?;
+        metadata
+            .settings
+            .validate()
+            .with_context(|| format!("Error invalid world metadata '{}'", path.display()))?;
+        metadata.dir = Some(dir.as_ref().to_path_buf());
+        Ok(metadata)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        create_dir_all(dir).with_context(|| format!("Error could not create world directory '{}'", dir.display()))?;
+
+        let path = dir.join(WORLD_METADATA_FILE);
+        let content = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        write(&path, content).with_context(|| format!("Error could not write world metadata '{}'", path.display()))
+    }
+
+    /// Whether `dir` already holds a world.
+    pub fn exists<P: AsRef<Path>>(dir: P) -> bool {
+        dir.as_ref().join(WORLD_METADATA_FILE).is_file()
+    }
+}